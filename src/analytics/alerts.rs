@@ -75,7 +75,10 @@ impl AlertGenerator {
         AlertRule {
             name: "Temperature Deviation".to_string(),
             condition: Box::new(|metrics| {
-                if metrics.temperature < 90.0 || metrics.temperature > 96.0 {
+                let ranges = metrics.ideal_ranges();
+                if metrics.temperature < ranges.temperature_min
+                    || metrics.temperature > ranges.temperature_max
+                {
                     Some(Alert {
                         id: Uuid::new_v4().to_string(),
                         timestamp: Utc::now(),
@@ -97,7 +100,9 @@ impl AlertGenerator {
         AlertRule {
             name: "Pressure Instability".to_string(),
             condition: Box::new(|metrics| {
-                if metrics.pressure < 8.0 || metrics.pressure > 10.0 {
+                let ranges = metrics.ideal_ranges();
+                if metrics.pressure < ranges.pressure_min || metrics.pressure > ranges.pressure_max
+                {
                     Some(Alert {
                         id: Uuid::new_v4().to_string(),
                         timestamp: Utc::now(),
@@ -12,8 +12,10 @@ impl ErrorMessage for RepositoryError {
     fn error_message(&self) -> String {
         match self {
             RepositoryError::DatabaseError(err) => err.to_string(),
+            RepositoryError::BackendError(message) => message.clone(),
             RepositoryError::SerializationError(err) => err.to_string(),
             RepositoryError::NotFound => "Item not found".to_string(),
+            RepositoryError::Conflict(message) => message.clone(),
         }
     }
 }
@@ -48,8 +50,12 @@ impl ErrorMessage for NetworkError {
 #[derive(Debug)]
 pub enum RepositoryError {
     DatabaseError(sled::Error),
+    // A storage backend other than sled failed
+    BackendError(String),
     SerializationError(serde_json::Error),
     NotFound,
+    // The write disagrees with what is already stored
+    Conflict(String),
 }
 
 impl fmt::Display for RepositoryError {
@@ -63,7 +69,9 @@ impl Error for RepositoryError {
         match self {
             RepositoryError::DatabaseError(err) => Some(err),
             RepositoryError::SerializationError(err) => Some(err),
-            RepositoryError::NotFound => None,
+            RepositoryError::BackendError(_)
+            | RepositoryError::NotFound
+            | RepositoryError::Conflict(_) => None,
         }
     }
 }
@@ -74,6 +82,13 @@ impl From<sled::Error> for RepositoryError {
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for RepositoryError {
+    fn from(err: rusqlite::Error) -> Self {
+        RepositoryError::BackendError(err.to_string())
+    }
+}
+
 impl From<serde_json::Error> for RepositoryError {
     fn from(err: serde_json::Error) -> Self {
         RepositoryError::SerializationError(err)
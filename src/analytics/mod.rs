@@ -2,4 +2,4 @@ pub mod trends;
 pub mod alerts;
 pub mod repository;
 pub mod notifier;
-mod errors;
\ No newline at end of file
+pub mod errors;
\ No newline at end of file
@@ -31,10 +31,9 @@ pub struct SlackNotifier {
 
 impl Notifier for EmailNotifier {
     fn send_alert(&self, _alert: &Alert) -> Result<(), NotificationError> {
-        match self .smtp_config {
-            SmtpConfig::Connect => Ok(()),
-            SmtpConfig::Disconnect => Ok(())
-        }
+        // Sending mail is not implemented yet
+        let _ = &self.smtp_config;
+        Ok(())
     }
 }
 
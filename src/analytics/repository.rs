@@ -1,41 +1,44 @@
+use std::ops::Bound;
 use std::sync::Arc;
 use chrono::Utc;
-use sled::Db;
 // use tracing_subscriber::fmt::format;
 use crate::analytics::alerts::Alert;
-use crate::analytics::trends::ExtractionTrends;
+use crate::analytics::trends::{ExtractionTrends, TrendPeriod};
 use crate::analytics::errors::RepositoryError;
+use crate::simulation::methods::BrewMethodKind;
+use crate::simulation::ExtractionMetrics;
+use crate::storage::{self, Storage, Table, TimeKey};
 
 pub struct AnalyticsRepository {
-    db: Arc<Db>,
+    db: Arc<dyn Storage>,
+    shots: Arc<dyn Table>,
+    alerts: Arc<dyn Table>,
 }
 
 impl AnalyticsRepository {
-    pub(crate) fn new(p0: Arc<Db>) -> Self {
-        Self { db: p0 }
+    pub(crate) fn new(p0: Arc<dyn Storage>) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            shots: p0.table(storage::SHOTS)?,
+            alerts: p0.table(storage::ALERTS)?,
+            db: p0,
+        })
     }
 }
 
 impl AnalyticsRepository {
-    pub fn store_trends(&self, trends: &ExtractionTrends) -> Result<(), RepositoryError> {
-        let key = format!("alert_{}", Utc::now().timestamp_millis());
-        let serialized = serde_json::to_vec(trends)?;
-        self.db.insert(key, serialized)?;
-        Ok(())
-    }
-    
     pub fn store_alerts(&self, alerts: &[Alert]) -> Result<(), RepositoryError> {
         for alert in alerts {
-            let key = format!("alert_{}", Utc::now().timestamp_millis());
+            let key = TimeKey::now(self.db.as_ref())?;
             let serialized = serde_json::to_vec(alert)?;
-            self.db.insert(key, serialized)?;
-}
+            self.alerts.insert(&key.to_bytes(), &serialized)?;
+        }
         Ok(())
     }
     
+    // Every stored alert, oldest first
     pub fn get_alerts(&self) -> Result<Vec<Alert>, RepositoryError> {
         let mut alerts = Vec::new();
-        for entry in self.db.iter() {
+        for entry in self.alerts.iter() {
             let (_key, value) = entry?;
             let alert: Alert = serde_json::from_slice(&value)?;
             alerts.push(alert);
@@ -43,28 +46,36 @@ impl AnalyticsRepository {
         Ok(alerts)
     }
     
-    pub fn get_trends(&self) -> Result<Vec<ExtractionTrends>, RepositoryError> {
-        let mut trends = Vec::new();
-        for entry in self.db.iter() {
+    // Trends over the brews stored within `period`, optionally for one method;
+    // none when nothing was brewed
+    pub fn get_trends(
+        &self,
+        period: TrendPeriod,
+        method: Option<BrewMethodKind>,
+    ) -> Result<Vec<ExtractionTrends>, RepositoryError> {
+        let since = (Utc::now().timestamp() as u64).saturating_sub(period.seconds());
+        let mut metrics = Vec::new();
+        // Shots are keyed by when they were stored, which is never before
+        // their timestamp
+        let from = Bound::Included(TimeKey::at(since * 1000).to_bytes().to_vec());
+        for entry in self.shots.range(from, Bound::Unbounded, false) {
             let (_key, value) = entry?;
-            let trend: ExtractionTrends = serde_json::from_slice(&value)?;
-            trends.push(trend);
+            let metric: ExtractionMetrics = serde_json::from_slice(&value)?;
+            if metric.timestamp >= since && method.is_none_or(|m| m == metric.brew_method) {
+                metrics.push(metric);
+            }
         }
-        Ok(trends)
-    }
-    
-    pub fn retrieve_trends(&self, key: &str) -> Result<ExtractionTrends, RepositoryError> {
-        let value = self.db.get(key)?;
-        if let Some(value) = value {
-            let trend: ExtractionTrends = serde_json::from_slice(&value)?;
-            Ok(trend)
-        } else {
-            Err(RepositoryError::NotFound)
+        if metrics.is_empty() {
+            return Ok(Vec::new());
         }
+        Ok(vec![ExtractionTrends {
+            brew_method: method,
+            ..ExtractionTrends::calculate(&metrics, period)
+        }])
     }
     
-    pub fn retrieve_alerts(&self, key: &str) -> Result<Alert, RepositoryError> {
-        let value = self.db.get(key)?;
+    pub fn retrieve_alerts(&self, key: TimeKey) -> Result<Alert, RepositoryError> {
+        let value = self.alerts.get(&key.to_bytes())?;
         if let Some(value) = value {
             let alert: Alert = serde_json::from_slice(&value)?;
             Ok(alert)
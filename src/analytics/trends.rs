@@ -1,5 +1,7 @@
+use crate::simulation::methods::BrewMethodKind;
 use crate::simulation::ExtractionMetrics;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExtractionTrends {
@@ -8,6 +10,15 @@ pub struct ExtractionTrends {
     pub avg_metrics: AverageMetrics,
     pub trend_direction: TrendDirection,
     pub quality_distribution: QualityDistribution,
+    #[serde(default)]
+    pub total_scale_mg: f64,
+    #[serde(default)]
+    pub water_breakdown: Vec<WaterTrend>,
+    // Method the trends were filtered to, if any
+    #[serde(default)]
+    pub brew_method: Option<BrewMethodKind>,
+    #[serde(default)]
+    pub method_breakdown: Vec<MethodTrend>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -18,11 +29,26 @@ pub enum TrendPeriod {
     Yearly,
 }
 
+impl TrendPeriod {
+    // How far back the period reaches
+    pub fn seconds(&self) -> u64 {
+        let days = match self {
+            TrendPeriod::Daily => 1,
+            TrendPeriod::Weekly => 7,
+            TrendPeriod::Monthly => 30,
+            TrendPeriod::Yearly => 365,
+        };
+        days * 24 * 60 * 60
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AverageMetrics {
     pub temperature: f64,
     pub pressure: f64,
     pub extraction_time: f64,
+    #[serde(default)]
+    pub extraction_yield_percent: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,6 +64,24 @@ pub struct QualityDistribution {
     pub good: u32,
     pub suboptimal: u32,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaterTrend {
+    pub water: String,
+    pub shots: u32,
+    pub avg_extraction_yield: f64,
+    pub avg_quality_score: f64,
+    pub total_scale_mg: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MethodTrend {
+    pub brew_method: BrewMethodKind,
+    pub brews: u32,
+    pub avg_extraction_yield: f64,
+    pub avg_tds: f64,
+    pub avg_quality_score: f64,
+}
 impl ExtractionTrends {
     pub fn calculate(metrics: &[ExtractionMetrics], period: TrendPeriod) -> Self {
         Self {
@@ -46,6 +90,10 @@ impl ExtractionTrends {
             avg_metrics: Self::calculate_average_metrics(metrics),
             trend_direction: Self::calculate_trend_direction(metrics),
             quality_distribution: Self::calculate_quality_distribution(metrics),
+            total_scale_mg: metrics.iter().map(|m| m.scale_mg).sum(),
+            water_breakdown: Self::calculate_water_breakdown(metrics),
+            brew_method: None,
+            method_breakdown: Self::calculate_method_breakdown(metrics),
         }
     }
 
@@ -62,17 +110,20 @@ impl ExtractionTrends {
                 temperature: 0.0,
                 pressure: 0.0,
                 extraction_time: 0.0,
+                extraction_yield_percent: 0.0,
             };
         }
         
         let sum_temperature: f64 = metrics.iter().map(|m| m.temperature).sum();
         let sum_pressure: f64 = metrics.iter().map(|m| m.pressure).sum();
         let sum_extraction_time: f64 = metrics.iter().map(|m| m.extraction_time).sum();
+        let sum_extraction_yield: f64 = metrics.iter().map(|m| m.extraction_yield_percent).sum();
         
         AverageMetrics {
             temperature: sum_temperature / total,
             pressure: sum_pressure / total,
             extraction_time: sum_extraction_time / total,
+            extraction_yield_percent: sum_extraction_yield / total,
         }
     }
 
@@ -92,4 +143,52 @@ impl ExtractionTrends {
             suboptimal: metrics.iter().filter(|m| !m.is_perfect() && !m.is_good()).count() as u32,
         }
     }
-}
\ No newline at end of file
+
+    // How each water recipe performed, so recipes can be compared side by side
+    fn calculate_water_breakdown(metrics: &[ExtractionMetrics]) -> Vec<WaterTrend> {
+        let mut groups: BTreeMap<&str, Vec<&ExtractionMetrics>> = BTreeMap::new();
+        for metric in metrics {
+            groups.entry(metric.water.label()).or_default().push(metric);
+        }
+
+        groups
+            .into_iter()
+            .map(|(water, shots)| {
+                let count = shots.len() as f64;
+                WaterTrend {
+                    water: water.to_string(),
+                    shots: shots.len() as u32,
+                    avg_extraction_yield: shots.iter().map(|m| m.extraction_yield_percent).sum::<f64>()
+                        / count,
+                    avg_quality_score: shots.iter().map(|m| m.quality_score as f64).sum::<f64>()
+                        / count,
+                    total_scale_mg: shots.iter().map(|m| m.scale_mg).sum(),
+                }
+            })
+            .collect()
+    }
+
+    // Each method is judged against its own ideal ranges, so compare them here
+    fn calculate_method_breakdown(metrics: &[ExtractionMetrics]) -> Vec<MethodTrend> {
+        let mut groups: BTreeMap<BrewMethodKind, Vec<&ExtractionMetrics>> = BTreeMap::new();
+        for metric in metrics {
+            groups.entry(metric.brew_method).or_default().push(metric);
+        }
+
+        groups
+            .into_iter()
+            .map(|(brew_method, brews)| {
+                let count = brews.len() as f64;
+                MethodTrend {
+                    brew_method,
+                    brews: brews.len() as u32,
+                    avg_extraction_yield: brews.iter().map(|m| m.extraction_yield_percent).sum::<f64>()
+                        / count,
+                    avg_tds: brews.iter().map(|m| m.tds_percent).sum::<f64>() / count,
+                    avg_quality_score: brews.iter().map(|m| m.quality_score as f64).sum::<f64>()
+                        / count,
+                }
+            })
+            .collect()
+    }
+}
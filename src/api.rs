@@ -1,30 +1,67 @@
-use crate::analytics::alerts::Alert;
+use crate::analytics::alerts::{Alert, AlertGenerator};
+use crate::analytics::errors::RepositoryError;
 use crate::analytics::repository::AnalyticsRepository;
-use crate::simulation::{CoffeeType, ExtractionMetrics, GrindSize, RoastLevel};
+use crate::beans::{Bean, BeanRepository, NewBean};
+use crate::calibrations::CalibrationRepository;
+use crate::recipe_file::{FileFormat, RecipeFile};
+use crate::recipes::{FieldChange, NewRecipe, Recipe, RecipeFork, RecipeParams, RecipeRepository};
+use crate::shots::{AnnotationPatch, ShotPage, ShotQuery, ShotRepository};
+use crate::simulation::calibration::{calibrate, Calibration, ShotLog};
+use crate::simulation::drinks::{Drink, DrinkKind};
+use crate::simulation::environment::Environment;
+use crate::simulation::machine::{MachineKind, MachineState};
+use crate::simulation::methods::{brew, BrewConfig, BrewMethodKind};
+use crate::simulation::milk::Steaming;
+use crate::simulation::optimizer::{optimize, suggest, Suggestion, TastedShot};
+use crate::simulation::profile::ProfileSelection;
+use crate::simulation::robustness::{analyze, Robustness, Tolerances, MAX_RUNS};
+use crate::simulation::scoring::{ScorerKind, ShotScores};
+use crate::simulation::standards::{self, Standards};
+use crate::simulation::sweep::{sweep, Axis, Sweep};
+use crate::simulation::water::{WaterProfile, WaterSelection};
+use crate::simulation::{
+    check_espresso_temperature, CoffeeType, ExtractionMetrics, FieldError, GrindSize, RoastLevel,
+    ShotConfig,
+};
+use crate::storage::{Storage, StorageConfig};
 use crate::{
     analytics::trends::{ExtractionTrends, TrendPeriod},
-    simulation::simulate_extraction,
+    simulation::simulate,
 };
 use axum::{
-    extract::{Query, State as AxumState},
+    extract::{Path, Query, State as AxumState},
     routing::{get, post},
     Json, Router,
 };
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
 use chrono::Utc;
-use serde::{Deserialize, Serialize};
-use sled::Db;
-use std::{net::SocketAddr, sync::Arc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 use tokio::net::TcpListener;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
 pub struct ApiError {
     message: String,
     status: u16,
+    // Every invalid field, when the request failed validation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
 impl IntoResponse for ApiError {
@@ -36,7 +73,11 @@ impl IntoResponse for ApiError {
 
 #[derive(Clone)]
 pub struct AppState {
-    db: Arc<Db>,
+    db: Arc<dyn Storage>,
+    // Thermal state of each machine, carried from one shot to the next
+    machines: Arc<Mutex<HashMap<MachineKind, MachineState>>>,
+    // Where edits to the extraction standards are written
+    standards_path: Arc<PathBuf>,
 }
 
 pub type Result<T> = std::result::Result<T, ApiError>;
@@ -55,6 +96,44 @@ pub struct ExtractionParams {
     pub roast_level: Option<RoastLevel>,
     #[serde(default)]
     pub grind_size: Option<GrindSize>,
+    #[serde(default)]
+    pub grind_microns: Option<f64>,
+    #[serde(default = "default_tamp_kg")]
+    pub tamp_kg: f64,
+    #[serde(default = "default_dose_g")]
+    pub dose_g: f64,
+    #[serde(default)]
+    pub yield_g: Option<f64>,
+    #[serde(default)]
+    pub profile: Option<ProfileSelection>,
+    #[serde(default)]
+    pub water: Option<WaterSelection>,
+    // Replays a varied shot; `variance=true` alone picks a fresh seed
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub variance: bool,
+    // Catalog bean; supplies coffee type, roast level and roast age
+    #[serde(default)]
+    pub bean_id: Option<String>,
+    // Pulls the shot on this machine, starting from whatever state the
+    // previous shot left it in
+    #[serde(default)]
+    pub machine: Option<MachineKind>,
+    #[serde(default)]
+    pub altitude_m: f64,
+    #[serde(default = "default_ambient_temperature")]
+    pub ambient_temperature: f64,
+    #[serde(default = "default_humidity")]
+    pub humidity: f64,
+    #[serde(default)]
+    pub scorer: ScorerKind,
+    // Calibration profile to simulate with instead of the textbook model
+    #[serde(default)]
+    pub calibration: Option<String>,
+    // Who pulled the shot, to tell baristas apart in `/metrics`
+    #[serde(default)]
+    pub user: Option<String>,
 }
 
 fn default_temperature() -> f64 {
@@ -66,166 +145,1472 @@ fn default_pressure() -> f64 {
 fn default_time_seconds() -> u64 {
     25
 }
+fn default_tamp_kg() -> f64 {
+    15.0
+}
+fn default_dose_g() -> f64 {
+    18.0
+}
+fn default_ambient_temperature() -> f64 {
+    20.0
+}
+fn default_humidity() -> f64 {
+    50.0
+}
+
+impl Default for ExtractionParams {
+    fn default() -> Self {
+        Self {
+            temperature: default_temperature(),
+            pressure: default_pressure(),
+            time_seconds: default_time_seconds(),
+            coffee_type: None,
+            roast_level: None,
+            grind_size: None,
+            grind_microns: None,
+            tamp_kg: default_tamp_kg(),
+            dose_g: default_dose_g(),
+            yield_g: None,
+            profile: None,
+            water: None,
+            seed: None,
+            variance: false,
+            bean_id: None,
+            machine: None,
+            altitude_m: 0.0,
+            ambient_temperature: default_ambient_temperature(),
+            humidity: default_humidity(),
+            scorer: ScorerKind::default(),
+            calibration: None,
+            user: None,
+        }
+    }
+}
 
 impl ExtractionParams {
-    pub fn validate(&self) -> Result<()> {
-        if !(90.0..=96.0).contains(&self.temperature) {
-            return Err(ApiError {
-                message: "Temperature must be between 90.0 and 96.0".to_string(),
-                status: 400,
-            });
+    pub fn validate(&self, bean: Option<&Bean>) -> Result<()> {
+        let errors = self.field_errors(bean);
+        if errors.is_empty() {
+            return Ok(());
         }
-        if !(8.0..=10.0).contains(&self.pressure) {
-            return Err(ApiError {
-                message: "Pressure must be between 8.0 and 10.0".to_string(),
-                status: 400,
-            });
+        Err(invalid_fields(errors))
+    }
+
+    // Every invalid field at once, with nested ones such as
+    // `profile.stages[0].target` named by their path. Temperature and
+    // pressure are judged for the coffee the shot is brewed from, `bean`'s
+    // unless the request names its own
+    pub fn field_errors(&self, bean: Option<&Bean>) -> Vec<FieldError> {
+        let environment = self.environment();
+        let mut errors = environment.field_errors();
+        let (coffee_type, roast_level) = self.coffee(bean);
+        let ranges =
+            standards::current().ranges(BrewMethodKind::Espresso, coffee_type, roast_level);
+        if let Err(message) =
+            check_espresso_temperature(self.temperature, coffee_type, roast_level, &environment)
+        {
+            errors.push(FieldError::new("temperature", message));
         }
-        if !(20..=30).contains(&self.time_seconds) {
-            return Err(ApiError {
-                message: "Time must be between 20 and 30 seconds".to_string(),
-                status: 400,
-            });
+        if !(ranges.pressure_min..=ranges.pressure_max).contains(&self.pressure) {
+            errors.push(FieldError::new(
+                "pressure",
+                format!(
+                    "Pressure must be between {:.1} and {:.1}",
+                    ranges.pressure_min, ranges.pressure_max
+                ),
+            ));
+        }
+        if !(ranges.time_min..=ranges.time_max).contains(&self.time_seconds) {
+            errors.push(FieldError::new(
+                "time_seconds",
+                format!(
+                    "Time must be between {} and {} seconds",
+                    ranges.time_min, ranges.time_max
+                ),
+            ));
+        }
+        if let Some(microns) = self.grind_microns {
+            if !(150.0..=1200.0).contains(&microns) {
+                errors.push(FieldError::new(
+                    "grind_microns",
+                    "Grind must be between 150 and 1200 microns",
+                ));
+            }
+        }
+        if !(5.0..=30.0).contains(&self.tamp_kg) {
+            errors.push(FieldError::new(
+                "tamp_kg",
+                "Tamp must be between 5.0 and 30.0 kg",
+            ));
+        }
+        if !(6.0..=25.0).contains(&self.dose_g) {
+            errors.push(FieldError::new(
+                "dose_g",
+                "Dose must be between 6.0 and 25.0 grams",
+            ));
+        }
+        if let Some(yield_g) = self.yield_g {
+            let ratio = yield_g / self.dose_g;
+            if !(1.0..=4.0).contains(&ratio) {
+                errors.push(FieldError::new(
+                    "yield_g",
+                    format!(
+                        "Yield must give a brew ratio between 1:1 and 1:4, got 1:{:.1}",
+                        ratio
+                    ),
+                ));
+            }
+        }
+        if let Some(Err(profile_errors)) = self
+            .profile
+            .as_ref()
+            .map(|profile| profile.resolve_fields(self.pressure))
+        {
+            errors.extend(profile_errors.into_iter().map(|e| e.within("profile")));
+        }
+        if let Some(water) = &self.water {
+            errors.extend(water.field_errors().into_iter().map(|e| e.within("water")));
+        }
+        errors
+    }
+
+    // The parameters a JSON body named itself, as `given` to `with_recipe`;
+    // the optional ones are only set when named anyway
+    pub fn named_in(&self, body: &serde_json::Value) -> RecipeParams {
+        let named = |field: &str| body.get(field).is_some();
+        RecipeParams {
+            temperature: named("temperature").then_some(self.temperature),
+            pressure: named("pressure").then_some(self.pressure),
+            time_seconds: named("time_seconds").then_some(self.time_seconds),
+            coffee_type: self.coffee_type,
+            roast_level: self.roast_level,
+            grind_size: self.grind_size,
+            grind_microns: self.grind_microns,
+            tamp_kg: named("tamp_kg").then_some(self.tamp_kg),
+            dose_g: named("dose_g").then_some(self.dose_g),
+            yield_g: self.yield_g,
+            profile: self.profile.clone(),
+            water: self.water.clone(),
+            bean_id: self.bean_id.clone(),
+        }
+    }
+
+    // The recipe's parameters, except where `given` (what the request named
+    // itself) says otherwise
+    pub fn with_recipe(self, recipe: RecipeParams, given: RecipeParams) -> Self {
+        Self {
+            temperature: given
+                .temperature
+                .or(recipe.temperature)
+                .unwrap_or(self.temperature),
+            pressure: given.pressure.or(recipe.pressure).unwrap_or(self.pressure),
+            time_seconds: given
+                .time_seconds
+                .or(recipe.time_seconds)
+                .unwrap_or(self.time_seconds),
+            coffee_type: given.coffee_type.or(recipe.coffee_type),
+            roast_level: given.roast_level.or(recipe.roast_level),
+            grind_size: given.grind_size.or(recipe.grind_size),
+            grind_microns: given.grind_microns.or(recipe.grind_microns),
+            tamp_kg: given.tamp_kg.or(recipe.tamp_kg).unwrap_or(self.tamp_kg),
+            dose_g: given.dose_g.or(recipe.dose_g).unwrap_or(self.dose_g),
+            yield_g: given.yield_g.or(recipe.yield_g),
+            profile: given.profile.or(recipe.profile),
+            water: given.water.or(recipe.water),
+            bean_id: given.bean_id.or(recipe.bean_id),
+            ..self
+        }
+    }
+
+    // The coffee type and roast level the shot is brewed with
+    pub fn coffee(&self, bean: Option<&Bean>) -> (CoffeeType, RoastLevel) {
+        (
+            self.coffee_type
+                .or(bean.map(|b| b.coffee_type))
+                .unwrap_or_default(),
+            self.roast_level
+                .or(bean.map(|b| b.roast_level))
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn environment(&self) -> Environment {
+        Environment {
+            altitude_m: self.altitude_m,
+            ambient_temperature: self.ambient_temperature,
+            humidity: self.humidity,
         }
-        // Podrías añadir validaciones para coffee_type, roast_level, grind_size si es necesario
-        Ok(())
+    }
+
+    // Explicit coffee type and roast level win over the bean's
+    pub fn shot_config(
+        &self,
+        bean: Option<&Bean>,
+        calibration: Option<&Calibration>,
+    ) -> Result<ShotConfig> {
+        let profile = match &self.profile {
+            Some(selection) => {
+                Some(
+                    selection
+                        .resolve(self.pressure)
+                        .map_err(|message| ApiError {
+                            message,
+                            status: 400,
+                            errors: Vec::new(),
+                        })?,
+                )
+            }
+            None => None,
+        };
+        let water = match &self.water {
+            Some(selection) => selection.resolve().map_err(|message| ApiError {
+                message,
+                status: 400,
+                errors: Vec::new(),
+            })?,
+            None => WaterProfile::default(),
+        };
+
+        let (coffee_type, roast_level) = self.coffee(bean);
+        Ok(ShotConfig {
+            temperature: self.temperature,
+            pressure: self.pressure,
+            time_seconds: self.time_seconds,
+            coffee_type,
+            roast_level,
+            grind_size: self.grind_size.unwrap_or_default(),
+            grind_microns: self.grind_microns,
+            tamp_kg: self.tamp_kg,
+            dose_g: self.dose_g,
+            target_yield_g: self.yield_g,
+            profile,
+            seed: self.seed.or_else(|| self.variance.then(fresh_seed)),
+            water,
+            days_off_roast: bean.map(|b| b.days_off_roast(Utc::now().date_naive())),
+            machine: None,
+            environment: self.environment(),
+            scorer: self.scorer,
+            coefficients: calibration.map(|c| c.coefficients).unwrap_or_default(),
+        })
     }
 }
 
-pub async fn start_extraction(
-    AxumState(state): AxumState<AppState>,
-    Query(params): Query<ExtractionParams>, // Query ahora está explícitamente importado
-) -> Result<Json<ExtractionMetrics>> {
-    // ExtractionMetrics ahora está importado
-    debug!("Received extraction request: {:?}", params);
+// A brew with any method; anything left out comes from the method's defaults
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BrewParams {
+    pub method: BrewMethodKind,
+    #[serde(default)]
+    pub dose_g: Option<f64>,
+    // Brew water; for espresso, the beverage weight to stop at
+    #[serde(default)]
+    pub water_g: Option<f64>,
+    #[serde(default)]
+    pub grind_microns: Option<f64>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub brew_seconds: Option<f64>,
+    #[serde(default)]
+    pub bloom_seconds: Option<f64>,
+    #[serde(default)]
+    pub pours: Option<u32>,
+    #[serde(default)]
+    pub coffee_type: Option<CoffeeType>,
+    #[serde(default)]
+    pub roast_level: Option<RoastLevel>,
+    #[serde(default)]
+    pub water: Option<WaterSelection>,
+    #[serde(default)]
+    pub bean_id: Option<String>,
+    #[serde(default)]
+    pub altitude_m: f64,
+    #[serde(default = "default_ambient_temperature")]
+    pub ambient_temperature: f64,
+    #[serde(default = "default_humidity")]
+    pub humidity: f64,
+    #[serde(default)]
+    pub scorer: ScorerKind,
+    #[serde(default)]
+    pub user: Option<String>,
+}
 
-    params.validate()?;
+impl BrewParams {
+    // Explicit coffee type and roast level win over the bean's, and the
+    // bean's over the method's
+    pub fn brew_config(&self, bean: Option<&Bean>) -> Result<BrewConfig> {
+        let defaults = self.method.method().defaults();
+        let water = match &self.water {
+            Some(selection) => selection.resolve().map_err(|message| ApiError {
+                message,
+                status: 400,
+                errors: Vec::new(),
+            })?,
+            None => defaults.water.clone(),
+        };
 
-    let metrics: ExtractionMetrics = simulate_extraction(
-        // Especificar el tipo de 'metrics' puede ayudar al compilador
-        Some(params.temperature),
-        Some(params.pressure),
-        Some(params.time_seconds),
-        params.coffee_type,
-        params.roast_level,
-        params.grind_size,
-    );
+        Ok(BrewConfig {
+            method: self.method,
+            dose_g: self.dose_g.unwrap_or(defaults.dose_g),
+            water_g: self.water_g.unwrap_or(defaults.water_g),
+            grind_microns: self.grind_microns.unwrap_or(defaults.grind_microns),
+            temperature: self.temperature.unwrap_or(defaults.temperature),
+            brew_seconds: self.brew_seconds.unwrap_or(defaults.brew_seconds),
+            bloom_seconds: self.bloom_seconds.unwrap_or(defaults.bloom_seconds),
+            pours: self.pours.unwrap_or(defaults.pours),
+            coffee_type: self
+                .coffee_type
+                .or(bean.map(|b| b.coffee_type))
+                .unwrap_or(defaults.coffee_type),
+            roast_level: self
+                .roast_level
+                .or(bean.map(|b| b.roast_level))
+                .unwrap_or(defaults.roast_level),
+            water,
+            environment: Environment {
+                altitude_m: self.altitude_m,
+                ambient_temperature: self.ambient_temperature,
+                humidity: self.humidity,
+            },
+            days_off_roast: bean.map(|b| b.days_off_roast(Utc::now().date_naive())),
+            scorer: self.scorer,
+        })
+    }
 
-    info!(
-        "Simulated extraction with temp={}, pressure={}, time={}, coffee_type={:?}, roast_level={:?}, grind_size={:?}", // <--- ACTUALIZADO LOG
-        params.temperature, params.pressure, params.time_seconds,
-        params.coffee_type, params.roast_level, params.grind_size
-    );
+    pub fn validate(&self, config: &BrewConfig) -> Result<()> {
+        config.environment.validate().map_err(|message| ApiError {
+            message,
+            status: 400,
+            errors: Vec::new(),
+        })?;
+        self.method
+            .method()
+            .validate(config)
+            .map_err(|message| ApiError {
+                message,
+                status: 400,
+                errors: Vec::new(),
+            })
+    }
+}
+
+// Shots as returned by /start or /brew, and the milk to steam for them
+#[derive(Debug, Deserialize)]
+pub struct DrinkRequest {
+    pub kind: DrinkKind,
+    pub shots: Vec<ExtractionMetrics>,
+    #[serde(default)]
+    pub milk: Steaming,
+}
+
+// The brew to improve on, and the shots pulled so far with what the brewer
+// thought of them, most recent last
+#[derive(Debug, Deserialize)]
+pub struct SuggestRequest {
+    #[serde(flatten)]
+    pub brew: BrewParams,
+    #[serde(default)]
+    pub shots: Vec<TastedShot>,
+}
+
+// The shot to sweep around, and the parameters to sweep
+#[derive(Debug, Deserialize)]
+pub struct SweepRequest {
+    #[serde(flatten)]
+    pub shot: ExtractionParams,
+    pub axes: Vec<Axis>,
+}
+
+// The recipe to test and how far each input strays; `seed` replays an
+// analysis and `variance` adds shot-to-shot variance to every run
+#[derive(Debug, Deserialize)]
+pub struct RobustnessRequest {
+    #[serde(flatten)]
+    pub shot: ExtractionParams,
+    #[serde(default = "default_runs")]
+    pub runs: usize,
+    #[serde(default)]
+    pub tolerances: Tolerances,
+}
+
+fn default_runs() -> usize {
+    500
+}
 
-    let metrics_bytes = serde_json::to_vec(&metrics).map_err(|e| {
-        error!("Failed to serialize metrics: {}", e);
+// Pulls a stored recipe instead of the raw parameters; any parameter given
+// alongside it overrides the recipe's
+#[derive(Debug, Deserialize)]
+pub struct RecipeSelection {
+    #[serde(default)]
+    pub recipe_id: Option<String>,
+    // Latest version when left out
+    #[serde(default)]
+    pub recipe_version: Option<u32>,
+}
+
+// Versions to compare; by default the one before the latest against the latest
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    #[serde(default)]
+    pub from: Option<u32>,
+    #[serde(default)]
+    pub to: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: FileFormat,
+    #[serde(default = "default_hash")]
+    pub hash: bool,
+}
+
+fn default_hash() -> bool {
+    true
+}
+
+// Read from the document itself when not given
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub format: Option<FileFormat>,
+}
+
+// The recipe as stored after an import, and what the import changed
+#[derive(Debug, Serialize)]
+pub struct RecipeImport {
+    pub recipe: Recipe,
+    pub added_versions: usize,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecipeDiff {
+    pub from: Recipe,
+    pub to: Recipe,
+    pub changes: Vec<FieldChange>,
+}
+
+// Shot logs in the machine's export format, and the name to save the fit as
+#[derive(Debug, Deserialize)]
+pub struct CalibrationRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub log: ShotLog,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrendQuery {
+    #[serde(default = "default_trend_period")]
+    pub period: TrendPeriod,
+    #[serde(default)]
+    pub method: Option<BrewMethodKind>,
+}
+
+fn default_trend_period() -> TrendPeriod {
+    TrendPeriod::Weekly
+}
+
+fn repository_error(what: &str, e: RepositoryError) -> ApiError {
+    match e {
+        RepositoryError::NotFound => ApiError {
+            message: format!("{} not found", what),
+            status: 404,
+            errors: Vec::new(),
+        },
+        RepositoryError::Conflict(message) => ApiError {
+            message,
+            status: 409,
+            errors: Vec::new(),
+        },
+        e => {
+            error!("Failed to access {}: {}", what, e);
+            ApiError {
+                message: format!("Failed to access {}: {}", what, e),
+                status: 500,
+                errors: Vec::new(),
+            }
+        }
+    }
+}
+
+// Runs a search or fit on the blocking pool, so the async workers keep
+// serving other requests while it computes
+async fn compute<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(work).await.map_err(|e| {
+        error!("Computation failed: {}", e);
         ApiError {
-            message: format!("Failed to serialize metrics: {}", e),
+            message: "Computation failed".to_string(),
             status: 500,
+            errors: Vec::new(),
         }
+    })
+}
+
+fn load_bean(db: &Arc<dyn Storage>, bean_id: Option<&String>) -> Result<Option<Bean>> {
+    match bean_id {
+        Some(id) => BeanRepository::new(db.clone())
+            .and_then(|beans| beans.get(id))
+            .map(Some)
+            .map_err(|e| repository_error("Bean", e)),
+        None => Ok(None),
+    }
+}
+
+fn load_calibration(db: &Arc<dyn Storage>, name: Option<&String>) -> Result<Option<Calibration>> {
+    match name {
+        Some(name) => CalibrationRepository::new(db.clone())
+            .and_then(|calibrations| calibrations.get(name))
+            .map(Some)
+            .map_err(|e| repository_error("Calibration", e)),
+        None => Ok(None),
+    }
+}
+
+fn recipe_repository(db: &Arc<dyn Storage>) -> Result<RecipeRepository> {
+    RecipeRepository::new(db.clone()).map_err(|e| repository_error("Recipe", e))
+}
+
+// A recipe must pull a valid shot on its own, with nothing overriding it
+fn validate_recipe(db: &Arc<dyn Storage>, recipe: &NewRecipe) -> Result<()> {
+    let params = validate_recipe_params(recipe)?;
+    let bean = load_bean(db, params.bean_id.as_ref())?;
+    params.validate(bean.as_ref())?;
+    params.shot_config(bean.as_ref(), None)?;
+    Ok(())
+}
+
+// Everything but the bean, which may live in another instance's catalog
+fn validate_recipe_params(recipe: &NewRecipe) -> Result<ExtractionParams> {
+    recipe.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
     })?;
+    let params =
+        ExtractionParams::default().with_recipe(recipe.params.clone(), RecipeParams::default());
+    params.validate(None)?;
+    params.shot_config(None, None)?;
+    Ok(params)
+}
 
-    let key = format!("metric_{}", Utc::now().timestamp_millis());
-    state
-        .db
-        .insert(key.as_bytes(), metrics_bytes)
-        .map_err(|e| {
-            error!("Failed to store metrics in sled: {}", e);
+// A recipe and all its versions in the sharing format
+pub fn export_recipe_file(
+    db: &Arc<dyn Storage>,
+    id: &str,
+    format: FileFormat,
+    hash: bool,
+) -> Result<String> {
+    let versions = recipe_repository(db)?
+        .versions(id)
+        .map_err(|e| repository_error("Recipe", e))?;
+    RecipeFile::new(versions, hash)
+        .and_then(|file| file.to_text(format))
+        .map_err(|message| {
+            error!("Failed to export recipe {}: {}", id, message);
             ApiError {
-                message: format!("Failed to store metrics: {}", e),
+                message: format!("Failed to export recipe: {}", message),
                 status: 500,
+                errors: Vec::new(),
             }
+        })
+}
+
+// Adds a recipe from another instance, keeping its id and versions. Known
+// versions must be identical, so importing the same file twice is harmless
+pub fn import_recipe_file(
+    db: &Arc<dyn Storage>,
+    text: &str,
+    format: FileFormat,
+) -> Result<RecipeImport> {
+    let file = RecipeFile::parse(text, format).map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    let mut warnings = Vec::new();
+    for recipe in &file.versions {
+        validate_recipe_params(&NewRecipe {
+            name: recipe.name.clone(),
+            author: recipe.author.clone(),
+            notes: recipe.notes.clone(),
+            params: recipe.params.clone(),
+        })
+        .map_err(|e| ApiError {
+            message: format!("Version {}: {}", recipe.version, e.message),
+            status: 400,
+            errors: Vec::new(),
         })?;
-    debug!("Stored metrics with key: {}", key);
+    }
+    let recipe = file.versions[file.versions.len() - 1].clone();
+    if let Some(bean_id) = &recipe.params.bean_id {
+        if load_bean(db, Some(bean_id)).is_err() {
+            warnings.push(format!(
+                "Bean {} is not in this catalog; shots pulled from the recipe need another bean_id",
+                bean_id
+            ));
+        }
+    }
+    let added_versions = recipe_repository(db)?
+        .import(&file.versions)
+        .map_err(|e| repository_error("Recipe", e))?;
+    Ok(RecipeImport {
+        recipe,
+        added_versions,
+        warnings,
+    })
+}
+
+// Logs the shot's alerts and keeps them for `/alerts`
+fn record_alerts(db: &Arc<dyn Storage>, metrics: &ExtractionMetrics) -> Result<()> {
+    let alerts = AlertGenerator::new().generate_alerts(metrics);
+    for alert in &alerts {
+        warn!(
+            severity = ?alert.severity,
+            category = ?alert.category,
+            metadata = ?alert.metadata,
+            "{}",
+            alert.message
+        );
+    }
+    AnalyticsRepository::new(db.clone())
+        .and_then(|repository| repository.store_alerts(&alerts))
+        .map_err(|e| repository_error("Alert", e))
+}
+
+// Shots and brews of every method go to the same tree; the stored shot
+// comes back with its id
+fn store_metrics(db: &Arc<dyn Storage>, metrics: ExtractionMetrics) -> Result<ExtractionMetrics> {
+    let metrics = ShotRepository::new(db.clone())
+        .and_then(|shots| shots.create(metrics))
+        .map_err(|e| repository_error("Shot", e))?;
+    debug!("Stored shot {}", metrics.id);
+    Ok(metrics)
+}
+
+fn now_seconds() -> f64 {
+    Utc::now().timestamp_millis() as f64 / 1000.0
+}
+
+// Kept under 2^53 so JavaScript clients can pass it back unchanged
+fn fresh_seed() -> u64 {
+    Uuid::new_v4().as_u64_pair().0 & ((1 << 53) - 1)
+}
+
+// A 400 naming every invalid field; one problem reads as before, several
+// are listed together
+fn invalid_fields(errors: Vec<FieldError>) -> ApiError {
+    let message = match errors.as_slice() {
+        [error] => error.message.clone(),
+        errors => format!(
+            "{} fields are invalid: {}",
+            errors.len(),
+            errors
+                .iter()
+                .map(|e| format!("{} ({})", e.field, e.message))
+                .collect::<Vec<_>>()
+                .join("; ")
+        ),
+    };
+    ApiError {
+        message,
+        status: 400,
+        errors,
+    }
+}
+
+// When the body doesn't parse, each of its fields is tried on its own, so
+// the error names every one that is invalid rather than serde's first
+fn from_body<T: DeserializeOwned>(body: &serde_json::Value) -> Result<T> {
+    T::deserialize(body).map_err(|e| {
+        let errors: Vec<FieldError> = body
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(field, value)| {
+                let alone = serde_json::json!({ field: value });
+                T::deserialize(&alone)
+                    .err()
+                    .map(|e| FieldError::new(field, format!("Invalid {}: {}", field, e)))
+            })
+            .collect();
+        if errors.is_empty() {
+            return ApiError {
+                message: format!("Invalid request body: {}", e),
+                status: 400,
+                errors,
+            };
+        }
+        invalid_fields(errors)
+    })
+}
+
+// Everything `/start` reads from a JSON body, parsed in one go
+#[derive(Debug, Deserialize)]
+struct ExtractionBody {
+    #[serde(flatten)]
+    params: ExtractionParams,
+    #[serde(flatten)]
+    selection: RecipeSelection,
+}
+
+pub async fn start_extraction(
+    AxumState(state): AxumState<AppState>,
+    Query(params): Query<ExtractionParams>,
+    Query(selection): Query<RecipeSelection>,
+    Query(given): Query<RecipeParams>,
+    body: Option<Json<serde_json::Value>>,
+) -> Result<Json<ExtractionMetrics>> {
+    // A JSON body carries the whole request, nested profiles and water
+    // included; the query string is only read without one
+    let (params, selection, given) = match body {
+        Some(Json(body)) => {
+            let ExtractionBody { params, selection } = from_body(&body)?;
+            let given = params.named_in(&body);
+            (params, selection, given)
+        }
+        None => (params, selection, given),
+    };
+    debug!("Received extraction request: {:?}", params);
+
+    let recipe = match &selection.recipe_id {
+        Some(id) => Some(
+            recipe_repository(&state.db)?
+                .resolve(id, selection.recipe_version)
+                .map_err(|e| repository_error("Recipe", e))?,
+        ),
+        None => None,
+    };
+    let params = match &recipe {
+        Some(recipe) => params.with_recipe(recipe.params.clone(), given),
+        None => params,
+    };
+
+    let bean = load_bean(&state.db, params.bean_id.as_ref())?;
+    params.validate(bean.as_ref())?;
+    let calibration = load_calibration(&state.db, params.calibration.as_ref())?;
+    let machine = params.machine.map(|kind| {
+        let now = now_seconds();
+        let machines = state.machines.lock().expect("machine state lock poisoned");
+        machines
+            .get(&kind)
+            .map_or_else(|| MachineState::warm(kind, now), |machine| machine.at(now))
+    });
+    let config = ShotConfig {
+        machine,
+        ..params.shot_config(bean.as_ref(), calibration.as_ref())?
+    };
+
+    let metrics = ExtractionMetrics {
+        bean_id: bean.map(|b| b.id),
+        calibration: calibration.map(|c| c.name),
+        recipe_id: recipe.as_ref().map(|r| r.id.clone()),
+        recipe_version: recipe.as_ref().map(|r| r.version),
+        user: params.user.clone(),
+        ..simulate(&config)
+    };
+    if let Some(machine) = metrics.machine {
+        state
+            .machines
+            .lock()
+            .expect("machine state lock poisoned")
+            .insert(machine.kind, machine);
+    }
+
+    record_alerts(&state.db, &metrics)?;
+
+    info!(
+        "Simulated extraction with temp={}, pressure={}, time={}, coffee_type={:?}, roast_level={:?}, grind_size={:?}, profile={}",
+        params.temperature, params.pressure, params.time_seconds,
+        params.coffee_type, params.roast_level, params.grind_size, metrics.profile.name
+    );
+
+    let metrics = store_metrics(&state.db, metrics)?;
 
     Ok(Json(metrics))
 }
 
-pub async fn get_metrics(
+// Brews with any method, stored alongside the espresso shots
+pub async fn start_brew(
     AxumState(state): AxumState<AppState>,
-) -> Result<Json<Vec<ExtractionMetrics>>> {
-    // ExtractionMetrics ahora está importado
-    let mut metrics_vec = Vec::new(); // Renombrado para evitar shadowing con el 'metrics' del bucle si lo hubiera
-    for entry in state.db.iter() {
-        let (_key, value) = entry.map_err(|e| {
-            error!("Failed to read from sled: {}", e);
-            ApiError {
-                message: format!("Failed to read metrics: {}", e),
-                status: 500,
-            }
+    Query(params): Query<BrewParams>,
+) -> Result<Json<ExtractionMetrics>> {
+    debug!("Received brew request: {:?}", params);
+
+    let bean = load_bean(&state.db, params.bean_id.as_ref())?;
+    let config = params.brew_config(bean.as_ref())?;
+    params.validate(&config)?;
+
+    let metrics = ExtractionMetrics {
+        bean_id: bean.map(|b| b.id),
+        user: params.user.clone(),
+        ..brew(&config)
+    };
+    record_alerts(&state.db, &metrics)?;
+
+    info!(
+        method = ?config.method,
+        dose_g = config.dose_g,
+        water_g = config.water_g,
+        extraction_yield = metrics.extraction_yield_percent,
+        "Simulated brew"
+    );
+
+    let metrics = store_metrics(&state.db, metrics)?;
+
+    Ok(Json(metrics))
+}
+
+// Settings with the highest predicted score for a bean and method; nothing
+// is brewed or stored
+pub async fn optimize_brew(
+    AxumState(state): AxumState<AppState>,
+    Query(params): Query<BrewParams>,
+) -> Result<Json<Suggestion>> {
+    let bean = load_bean(&state.db, params.bean_id.as_ref())?;
+    let config = params.brew_config(bean.as_ref())?;
+    params.validate(&config)?;
+
+    let method = config.method;
+    let suggestion = compute(move || optimize(&config)).await?;
+    info!(
+        method = ?method,
+        evaluations = suggestion.evaluations,
+        quality_score = suggestion.predicted.quality_score,
+        "Optimized brew"
+    );
+    Ok(Json(suggestion))
+}
+
+// The next shot to pull, given the previous ones and how they tasted
+pub async fn suggest_shot(
+    AxumState(state): AxumState<AppState>,
+    Json(request): Json<SuggestRequest>,
+) -> Result<Json<Suggestion>> {
+    let params = &request.brew;
+    let bean = load_bean(&state.db, params.bean_id.as_ref())?;
+    let config = params.brew_config(bean.as_ref())?;
+    params.validate(&config)?;
+
+    let (method, shots) = (config.method, request.shots.len());
+    let suggestion = compute(move || suggest(&config, &request.shots))
+        .await?
+        .map_err(|message| ApiError {
+            message,
+            status: 400,
+            errors: Vec::new(),
         })?;
-        let metric: ExtractionMetrics = serde_json::from_slice(&value).map_err(|e| {
-            // ExtractionMetrics ahora está importado
-            error!("Failed to deserialize metric: {}", e);
-            ApiError {
-                message: format!("Failed to deserialize metric: {}", e),
-                status: 500,
-            }
+    info!(
+        method = ?method,
+        shots,
+        quality_score = suggestion.predicted.quality_score,
+        "Suggested next shot"
+    );
+    Ok(Json(suggestion))
+}
+
+// Scores, EY and results over a grid of parameters around one shot, plus how
+// sensitive that shot is to each parameter; nothing is stored
+pub async fn sweep_shot(
+    AxumState(state): AxumState<AppState>,
+    Json(request): Json<SweepRequest>,
+) -> Result<Json<Sweep>> {
+    let bean = load_bean(&state.db, request.shot.bean_id.as_ref())?;
+    request.shot.validate(bean.as_ref())?;
+    let calibration = load_calibration(&state.db, request.shot.calibration.as_ref())?;
+    let config = request
+        .shot
+        .shot_config(bean.as_ref(), calibration.as_ref())?;
+
+    let parameters: Vec<_> = request.axes.iter().map(|a| a.parameter).collect();
+    let result = compute(move || sweep(&config, &request.axes))
+        .await?
+        .map_err(|message| ApiError {
+            message,
+            status: 400,
+            errors: Vec::new(),
         })?;
-        metrics_vec.push(metric);
+    info!(
+        axes = ?parameters,
+        "Swept extraction parameters"
+    );
+    Ok(Json(result))
+}
+
+// How forgiving a recipe is when temperature, pressure, grind and dose
+// wander; nothing is stored
+pub async fn analyze_robustness(
+    AxumState(state): AxumState<AppState>,
+    Json(request): Json<RobustnessRequest>,
+) -> Result<Json<Robustness>> {
+    let bean = load_bean(&state.db, request.shot.bean_id.as_ref())?;
+    request.shot.validate(bean.as_ref())?;
+    request.tolerances.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    if !(1..=MAX_RUNS).contains(&request.runs) {
+        return Err(ApiError {
+            message: format!("Runs must be between 1 and {}", MAX_RUNS),
+            status: 400,
+            errors: Vec::new(),
+        });
     }
+    let calibration = load_calibration(&state.db, request.shot.calibration.as_ref())?;
+    let recipe = ShotConfig {
+        seed: None,
+        ..request
+            .shot
+            .shot_config(bean.as_ref(), calibration.as_ref())?
+    };
+    let seed = request.shot.seed.unwrap_or_else(fresh_seed);
 
-    if metrics_vec.is_empty() {
-        info!("No metrics available in sled");
+    let report = compute(move || {
+        analyze(
+            &recipe,
+            &request.tolerances,
+            request.runs,
+            seed,
+            request.shot.variance,
+        )
+    })
+    .await?;
+    info!(
+        runs = report.runs,
+        seed = report.seed,
+        perfect_probability = report.perfect_probability,
+        "Analyzed recipe robustness"
+    );
+    Ok(Json(report))
+}
+
+// Milk drinks built from shots already pulled
+pub async fn build_drink(Json(request): Json<DrinkRequest>) -> Result<Json<Drink>> {
+    if !(1..=4).contains(&request.shots.len()) {
         return Err(ApiError {
-            message: "No metrics available".to_string(),
-            status: 404,
+            message: "A drink takes between 1 and 4 shots".to_string(),
+            status: 400,
+            errors: Vec::new(),
+        });
+    }
+    request.milk.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+
+    let drink = Drink::build(request.kind, &request.shots, request.milk.steam());
+    info!(
+        kind = ?drink.kind,
+        shots = drink.shots,
+        milk_type = ?drink.milk.milk_type,
+        quality_score = drink.quality_score,
+        "Built milk drink"
+    );
+    Ok(Json(drink))
+}
+
+// One page of stored shots; an empty page is still a 200
+pub async fn get_metrics(
+    AxumState(state): AxumState<AppState>,
+    Query(query): Query<ShotQuery>,
+) -> Result<Json<ShotPage>> {
+    query.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    let page = ShotRepository::new(state.db.clone())
+        .and_then(|shots| shots.query(&query))
+        .map_err(|e| repository_error("Shot", e))?;
+    info!("Returning {} stored metrics", page.shots.len());
+    Ok(Json(page))
+}
+
+pub async fn get_shot(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ExtractionMetrics>> {
+    let metrics = ShotRepository::new(state.db.clone())
+        .and_then(|shots| shots.get(&id))
+        .map_err(|e| repository_error("Shot", e))?;
+    Ok(Json(metrics))
+}
+
+pub async fn annotate_shot(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+    Json(patch): Json<AnnotationPatch>,
+) -> Result<Json<ExtractionMetrics>> {
+    patch.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    let metrics = ShotRepository::new(state.db.clone())
+        .and_then(|shots| shots.annotate(&id, patch))
+        .map_err(|e| repository_error("Shot", e))?;
+    info!("Annotated shot {}", id);
+    Ok(Json(metrics))
+}
+
+pub async fn delete_shot(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    ShotRepository::new(state.db.clone())
+        .and_then(|shots| shots.delete(&id))
+        .map_err(|e| repository_error("Shot", e))?;
+    info!("Removed shot {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Bean catalog endpoints
+pub async fn create_bean(
+    AxumState(state): AxumState<AppState>,
+    Json(bean): Json<NewBean>,
+) -> Result<(StatusCode, Json<Bean>)> {
+    bean.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    let bean = BeanRepository::new(state.db.clone())
+        .and_then(|beans| beans.create(bean))
+        .map_err(|e| repository_error("Bean", e))?;
+    info!("Added bean {} from {}", bean.id, bean.roaster);
+    Ok((StatusCode::CREATED, Json(bean)))
+}
+
+pub async fn list_beans(AxumState(state): AxumState<AppState>) -> Result<Json<Vec<Bean>>> {
+    let beans = BeanRepository::new(state.db.clone())
+        .and_then(|beans| beans.list())
+        .map_err(|e| repository_error("Bean", e))?;
+    Ok(Json(beans))
+}
+
+pub async fn get_bean(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Bean>> {
+    let bean = BeanRepository::new(state.db.clone())
+        .and_then(|beans| beans.get(&id))
+        .map_err(|e| repository_error("Bean", e))?;
+    Ok(Json(bean))
+}
+
+pub async fn update_bean(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+    Json(bean): Json<NewBean>,
+) -> Result<Json<Bean>> {
+    bean.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    let bean = BeanRepository::new(state.db.clone())
+        .and_then(|beans| beans.update(&id, bean))
+        .map_err(|e| repository_error("Bean", e))?;
+    Ok(Json(bean))
+}
+
+pub async fn delete_bean(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    BeanRepository::new(state.db.clone())
+        .and_then(|beans| beans.delete(&id))
+        .map_err(|e| repository_error("Bean", e))?;
+    info!("Removed bean {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Calibration endpoints
+pub async fn create_calibration(
+    AxumState(state): AxumState<AppState>,
+    Json(request): Json<CalibrationRequest>,
+) -> Result<(StatusCode, Json<Calibration>)> {
+    let calibration = compute(move || calibrate(&request.name, &request.log))
+        .await?
+        .map_err(|message| ApiError {
+            message,
+            status: 400,
+            errors: Vec::new(),
+        })?;
+    CalibrationRepository::new(state.db.clone())
+        .and_then(|calibrations| calibrations.save(&calibration))
+        .map_err(|e| repository_error("Calibration", e))?;
+    info!(
+        name = %calibration.name,
+        shots = calibration.shots,
+        default_error = calibration.default_fit.rms_error,
+        error = calibration.fit.rms_error,
+        "Saved calibration"
+    );
+    Ok((StatusCode::CREATED, Json(calibration)))
+}
+
+pub async fn list_calibrations(
+    AxumState(state): AxumState<AppState>,
+) -> Result<Json<Vec<Calibration>>> {
+    let calibrations = CalibrationRepository::new(state.db.clone())
+        .and_then(|calibrations| calibrations.list())
+        .map_err(|e| repository_error("Calibration", e))?;
+    Ok(Json(calibrations))
+}
+
+pub async fn get_calibration(
+    AxumState(state): AxumState<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Calibration>> {
+    let calibration = CalibrationRepository::new(state.db.clone())
+        .and_then(|calibrations| calibrations.get(&name))
+        .map_err(|e| repository_error("Calibration", e))?;
+    Ok(Json(calibration))
+}
+
+pub async fn delete_calibration(
+    AxumState(state): AxumState<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    CalibrationRepository::new(state.db.clone())
+        .and_then(|calibrations| calibrations.delete(&name))
+        .map_err(|e| repository_error("Calibration", e))?;
+    info!("Removed calibration {}", name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Recipe endpoints
+pub async fn create_recipe(
+    AxumState(state): AxumState<AppState>,
+    Json(recipe): Json<NewRecipe>,
+) -> Result<(StatusCode, Json<Recipe>)> {
+    validate_recipe(&state.db, &recipe)?;
+    let recipe = recipe_repository(&state.db)?
+        .create(recipe)
+        .map_err(|e| repository_error("Recipe", e))?;
+    info!("Added recipe {} by {}", recipe.id, recipe.author);
+    Ok((StatusCode::CREATED, Json(recipe)))
+}
+
+pub async fn list_recipes(AxumState(state): AxumState<AppState>) -> Result<Json<Vec<Recipe>>> {
+    let recipes = recipe_repository(&state.db)?
+        .list()
+        .map_err(|e| repository_error("Recipe", e))?;
+    Ok(Json(recipes))
+}
+
+pub async fn get_recipe(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Recipe>> {
+    let recipe = recipe_repository(&state.db)?
+        .get(&id)
+        .map_err(|e| repository_error("Recipe", e))?;
+    Ok(Json(recipe))
+}
+
+// Saves the changes as the recipe's next version
+pub async fn update_recipe(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+    Json(recipe): Json<NewRecipe>,
+) -> Result<Json<Recipe>> {
+    validate_recipe(&state.db, &recipe)?;
+    let recipe = recipe_repository(&state.db)?
+        .update(&id, recipe)
+        .map_err(|e| repository_error("Recipe", e))?;
+    info!("Saved recipe {} version {}", recipe.id, recipe.version);
+    Ok(Json(recipe))
+}
+
+pub async fn delete_recipe(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    recipe_repository(&state.db)?
+        .delete(&id)
+        .map_err(|e| repository_error("Recipe", e))?;
+    info!("Removed recipe {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_recipe_versions(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Recipe>>> {
+    let versions = recipe_repository(&state.db)?
+        .versions(&id)
+        .map_err(|e| repository_error("Recipe", e))?;
+    Ok(Json(versions))
+}
+
+pub async fn get_recipe_version(
+    AxumState(state): AxumState<AppState>,
+    Path((id, version)): Path<(String, u32)>,
+) -> Result<Json<Recipe>> {
+    let recipe = recipe_repository(&state.db)?
+        .get_version(&id, version)
+        .map_err(|e| repository_error("Recipe version", e))?;
+    Ok(Json(recipe))
+}
+
+pub async fn fork_recipe(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+    Json(fork): Json<RecipeFork>,
+) -> Result<(StatusCode, Json<Recipe>)> {
+    if fork.author.trim().is_empty() {
+        return Err(ApiError {
+            message: "Author must not be empty".to_string(),
+            status: 400,
+            errors: Vec::new(),
         });
     }
+    let recipe = recipe_repository(&state.db)?
+        .fork(&id, fork)
+        .map_err(|e| repository_error("Recipe", e))?;
+    info!("Forked recipe {} into {}", id, recipe.id);
+    Ok((StatusCode::CREATED, Json(recipe)))
+}
+
+pub async fn diff_recipe(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<RecipeDiff>> {
+    let recipes = recipe_repository(&state.db)?;
+    let to = recipes
+        .resolve(&id, query.to)
+        .map_err(|e| repository_error("Recipe version", e))?;
+    let previous = to.version.saturating_sub(1).max(1);
+    let from = recipes
+        .get_version(&id, query.from.unwrap_or(previous))
+        .map_err(|e| repository_error("Recipe version", e))?;
+    let changes = from.diff(&to);
+    Ok(Json(RecipeDiff { from, to, changes }))
+}
 
-    info!("Returning {} stored metrics", metrics_vec.len());
-    Ok(Json(metrics_vec))
+pub async fn export_recipe(
+    AxumState(state): AxumState<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse> {
+    let text = export_recipe_file(&state.db, &id, query.format, query.hash)?;
+    info!("Exported recipe {} as {:?}", id, query.format);
+    Ok(([(header::CONTENT_TYPE, query.format.content_type())], text))
+}
+
+pub async fn import_recipe(
+    AxumState(state): AxumState<AppState>,
+    Query(query): Query<ImportQuery>,
+    text: String,
+) -> Result<(StatusCode, Json<RecipeImport>)> {
+    let format = query.format.unwrap_or_else(|| FileFormat::detect(&text));
+    let import = import_recipe_file(&state.db, &text, format)?;
+    info!(
+        id = %import.recipe.id,
+        added_versions = import.added_versions,
+        "Imported recipe"
+    );
+    let status = if import.added_versions > 0 {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(import)))
+}
+
+// Standards endpoints
+pub async fn get_standards() -> Json<Standards> {
+    Json(standards::current().as_ref().clone())
+}
+
+pub async fn update_standards(
+    AxumState(state): AxumState<AppState>,
+    Json(new_standards): Json<Standards>,
+) -> Result<Json<Standards>> {
+    new_standards.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    new_standards
+        .save(&state.standards_path)
+        .map_err(|message| {
+            error!("Error saving standards: {}", message);
+            ApiError {
+                message: "Error saving standards".to_string(),
+                status: 500,
+                errors: Vec::new(),
+            }
+        })?;
+    standards::replace(new_standards.clone());
+    info!("Updated extraction standards");
+    Ok(Json(new_standards))
+}
+
+// Machine endpoints
+pub async fn list_machines(AxumState(state): AxumState<AppState>) -> Json<Vec<MachineState>> {
+    let now = now_seconds();
+    let machines = state.machines.lock().expect("machine state lock poisoned");
+    Json(machines.values().map(|machine| machine.at(now)).collect())
+}
+
+// Switches a machine on from cold; shots pulled right after show it warming up
+pub async fn power_on_machine(
+    AxumState(state): AxumState<AppState>,
+    Path(kind): Path<MachineKind>,
+) -> Json<MachineState> {
+    let machine = MachineState::cold(kind, now_seconds());
+    state
+        .machines
+        .lock()
+        .expect("machine state lock poisoned")
+        .insert(kind, machine);
+    info!("Powered on {:?} from cold", kind);
+    Json(machine)
 }
 
 // Trends endpoint
 pub async fn get_trends(
     AxumState(state): AxumState<AppState>,
-    Query(_period): Query<TrendPeriod>, // Query ahora está explícitamente importado
+    Query(query): Query<TrendQuery>,
 ) -> Result<Json<Vec<ExtractionTrends>>> {
-    let repository = AnalyticsRepository::new(state.db.clone());
-    let trends = repository.get_trends().map_err(|e| {
-        error!("Error fetching trends: {:?}", e);
-        ApiError {
-            message: "Error fetching trends".to_string(),
-            status: 500,
-        }
-    })?;
+    let trends = AnalyticsRepository::new(state.db.clone())
+        .and_then(|repository| repository.get_trends(query.period, query.method))
+        .map_err(|e| repository_error("Metrics", e))?;
     Ok(Json(trends))
 }
 
+// One page of stored brews, each scored by every scorer
+#[derive(Debug, Serialize)]
+pub struct ScorePage {
+    pub scores: Vec<ShotScores>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+// Scores stored brews with each scorer, to compare them on the same shots;
+// paged and filtered like `/metrics`
+pub async fn get_scores(
+    AxumState(state): AxumState<AppState>,
+    Query(query): Query<ShotQuery>,
+) -> Result<Json<ScorePage>> {
+    query.validate().map_err(|message| ApiError {
+        message,
+        status: 400,
+        errors: Vec::new(),
+    })?;
+    let page = ShotRepository::new(state.db.clone())
+        .and_then(|shots| shots.query(&query))
+        .map_err(|e| repository_error("Shot", e))?;
+    Ok(Json(ScorePage {
+        scores: page.shots.iter().map(ShotScores::of).collect(),
+        next_cursor: page.next_cursor,
+    }))
+}
+
 // Alerts endpoint
 pub async fn get_alerts(AxumState(state): AxumState<AppState>) -> Result<Json<Vec<Alert>>> {
-    let repository = AnalyticsRepository::new(state.db.clone());
-    let alerts = repository.get_alerts().map_err(|e| {
-        error!("Error fetching alerts: {:?}", e);
-        ApiError {
-            message: "Error fetching alerts".to_string(),
-            status: 500,
-        }
-    })?;
+    let alerts = AnalyticsRepository::new(state.db.clone())
+        .and_then(|repository| repository.get_alerts())
+        .map_err(|e| {
+            error!("Error fetching alerts: {:?}", e);
+            ApiError {
+                message: "Error fetching alerts".to_string(),
+                status: 500,
+                errors: Vec::new(),
+            }
+        })?;
     Ok(Json(alerts))
 }
 
+// The server and the command line share one database, picked by
+// `ESPRESSIA_STORAGE` and `ESPRESSIA_DB`
+pub fn open_db() -> std::result::Result<Arc<dyn Storage>, String> {
+    let config = StorageConfig::from_env()?;
+    let db = config
+        .open()
+        .map_err(|e| format!("Failed to open the database: {}", e))?;
+    info!("Storing records in {}", config.describe());
+    Ok(db)
+}
+
 impl AppState {
     pub fn new() -> Self {
-        let db_config = sled::Config::new()
-            .path("espressia_metrics_db")
-            .use_compression(true)
-            .mode(sled::Mode::HighThroughput);
+        let db = open_db().unwrap_or_else(|message| panic!("{}", message));
+        let indexed = ShotRepository::new(db.clone())
+            .and_then(|shots| shots.index_stored_shots())
+            .expect("Failed to index stored shots");
+        if indexed > 0 {
+            info!("Indexed {} stored shots", indexed);
+        }
 
-        let db = db_config.open().expect("Failed to open sled database");
-        info!("Initialized sled database at espressia_metrics_db");
-        Self { db: Arc::new(db) }
+        let standards_path = PathBuf::from(
+            std::env::var("ESPRESSIA_STANDARDS").unwrap_or_else(|_| "standards.toml".to_string()),
+        );
+        if standards_path.exists() {
+            let loaded = Standards::load(&standards_path).expect("Failed to load standards");
+            standards::replace(loaded);
+            info!(
+                "Loaded extraction standards from {}",
+                standards_path.display()
+            );
+        }
+        Self {
+            db,
+            machines: Arc::new(Mutex::new(HashMap::new())),
+            standards_path: Arc::new(standards_path),
+        }
     }
 }
 
 pub async fn setup_server(app_state: AppState) -> std::io::Result<()> {
     let app = Router::new()
         .route("/start", post(start_extraction))
+        .route("/brew", post(start_brew))
+        .route("/drinks", post(build_drink))
+        .route("/optimize", get(optimize_brew))
+        .route("/suggest", post(suggest_shot))
+        .route("/sweep", post(sweep_shot))
+        .route("/robustness", post(analyze_robustness))
         .route("/metrics", get(get_metrics))
-        // Deberías añadir tus rutas de trends y alerts aquí también si quieres exponerlas
-        .route("/trends", get(get_trends)) // <--- AÑADIDO (Ejemplo)
-        .route("/alerts", get(get_alerts)) // <--- AÑADIDO (Ejemplo)
+        .route(
+            "/metrics/{id}",
+            get(get_shot).patch(annotate_shot).delete(delete_shot),
+        )
+        .route("/trends", get(get_trends))
+        .route("/scores", get(get_scores))
+        .route("/alerts", get(get_alerts))
+        .route("/beans", get(list_beans).post(create_bean))
+        .route(
+            "/beans/{id}",
+            get(get_bean).put(update_bean).delete(delete_bean),
+        )
+        .route(
+            "/calibrations",
+            get(list_calibrations).post(create_calibration),
+        )
+        .route(
+            "/calibrations/{name}",
+            get(get_calibration).delete(delete_calibration),
+        )
+        .route("/recipes", get(list_recipes).post(create_recipe))
+        .route(
+            "/recipes/{id}",
+            get(get_recipe).put(update_recipe).delete(delete_recipe),
+        )
+        .route("/recipes/{id}/versions", get(list_recipe_versions))
+        .route("/recipes/{id}/versions/{version}", get(get_recipe_version))
+        .route("/recipes/{id}/fork", post(fork_recipe))
+        .route("/recipes/{id}/diff", get(diff_recipe))
+        .route("/recipes/{id}/export", get(export_recipe))
+        .route("/recipes/import", post(import_recipe))
+        .route("/standards", get(get_standards).put(update_standards))
+        .route("/machines", get(list_machines))
+        .route("/machines/{kind}/power-on", post(power_on_machine))
         .with_state(app_state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
@@ -234,3 +1619,181 @@ pub async fn setup_server(app_state: AppState) -> std::io::Result<()> {
     axum::serve(listener, app).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beans::Process;
+    use crate::storage::MemoryStorage;
+    use chrono::NaiveDate;
+
+    fn state() -> AppState {
+        AppState {
+            db: Arc::new(MemoryStorage::default()),
+            machines: Arc::new(Mutex::new(HashMap::new())),
+            standards_path: Arc::new(PathBuf::from("standards.toml")),
+        }
+    }
+
+    async fn add_bean(state: &AppState, roast_level: RoastLevel) -> Bean {
+        let bean = NewBean {
+            roaster: "Tim Wendelboe".to_string(),
+            origin: "Ethiopia".to_string(),
+            varietal: None,
+            process: Process::Washed,
+            coffee_type: CoffeeType::SingleOrigin,
+            roast_level,
+            roast_date: NaiveDate::from_ymd_opt(2025, 2, 1).unwrap(),
+            bag_weight_g: 250.0,
+        };
+        let (status, Json(bean)) = create_bean(AxumState(state.clone()), Json(bean))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        bean
+    }
+
+    async fn start(state: &AppState, params: ExtractionParams) -> Result<ExtractionMetrics> {
+        let selection = RecipeSelection {
+            recipe_id: None,
+            recipe_version: None,
+        };
+        start_extraction(
+            AxumState(state.clone()),
+            Query(params),
+            Query(selection),
+            Query(RecipeParams::default()),
+            None,
+        )
+        .await
+        .map(|Json(metrics)| metrics)
+    }
+
+    #[tokio::test]
+    async fn test_scores_page_through_stored_shots() {
+        let state = state();
+        let scores = |query: ShotQuery| get_scores(AxumState(state.clone()), Query(query));
+        // Nothing stored yet is an empty page, not an error
+        let Json(empty) = scores(ShotQuery::default()).await.unwrap();
+        assert!(empty.scores.is_empty());
+        assert!(empty.next_cursor.is_none());
+
+        for _ in 0..3 {
+            start(&state, ExtractionParams::default()).await.unwrap();
+        }
+        let Json(first) = scores(ShotQuery {
+            limit: Some(2),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(first.scores.len(), 2);
+        let Json(rest) = scores(ShotQuery {
+            limit: Some(2),
+            cursor: first.next_cursor,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(rest.scores.len(), 1);
+        assert!(rest.next_cursor.is_none());
+
+        let invalid = ShotQuery {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(scores(invalid).await.unwrap_err().status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_trends_are_a_list() {
+        let state = state();
+        let trends = || {
+            get_trends(
+                AxumState(state.clone()),
+                Query(TrendQuery {
+                    period: TrendPeriod::Daily,
+                    method: None,
+                }),
+            )
+        };
+        let Json(empty) = trends().await.unwrap();
+        assert!(empty.is_empty());
+        start(&state, ExtractionParams::default()).await.unwrap();
+        let Json(trends) = trends().await.unwrap();
+        assert_eq!(trends.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shots_from_a_bean() {
+        let state = state();
+        let light = add_bean(&state, RoastLevel::Light).await;
+        let dark = add_bean(&state, RoastLevel::Dark).await;
+        let at = |temperature, bean: &Bean| ExtractionParams {
+            temperature,
+            bean_id: Some(bean.id.clone()),
+            ..Default::default()
+        };
+
+        // The bean's coffee reaches the shot
+        let metrics = start(&state, at(93.0, &light)).await.unwrap();
+        assert_eq!(metrics.bean_id.as_deref(), Some(light.id.as_str()));
+        assert_eq!(metrics.coffee_type, CoffeeType::SingleOrigin);
+        assert_eq!(metrics.roast_level, RoastLevel::Light);
+        assert!(metrics.days_off_roast.is_some());
+
+        // ...and so do its temperature windows, which the default Medium
+        // window would let through
+        for (temperature, bean) in [(95.0, &dark), (91.0, &light)] {
+            let error = start(&state, at(temperature, bean)).await.unwrap_err();
+            assert_eq!(error.status, 400);
+            assert_eq!(error.errors[0].field, "temperature");
+            assert!(start(
+                &state,
+                ExtractionParams {
+                    temperature,
+                    ..Default::default()
+                }
+            )
+            .await
+            .is_ok());
+        }
+
+        // A roast level named in the request wins over the bean's
+        let medium = ExtractionParams {
+            roast_level: Some(RoastLevel::Medium),
+            ..at(91.0, &light)
+        };
+        assert_eq!(
+            start(&state, medium).await.unwrap().roast_level,
+            RoastLevel::Medium
+        );
+
+        let missing = ExtractionParams {
+            bean_id: Some(Uuid::new_v4().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(start(&state, missing).await.unwrap_err().status, 404);
+    }
+
+    #[test]
+    fn test_body_errors_name_every_invalid_field() {
+        let body = serde_json::json!({"dose_g": "abc", "temperature": "hot", "pressure": 9.5});
+        let error = from_body::<ExtractionBody>(&body).unwrap_err();
+        assert_eq!(error.status, 400);
+        let fields: Vec<&str> = error.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["dose_g", "temperature"]);
+        assert!(error.message.starts_with("2 fields are invalid"));
+
+        // Only what the body named overrides a recipe
+        let body = serde_json::json!({"pressure": 9.5, "recipe_id": "house"});
+        let ExtractionBody { params, selection } = from_body(&body).unwrap();
+        assert_eq!(selection.recipe_id.as_deref(), Some("house"));
+        let given = params.named_in(&body);
+        assert_eq!(given.pressure, Some(9.5));
+        assert_eq!(given.temperature, None);
+
+        let error = from_body::<ExtractionBody>(&serde_json::json!("espresso")).unwrap_err();
+        assert!(error.message.starts_with("Invalid request body"));
+    }
+}
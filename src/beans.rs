@@ -0,0 +1,245 @@
+use crate::analytics::errors::RepositoryError;
+use crate::simulation::{CoffeeType, RoastLevel};
+use crate::storage::{Storage, Table};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum Process {
+    Washed,
+    Natural,
+    Honey,
+    Anaerobic,
+    WetHulled,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Bean {
+    pub id: String,
+    pub roaster: String,
+    pub origin: String,
+    #[serde(default)]
+    pub varietal: Option<String>,
+    pub process: Process,
+    #[serde(default)]
+    pub coffee_type: CoffeeType,
+    pub roast_level: RoastLevel,
+    pub roast_date: NaiveDate,
+    pub bag_weight_g: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+// A bean as sent by clients, before it gets an id
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct NewBean {
+    pub roaster: String,
+    pub origin: String,
+    #[serde(default)]
+    pub varietal: Option<String>,
+    pub process: Process,
+    #[serde(default)]
+    pub coffee_type: CoffeeType,
+    pub roast_level: RoastLevel,
+    pub roast_date: NaiveDate,
+    pub bag_weight_g: f64,
+}
+
+impl NewBean {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.roaster.trim().is_empty() {
+            return Err("Roaster must not be empty".to_string());
+        }
+        if self.origin.trim().is_empty() {
+            return Err("Origin must not be empty".to_string());
+        }
+        if !(self.bag_weight_g > 0.0 && self.bag_weight_g <= 5000.0) {
+            return Err("Bag weight must be between 0 and 5000 grams".to_string());
+        }
+        if self.roast_date > Utc::now().date_naive() {
+            return Err("Roast date must not be in the future".to_string());
+        }
+        Ok(())
+    }
+
+    fn into_bean(self, id: String, created_at: DateTime<Utc>) -> Bean {
+        Bean {
+            id,
+            roaster: self.roaster,
+            origin: self.origin,
+            varietal: self.varietal,
+            process: self.process,
+            coffee_type: self.coffee_type,
+            roast_level: self.roast_level,
+            roast_date: self.roast_date,
+            bag_weight_g: self.bag_weight_g,
+            created_at,
+        }
+    }
+}
+
+impl Bean {
+    pub fn days_off_roast(&self, today: NaiveDate) -> f64 {
+        (today - self.roast_date).num_days().max(0) as f64
+    }
+}
+
+// Beans live in their own table so they never mix with stored shots
+pub struct BeanRepository {
+    tree: Arc<dyn Table>,
+}
+
+impl BeanRepository {
+    pub fn new(db: Arc<dyn Storage>) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            tree: db.table("beans")?,
+        })
+    }
+
+    pub fn create(&self, bean: NewBean) -> Result<Bean, RepositoryError> {
+        let bean = bean.into_bean(Uuid::new_v4().to_string(), Utc::now());
+        self.tree
+            .insert(bean.id.as_bytes(), &serde_json::to_vec(&bean)?)?;
+        Ok(bean)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Bean, RepositoryError> {
+        match self.tree.get(id.as_bytes())? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Err(RepositoryError::NotFound),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Bean>, RepositoryError> {
+        let mut beans = Vec::new();
+        for entry in self.tree.iter() {
+            let (_key, value) = entry?;
+            beans.push(serde_json::from_slice(&value)?);
+        }
+        Ok(beans)
+    }
+
+    pub fn update(&self, id: &str, bean: NewBean) -> Result<Bean, RepositoryError> {
+        let existing = self.get(id)?;
+        let bean = bean.into_bean(existing.id, existing.created_at);
+        self.tree
+            .insert(bean.id.as_bytes(), &serde_json::to_vec(&bean)?)?;
+        Ok(bean)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), RepositoryError> {
+        if self.tree.remove(id.as_bytes())? {
+            Ok(())
+        } else {
+            Err(RepositoryError::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn new_bean(roast_level: RoastLevel) -> NewBean {
+        NewBean {
+            roaster: "Tim Wendelboe".to_string(),
+            origin: "Ethiopia".to_string(),
+            varietal: Some("Heirloom".to_string()),
+            process: Process::Washed,
+            coffee_type: CoffeeType::SingleOrigin,
+            roast_level,
+            roast_date: NaiveDate::from_ymd_opt(2025, 2, 1).unwrap(),
+            bag_weight_g: 250.0,
+        }
+    }
+
+    #[test]
+    fn test_bean_catalog() {
+        let beans = BeanRepository::new(Arc::new(MemoryStorage::default())).unwrap();
+        let light = beans.create(new_bean(RoastLevel::Light)).unwrap();
+        let dark = beans.create(new_bean(RoastLevel::Dark)).unwrap();
+        assert!(light.id.parse::<Uuid>().is_ok());
+        assert_ne!(light.id, dark.id);
+
+        let fetched = beans.get(&light.id).unwrap();
+        assert_eq!(fetched.roast_level, RoastLevel::Light);
+        assert_eq!(fetched.coffee_type, CoffeeType::SingleOrigin);
+        assert_eq!(beans.list().unwrap().len(), 2);
+
+        // Updates keep the id and when the bean was added
+        let updated = beans
+            .update(
+                &light.id,
+                NewBean {
+                    bag_weight_g: 1000.0,
+                    ..new_bean(RoastLevel::Medium)
+                },
+            )
+            .unwrap();
+        assert_eq!(updated.id, light.id);
+        assert_eq!(updated.created_at, light.created_at);
+        assert_eq!(
+            beans.get(&light.id).unwrap().roast_level,
+            RoastLevel::Medium
+        );
+
+        beans.delete(&dark.id).unwrap();
+        assert!(matches!(
+            beans.get(&dark.id),
+            Err(RepositoryError::NotFound)
+        ));
+        assert!(matches!(
+            beans.delete(&dark.id),
+            Err(RepositoryError::NotFound)
+        ));
+        assert!(matches!(
+            beans.update(&dark.id, new_bean(RoastLevel::Dark)),
+            Err(RepositoryError::NotFound)
+        ));
+        assert_eq!(beans.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_bean_validation_and_age() {
+        assert!(new_bean(RoastLevel::Medium).validate().is_ok());
+        let invalid = [
+            NewBean {
+                roaster: " ".to_string(),
+                ..new_bean(RoastLevel::Medium)
+            },
+            NewBean {
+                origin: String::new(),
+                ..new_bean(RoastLevel::Medium)
+            },
+            NewBean {
+                bag_weight_g: 0.0,
+                ..new_bean(RoastLevel::Medium)
+            },
+            NewBean {
+                bag_weight_g: 5001.0,
+                ..new_bean(RoastLevel::Medium)
+            },
+            NewBean {
+                roast_date: Utc::now().date_naive() + chrono::Duration::days(1),
+                ..new_bean(RoastLevel::Medium)
+            },
+        ];
+        for bean in invalid {
+            assert!(bean.validate().is_err(), "{:?}", bean);
+        }
+
+        let bean = new_bean(RoastLevel::Medium).into_bean("bean".to_string(), Utc::now());
+        let roasted = bean.roast_date;
+        assert_eq!(
+            bean.days_off_roast(roasted + chrono::Duration::days(9)),
+            9.0
+        );
+        // A bag dated after the shot hasn't aged at all
+        assert_eq!(
+            bean.days_off_roast(roasted - chrono::Duration::days(3)),
+            0.0
+        );
+    }
+}
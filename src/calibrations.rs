@@ -0,0 +1,50 @@
+use crate::analytics::errors::RepositoryError;
+use crate::simulation::calibration::Calibration;
+use crate::storage::{Storage, Table};
+use std::sync::Arc;
+
+// Calibration profiles, keyed by name; fitting one again under the same name
+// replaces it
+pub struct CalibrationRepository {
+    tree: Arc<dyn Table>,
+}
+
+impl CalibrationRepository {
+    pub fn new(db: Arc<dyn Storage>) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            tree: db.table("calibrations")?,
+        })
+    }
+
+    pub fn save(&self, calibration: &Calibration) -> Result<(), RepositoryError> {
+        self.tree.insert(
+            calibration.name.as_bytes(),
+            &serde_json::to_vec(calibration)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Calibration, RepositoryError> {
+        match self.tree.get(name.as_bytes())? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Err(RepositoryError::NotFound),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Calibration>, RepositoryError> {
+        let mut calibrations = Vec::new();
+        for entry in self.tree.iter() {
+            let (_key, value) = entry?;
+            calibrations.push(serde_json::from_slice(&value)?);
+        }
+        Ok(calibrations)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), RepositoryError> {
+        if self.tree.remove(name.as_bytes())? {
+            Ok(())
+        } else {
+            Err(RepositoryError::NotFound)
+        }
+    }
+}
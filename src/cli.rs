@@ -0,0 +1,89 @@
+use crate::api::{export_recipe_file, import_recipe_file, open_db};
+use crate::recipe_file::FileFormat;
+use std::path::Path;
+
+pub const USAGE: &str = "Usage:
+  espressia                    start the server
+  espressia recipe export <id> [--format json|toml] [--no-hash] [--output <file>]
+  espressia recipe import <file> [--format json|toml]";
+
+// Options after the positional arguments, as (name, value); flags have none
+fn options(args: &[String]) -> Result<Vec<(&str, Option<&str>)>, String> {
+    let mut options = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-hash" => options.push(("--no-hash", None)),
+            "--format" | "--output" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                options.push((arg.as_str(), Some(value.as_str())));
+            }
+            _ => return Err(format!("Unknown option {}\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_format(value: &str) -> Result<FileFormat, String> {
+    match value {
+        "json" => Ok(FileFormat::Json),
+        "toml" => Ok(FileFormat::Toml),
+        _ => Err(format!("Format must be json or toml, got {}", value)),
+    }
+}
+
+// Runs one command against the local database; the server must not be
+// running, since it holds the database open
+pub fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [group, command, target, rest @ ..] if group == "recipe" && command == "export" => {
+            let options = options(rest)?;
+            let output = options
+                .iter()
+                .find(|(name, _)| *name == "--output")
+                .and_then(|(_, value)| *value);
+            // An explicit format wins over the output file's extension
+            let format = match options.iter().find(|(name, _)| *name == "--format") {
+                Some((_, Some(value))) => parse_format(value)?,
+                _ => output.map_or(FileFormat::Json, |path| {
+                    FileFormat::for_path(Path::new(path))
+                }),
+            };
+            let hash = !options.iter().any(|(name, _)| *name == "--no-hash");
+
+            let db = open_db()?;
+            let text = export_recipe_file(&db, target, format, hash).map_err(|e| e.to_string())?;
+            match output {
+                Some(path) => {
+                    std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e))?;
+                    eprintln!("Exported recipe {} to {}", target, path);
+                }
+                None => print!("{}", text),
+            }
+            Ok(())
+        }
+        [group, command, target, rest @ ..] if group == "recipe" && command == "import" => {
+            let options = options(rest)?;
+            let text = std::fs::read_to_string(target).map_err(|e| format!("{}: {}", target, e))?;
+            let format = match options.iter().find(|(name, _)| *name == "--format") {
+                Some((_, Some(value))) => parse_format(value)?,
+                _ => FileFormat::detect(&text),
+            };
+
+            let db = open_db()?;
+            let import = import_recipe_file(&db, &text, format).map_err(|e| e.to_string())?;
+            db.flush().map_err(|e| format!("Failed to save the recipe: {}", e))?;
+            eprintln!(
+                "Imported recipe {} ({}), {} new version(s), now at version {}",
+                import.recipe.id, import.recipe.name, import.added_versions, import.recipe.version
+            );
+            for warning in import.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
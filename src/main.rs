@@ -1,11 +1,32 @@
 mod simulation;
 mod api;
 mod analytics;
+mod beans;
+mod calibrations;
+mod cli;
+mod recipe_file;
+mod recipes;
+mod shots;
+mod storage;
 
 use tracing::{info, Level};
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        // Commands print their results to stdout, so logs go to stderr
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_max_level(Level::WARN)
+            .init();
+        if let Err(message) = cli::run(&args) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Initialize the tracing subscriber
     tracing_subscriber::fmt()
         .with_max_level(Level::DEBUG)
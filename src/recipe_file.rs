@@ -0,0 +1,328 @@
+use crate::recipes::Recipe;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use uuid::Uuid;
+
+// Marks a file as an Espressia recipe, whatever it is called
+pub const FORMAT: &str = "espressia-recipe";
+// Bumped whenever a change would make older instances misread the file
+pub const SCHEMA_VERSION: u32 = 1;
+const HASH_PREFIX: &str = "sha256:";
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    #[default]
+    Json,
+    Toml,
+}
+
+impl FileFormat {
+    // JSON for .json files, TOML for anything else
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension == "json" => FileFormat::Json,
+            _ => FileFormat::Toml,
+        }
+    }
+
+    // JSON documents open with a brace, TOML ones never do
+    pub fn detect(text: &str) -> Self {
+        if text.trim_start().starts_with('{') {
+            FileFormat::Json
+        } else {
+            FileFormat::Toml
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FileFormat::Json => "application/json",
+            FileFormat::Toml => "application/toml",
+        }
+    }
+}
+
+// The unit each recipe parameter is given in
+pub fn units() -> BTreeMap<String, String> {
+    [
+        ("temperature", "°C"),
+        ("pressure", "bar"),
+        ("time_seconds", "s"),
+        ("grind_microns", "µm"),
+        ("tamp_kg", "kg"),
+        ("dose_g", "g"),
+        ("yield_g", "g"),
+    ]
+    .into_iter()
+    .map(|(parameter, unit)| (parameter.to_string(), unit.to_string()))
+    .collect()
+}
+
+// A recipe with its whole version history, oldest first, as traded between
+// instances
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct RecipeFile {
+    pub format: String,
+    pub schema_version: u32,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub units: BTreeMap<String, String>,
+    // SHA-256 of the versions, to catch files edited or damaged on the way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    pub versions: Vec<Recipe>,
+}
+
+// Only the fields that exist in every schema version, so a file from a
+// newer instance can be told apart from a broken one
+#[derive(Deserialize)]
+struct Header {
+    format: Option<String>,
+    schema_version: Option<u32>,
+}
+
+fn content_hash(versions: &[Recipe]) -> Result<String, String> {
+    // Going through a `Value` sorts the keys, so the hash doesn't depend on
+    // the field order of whoever wrote the file
+    let value = serde_json::to_value(versions).map_err(|e| e.to_string())?;
+    let bytes = serde_json::to_vec(&value).map_err(|e| e.to_string())?;
+    let digest = Sha256::digest(&bytes);
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(format!("{}{}", HASH_PREFIX, hex))
+}
+
+impl RecipeFile {
+    pub fn new(versions: Vec<Recipe>, with_hash: bool) -> Result<Self, String> {
+        let content_hash = if with_hash {
+            Some(content_hash(&versions)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            format: FORMAT.to_string(),
+            schema_version: SCHEMA_VERSION,
+            exported_at: Utc::now(),
+            units: units(),
+            content_hash,
+            versions,
+        })
+    }
+
+    pub fn to_text(&self, format: FileFormat) -> Result<String, String> {
+        match format {
+            FileFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            FileFormat::Toml => toml::to_string(self).map_err(|e| e.to_string()),
+        }
+    }
+
+    // Reads and checks a recipe file; the recipes' parameters are left to
+    // the caller to check against the standards
+    pub fn parse(text: &str, format: FileFormat) -> Result<Self, String> {
+        let header: Header = match format {
+            FileFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            FileFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("Not a readable {:?} document: {}", format, e))?;
+        if header.format.as_deref() != Some(FORMAT) {
+            return Err(format!(
+                "Not an Espressia recipe: `format` must be \"{}\"",
+                FORMAT
+            ));
+        }
+        match header.schema_version {
+            None => return Err("Recipe file has no `schema_version`".to_string()),
+            Some(version) if version > SCHEMA_VERSION => {
+                return Err(format!(
+                    "Recipe file uses schema version {}, but this instance only reads up to version {}; upgrade Espressia to import it",
+                    version, SCHEMA_VERSION
+                ))
+            }
+            Some(version) if version < 1 => {
+                return Err(format!("Unknown recipe schema version {}", version))
+            }
+            Some(_) => {}
+        }
+
+        let file: Self = match format {
+            FileFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            FileFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| {
+            format!(
+                "Recipe file does not match schema version {}: {}",
+                SCHEMA_VERSION, e
+            )
+        })?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let expected = units();
+        for (parameter, unit) in &self.units {
+            match expected.get(parameter) {
+                Some(expected) if expected != unit => {
+                    return Err(format!(
+                        "`{}` is given in {}, but Espressia expects {}",
+                        parameter, unit, expected
+                    ))
+                }
+                Some(_) => {}
+                None => return Err(format!("Unknown parameter `{}` in `units`", parameter)),
+            }
+        }
+
+        let Some(first) = self.versions.first() else {
+            return Err("Recipe file has no versions".to_string());
+        };
+        // Ids become storage keys, where anything but a UUID could reach
+        // into another recipe's versions
+        let canonical = |id: Uuid| id.to_string() == first.id;
+        if !first.id.parse::<Uuid>().is_ok_and(canonical) {
+            return Err(format!("Recipe id must be a UUID, found {}", first.id));
+        }
+        for (i, recipe) in self.versions.iter().enumerate() {
+            if recipe.id != first.id {
+                return Err(format!(
+                    "Every version must belong to recipe {}, found {}",
+                    first.id, recipe.id
+                ));
+            }
+            if recipe.version as usize != i + 1 {
+                return Err(format!(
+                    "Versions must run 1, 2, 3... in order, found version {} in place {}",
+                    recipe.version,
+                    i + 1
+                ));
+            }
+        }
+
+        if let Some(hash) = &self.content_hash {
+            if !hash.starts_with(HASH_PREFIX) {
+                return Err(format!("Content hash must start with \"{}\"", HASH_PREFIX));
+            }
+            if *hash != content_hash(&self.versions)? {
+                return Err(
+                    "Content hash does not match the recipe; the file was changed after export"
+                        .to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipes::{RecipeParams, RecipeRef};
+    use crate::simulation::profile::{ProfileSelection, ShotProfile};
+    use crate::simulation::water::{WaterRecipe, WaterSelection};
+
+    fn versions() -> Vec<Recipe> {
+        let first = Recipe {
+            id: "5f0c2a4e-8d61-4b3a-9c57-1e2f3a4b5c6d".to_string(),
+            version: 1,
+            name: "Lever day".to_string(),
+            author: "Ana".to_string(),
+            notes: "Slow \"decline\"\nthen stop".to_string(),
+            params: RecipeParams {
+                temperature: Some(93.5),
+                dose_g: Some(18.2),
+                profile: Some(ProfileSelection::Custom(
+                    ShotProfile::preset("lever", 9.0).unwrap(),
+                )),
+                water: Some(WaterSelection::Recipe(WaterRecipe::Rao)),
+                ..Default::default()
+            },
+            forked_from: Some(RecipeRef {
+                id: "a1b2".to_string(),
+                version: 3,
+            }),
+            created_at: Utc::now(),
+        };
+        let second = Recipe {
+            version: 2,
+            params: RecipeParams {
+                yield_g: Some(36.0),
+                ..first.params.clone()
+            },
+            ..first.clone()
+        };
+        vec![first, second]
+    }
+
+    #[test]
+    fn test_recipe_file_round_trip() {
+        let versions = versions();
+        let original = serde_json::to_value(&versions).unwrap();
+        for format in [FileFormat::Json, FileFormat::Toml] {
+            let text = RecipeFile::new(versions.clone(), true)
+                .unwrap()
+                .to_text(format)
+                .unwrap();
+            assert_eq!(FileFormat::detect(&text), format);
+            let file = RecipeFile::parse(&text, format).unwrap();
+            assert_eq!(serde_json::to_value(&file.versions).unwrap(), original);
+            assert_eq!(file.units["temperature"], "°C");
+        }
+
+        // Without a hash the file is taken as it is
+        let text = RecipeFile::new(versions.clone(), false)
+            .unwrap()
+            .to_text(FileFormat::Toml)
+            .unwrap();
+        assert!(!text.contains("content_hash"));
+        assert!(RecipeFile::parse(&text, FileFormat::Toml).is_ok());
+    }
+
+    #[test]
+    fn test_recipe_file_rejects_bad_files() {
+        let file = RecipeFile::new(versions(), true).unwrap();
+        let parse = |file: &RecipeFile| {
+            RecipeFile::parse(&file.to_text(FileFormat::Json).unwrap(), FileFormat::Json)
+        };
+
+        let newer = RecipeFile {
+            schema_version: SCHEMA_VERSION + 1,
+            ..file.clone()
+        };
+        assert!(parse(&newer).unwrap_err().contains("upgrade Espressia"));
+        // A newer schema is reported as such even if the rest doesn't parse
+        let text = format!(
+            "{{\"format\": \"{}\", \"schema_version\": 2, \"recipe\": {{}}}}",
+            FORMAT
+        );
+        assert!(RecipeFile::parse(&text, FileFormat::Json)
+            .unwrap_err()
+            .contains("schema version 2"));
+        assert!(RecipeFile::parse("{\"versions\": []}", FileFormat::Json).is_err());
+
+        let mut fahrenheit = file.clone();
+        fahrenheit
+            .units
+            .insert("temperature".to_string(), "°F".to_string());
+        assert!(parse(&fahrenheit).unwrap_err().contains("°F"));
+
+        let mut tampered = file.clone();
+        tampered.versions[1].params.temperature = Some(96.0);
+        assert!(parse(&tampered).unwrap_err().contains("hash"));
+
+        // An id that would write into another recipe's versions
+        let mut hostile = file.clone();
+        hostile.content_hash = None;
+        for recipe in &mut hostile.versions {
+            recipe.id = "6a1d9c2b-3e4f-4a5b-8c7d-0e1f2a3b4c5d/9999999999".to_string();
+        }
+        assert!(parse(&hostile).unwrap_err().contains("UUID"));
+
+        let mut gap = file.clone();
+        gap.content_hash = None;
+        gap.versions.remove(0);
+        assert!(parse(&gap).is_err());
+    }
+}
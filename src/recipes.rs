@@ -0,0 +1,417 @@
+use crate::analytics::errors::RepositoryError;
+use crate::simulation::profile::ProfileSelection;
+use crate::simulation::water::WaterSelection;
+use crate::simulation::{CoffeeType, GrindSize, RoastLevel};
+use crate::storage::{Storage, Table};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// The shot a recipe pulls; anything left out falls back to the `/start`
+// defaults. Also parsed from the `/start` query, where it holds only the
+// parameters that were given, to override the recipe's
+#[derive(Serialize, Debug, Deserialize, Clone, Default)]
+pub struct RecipeParams {
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub pressure: Option<f64>,
+    #[serde(default)]
+    pub time_seconds: Option<u64>,
+    #[serde(default)]
+    pub coffee_type: Option<CoffeeType>,
+    #[serde(default)]
+    pub roast_level: Option<RoastLevel>,
+    #[serde(default)]
+    pub grind_size: Option<GrindSize>,
+    #[serde(default)]
+    pub grind_microns: Option<f64>,
+    #[serde(default)]
+    pub tamp_kg: Option<f64>,
+    #[serde(default)]
+    pub dose_g: Option<f64>,
+    #[serde(default)]
+    pub yield_g: Option<f64>,
+    #[serde(default)]
+    pub profile: Option<ProfileSelection>,
+    #[serde(default)]
+    pub water: Option<WaterSelection>,
+    #[serde(default)]
+    pub bean_id: Option<String>,
+}
+
+// One version of a recipe; versions are never changed once written, an
+// update adds the next one
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Recipe {
+    pub id: String,
+    pub version: u32,
+    pub name: String,
+    pub author: String,
+    #[serde(default)]
+    pub notes: String,
+    pub params: RecipeParams,
+    // The recipe version this one was forked from, if any
+    #[serde(default)]
+    pub forked_from: Option<RecipeRef>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct RecipeRef {
+    pub id: String,
+    pub version: u32,
+}
+
+// A recipe as sent by clients, before it gets an id and version
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct NewRecipe {
+    pub name: String,
+    pub author: String,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub params: RecipeParams,
+}
+
+impl NewRecipe {
+    // The parameters themselves are checked against the standards by the API
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > 100 {
+            return Err("Name must be between 1 and 100 characters".to_string());
+        }
+        if self.author.trim().is_empty() {
+            return Err("Author must not be empty".to_string());
+        }
+        if self.notes.len() > 5000 {
+            return Err("Notes must be at most 5000 characters".to_string());
+        }
+        Ok(())
+    }
+
+    fn into_recipe(
+        self,
+        id: String,
+        version: u32,
+        forked_from: Option<RecipeRef>,
+        created_at: DateTime<Utc>,
+    ) -> Recipe {
+        Recipe {
+            id,
+            version,
+            name: self.name.trim().to_string(),
+            author: self.author.trim().to_string(),
+            notes: self.notes,
+            params: self.params,
+            forked_from,
+            created_at,
+        }
+    }
+}
+
+// Who forks a recipe, and optionally which version and under what name
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct RecipeFork {
+    pub author: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<u32>,
+}
+
+// One field that differs between two versions, named by its path such as
+// `params.temperature`; `null` stands for a field that isn't set
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+impl Recipe {
+    pub fn reference(&self) -> RecipeRef {
+        RecipeRef {
+            id: self.id.clone(),
+            version: self.version,
+        }
+    }
+
+    // What changed from `self` to `other`; bookkeeping such as the version
+    // number and creation time is left out
+    pub fn diff(&self, other: &Recipe) -> Vec<FieldChange> {
+        let mut changes = Vec::new();
+        for (field, from, to) in [
+            (
+                "name",
+                Value::from(self.name.as_str()),
+                Value::from(other.name.as_str()),
+            ),
+            (
+                "author",
+                Value::from(self.author.as_str()),
+                Value::from(other.author.as_str()),
+            ),
+            (
+                "notes",
+                Value::from(self.notes.as_str()),
+                Value::from(other.notes.as_str()),
+            ),
+        ] {
+            if from != to {
+                changes.push(FieldChange {
+                    field: field.to_string(),
+                    from,
+                    to,
+                });
+            }
+        }
+        // Both sides serialize every field, so the keys line up
+        let from = serde_json::to_value(&self.params).unwrap_or_default();
+        let to = serde_json::to_value(&other.params).unwrap_or_default();
+        if let (Value::Object(from), Value::Object(to)) = (from, to) {
+            for (field, from) in from {
+                let to = to.get(&field).cloned().unwrap_or(Value::Null);
+                if from != to {
+                    changes.push(FieldChange {
+                        field: format!("params.{}", field),
+                        from,
+                        to,
+                    });
+                }
+            }
+        }
+        changes
+    }
+}
+
+// Every version of every recipe, keyed `{id}/{version}` with the version
+// zero-padded so a recipe's versions sort in order
+pub struct RecipeRepository {
+    tree: Arc<dyn Table>,
+}
+
+fn key(id: &str, version: u32) -> String {
+    format!("{}/{:010}", id, version)
+}
+
+fn prefix(id: &str) -> String {
+    format!("{}/", id)
+}
+
+impl RecipeRepository {
+    pub fn new(db: Arc<dyn Storage>) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            tree: db.table("recipes")?,
+        })
+    }
+
+    fn insert(&self, recipe: &Recipe) -> Result<(), RepositoryError> {
+        self.tree.insert(
+            key(&recipe.id, recipe.version).as_bytes(),
+            &serde_json::to_vec(recipe)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn create(&self, recipe: NewRecipe) -> Result<Recipe, RepositoryError> {
+        let recipe = recipe.into_recipe(Uuid::new_v4().to_string(), 1, None, Utc::now());
+        self.insert(&recipe)?;
+        Ok(recipe)
+    }
+
+    // Latest version
+    pub fn get(&self, id: &str) -> Result<Recipe, RepositoryError> {
+        match self.tree.last_with_prefix(prefix(id).as_bytes())? {
+            Some((_key, value)) => Ok(serde_json::from_slice(&value)?),
+            None => Err(RepositoryError::NotFound),
+        }
+    }
+
+    pub fn get_version(&self, id: &str, version: u32) -> Result<Recipe, RepositoryError> {
+        match self.tree.get(key(id, version).as_bytes())? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Err(RepositoryError::NotFound),
+        }
+    }
+
+    // The given version, or the latest one
+    pub fn resolve(&self, id: &str, version: Option<u32>) -> Result<Recipe, RepositoryError> {
+        match version {
+            Some(version) => self.get_version(id, version),
+            None => self.get(id),
+        }
+    }
+
+    // Oldest first
+    pub fn versions(&self, id: &str) -> Result<Vec<Recipe>, RepositoryError> {
+        let mut versions = Vec::new();
+        for entry in self.tree.scan_prefix(prefix(id).as_bytes()) {
+            let (_key, value) = entry?;
+            versions.push(serde_json::from_slice(&value)?);
+        }
+        if versions.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(versions)
+    }
+
+    // Latest version of each recipe
+    pub fn list(&self) -> Result<Vec<Recipe>, RepositoryError> {
+        let mut recipes: Vec<Recipe> = Vec::new();
+        for entry in self.tree.iter() {
+            let (_key, value) = entry?;
+            let recipe: Recipe = serde_json::from_slice(&value)?;
+            // Versions of one recipe come one after the other
+            match recipes.last_mut() {
+                Some(last) if last.id == recipe.id => *last = recipe,
+                _ => recipes.push(recipe),
+            }
+        }
+        Ok(recipes)
+    }
+
+    // Adds the next version; the earlier ones stay as they were
+    pub fn update(&self, id: &str, recipe: NewRecipe) -> Result<Recipe, RepositoryError> {
+        let latest = self.get(id)?;
+        let recipe = recipe.into_recipe(
+            latest.id,
+            latest.version + 1,
+            latest.forked_from,
+            Utc::now(),
+        );
+        self.insert(&recipe)?;
+        Ok(recipe)
+    }
+
+    // A new recipe starting from a copy of one version of another
+    pub fn fork(&self, id: &str, fork: RecipeFork) -> Result<Recipe, RepositoryError> {
+        let source = self.resolve(id, fork.version)?;
+        let recipe = NewRecipe {
+            name: fork.name.unwrap_or_else(|| source.name.clone()),
+            author: fork.author,
+            notes: source.notes.clone(),
+            params: source.params.clone(),
+        }
+        .into_recipe(
+            Uuid::new_v4().to_string(),
+            1,
+            Some(source.reference()),
+            Utc::now(),
+        );
+        self.insert(&recipe)?;
+        Ok(recipe)
+    }
+
+    // Stores a version history exported from another instance as it is.
+    // Versions already stored must match the imported ones; returns how many
+    // were new
+    pub fn import(&self, versions: &[Recipe]) -> Result<usize, RepositoryError> {
+        let Some(first) = versions.first() else {
+            return Ok(0);
+        };
+        let stored = match self.versions(&first.id) {
+            Ok(stored) => stored,
+            Err(RepositoryError::NotFound) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        for (stored, imported) in stored.iter().zip(versions) {
+            if serde_json::to_value(stored)? != serde_json::to_value(imported)? {
+                return Err(RepositoryError::Conflict(format!(
+                    "Version {} of recipe {} differs from the one already stored; fork the recipe instead",
+                    stored.version, stored.id
+                )));
+            }
+        }
+        for recipe in versions.iter().skip(stored.len()) {
+            self.insert(recipe)?;
+        }
+        Ok(versions.len().saturating_sub(stored.len()))
+    }
+
+    // Removes every version; forks keep their own copies
+    pub fn delete(&self, id: &str) -> Result<(), RepositoryError> {
+        let mut removed = false;
+        for entry in self.tree.scan_prefix(prefix(id).as_bytes()) {
+            let (key, _value) = entry?;
+            self.tree.remove(&key)?;
+            removed = true;
+        }
+        if removed {
+            Ok(())
+        } else {
+            Err(RepositoryError::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn repository() -> RecipeRepository {
+        RecipeRepository::new(Arc::new(MemoryStorage::default())).unwrap()
+    }
+
+    fn new_recipe(name: &str, temperature: f64) -> NewRecipe {
+        NewRecipe {
+            name: name.to_string(),
+            author: "Ana".to_string(),
+            notes: String::new(),
+            params: RecipeParams {
+                temperature: Some(temperature),
+                dose_g: Some(18.0),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_recipe_versions_and_forks() {
+        let recipes = repository();
+        let first = recipes.create(new_recipe("Morning", 93.0)).unwrap();
+        assert_eq!(first.version, 1);
+        let second = recipes
+            .update(&first.id, new_recipe("Morning", 94.5))
+            .unwrap();
+        assert_eq!(second.version, 2);
+
+        // Earlier versions stay as they were
+        assert_eq!(recipes.get(&first.id).unwrap().version, 2);
+        let kept = recipes.get_version(&first.id, 1).unwrap();
+        assert_eq!(kept.params.temperature, Some(93.0));
+        assert_eq!(recipes.versions(&first.id).unwrap().len(), 2);
+
+        assert_eq!(
+            kept.diff(&second),
+            vec![FieldChange {
+                field: "params.temperature".to_string(),
+                from: Value::from(93.0),
+                to: Value::from(94.5),
+            }]
+        );
+
+        let fork = recipes
+            .fork(
+                &first.id,
+                RecipeFork {
+                    author: "Ben".to_string(),
+                    name: None,
+                    version: Some(1),
+                },
+            )
+            .unwrap();
+        assert_ne!(fork.id, first.id);
+        assert_eq!(fork.version, 1);
+        assert_eq!(fork.forked_from, Some(kept.reference()));
+        assert_eq!(fork.params.temperature, Some(93.0));
+        assert_eq!(recipes.list().unwrap().len(), 2);
+
+        recipes.delete(&first.id).unwrap();
+        assert!(recipes.get(&first.id).is_err());
+        assert!(recipes.get(&fork.id).is_ok());
+        assert!(new_recipe(" ", 93.0).validate().is_err());
+    }
+}
@@ -0,0 +1,654 @@
+use crate::analytics::errors::RepositoryError;
+use crate::simulation::{CoffeeType, ExtractionMetrics, GrindSize, RoastLevel, ShotAnnotation};
+use crate::storage::{self, from_hex, to_hex, Batch, Storage, Table, TimeKey};
+use serde::{Deserialize, Serialize};
+use std::ops::Bound;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Bumped whenever the indexes change, to rebuild them from the shots
+const INDEX_VERSION: u32 = 3;
+const IDS: &str = "shot_ids";
+const SCORES: &str = "shot_scores";
+const RECIPES: &str = "shot_recipes";
+const USERS: &str = "shot_users";
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+// Shots are stored a moment after they are timestamped, so the key range
+// for a time window reaches a little past its end
+const STORE_SLACK_SECONDS: u64 = 60;
+
+// Changes to a shot's annotation; fields left out keep their value, and
+// null clears the notes or the rating
+#[derive(Serialize, Debug, Deserialize, Clone, Default)]
+pub struct AnnotationPatch {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_with::rust::double_option"
+    )]
+    pub notes: Option<Option<String>>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_with::rust::double_option"
+    )]
+    pub rating: Option<Option<u8>>,
+}
+
+impl AnnotationPatch {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(Some(rating)) = self.rating {
+            if rating > 10 {
+                return Err("Rating must be between 0 and 10".to_string());
+            }
+        }
+        if let Some(tags) = &self.tags {
+            if tags.iter().any(|tag| tag.trim().is_empty()) {
+                return Err("Tags must not be empty".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, annotation: ShotAnnotation) -> ShotAnnotation {
+        ShotAnnotation {
+            notes: match &self.notes {
+                Some(notes) => notes.clone().unwrap_or_default(),
+                None => annotation.notes,
+            },
+            tags: self
+                .tags
+                .as_ref()
+                .map(|tags| tags.iter().map(|tag| tag.trim().to_string()).collect())
+                .unwrap_or(annotation.tags),
+            rating: self.rating.unwrap_or(annotation.rating),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ShotSort {
+    #[default]
+    Timestamp,
+    QualityScore,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// Which stored shots to list, and in what order
+#[derive(Serialize, Debug, Deserialize, Clone, Default)]
+pub struct ShotQuery {
+    // Unix seconds, both ends included
+    #[serde(default)]
+    pub from: Option<u64>,
+    #[serde(default)]
+    pub to: Option<u64>,
+    #[serde(default)]
+    pub coffee_type: Option<CoffeeType>,
+    #[serde(default)]
+    pub roast_level: Option<RoastLevel>,
+    #[serde(default)]
+    pub grind_size: Option<GrindSize>,
+    // Such as `Perfect Extraction`, in any case
+    #[serde(default)]
+    pub result: Option<String>,
+    #[serde(default)]
+    pub min_score: Option<u8>,
+    #[serde(default)]
+    pub max_score: Option<u8>,
+    #[serde(default)]
+    pub recipe_id: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub sort: ShotSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    // `next_cursor` of the previous page
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl ShotQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err("`from` must not be after `to`".to_string());
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_score, self.max_score) {
+            if min > max {
+                return Err("`min_score` must not be above `max_score`".to_string());
+            }
+        }
+        if let Some(limit) = self.limit {
+            if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+                return Err(format!("Limit must be between 1 and {}", MAX_PAGE_SIZE));
+            }
+        }
+        if let Some(cursor) = &self.cursor {
+            let length = match self.sort {
+                ShotSort::Timestamp => TimeKey::LEN,
+                ShotSort::QualityScore => TimeKey::LEN + 1,
+            };
+            if from_hex(cursor).is_none_or(|cursor| cursor.len() != length) {
+                return Err("Cursor does not belong to this sort".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, shot: &ExtractionMetrics) -> bool {
+        self.from.is_none_or(|from| shot.timestamp >= from)
+            && self.to.is_none_or(|to| shot.timestamp <= to)
+            && self.coffee_type.is_none_or(|c| c == shot.coffee_type)
+            && self.roast_level.is_none_or(|r| r == shot.roast_level)
+            && self.grind_size.is_none_or(|g| g == shot.grind_size)
+            && self
+                .result
+                .as_ref()
+                .is_none_or(|result| result.eq_ignore_ascii_case(&shot.result))
+            && self.min_score.is_none_or(|min| shot.quality_score >= min)
+            && self.max_score.is_none_or(|max| shot.quality_score <= max)
+            && self
+                .recipe_id
+                .as_ref()
+                .is_none_or(|id| shot.recipe_id.as_ref() == Some(id))
+            && self
+                .user
+                .as_ref()
+                .is_none_or(|user| shot.user.as_ref() == Some(user))
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct ShotPage {
+    pub shots: Vec<ExtractionMetrics>,
+    // Passed back as `cursor` for the next page; missing on the last one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+// Score index entries are the score, then the shot's key
+fn score_key(score: u8, key: &[u8]) -> Vec<u8> {
+    [&[score], key].concat()
+}
+
+// Recipe and user index entries are `{value}/`, then the shot's key, so
+// each value's shots sit together in time order
+fn index_prefix(value: &str) -> Vec<u8> {
+    [value.as_bytes(), b"/"].concat()
+}
+
+fn index_key(value: &str, key: &[u8]) -> Vec<u8> {
+    [&index_prefix(value), key].concat()
+}
+
+// Shots are kept in time order, where the analytics read them. Separate
+// tables map each shot's id to its key, order the keys by score, and
+// group them by recipe and by user
+pub struct ShotRepository {
+    db: Arc<dyn Storage>,
+    shots: Arc<dyn Table>,
+    ids: Arc<dyn Table>,
+    scores: Arc<dyn Table>,
+    recipes: Arc<dyn Table>,
+    users: Arc<dyn Table>,
+    meta: Arc<dyn Table>,
+}
+
+impl ShotRepository {
+    pub fn new(db: Arc<dyn Storage>) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            shots: db.table(storage::SHOTS)?,
+            ids: db.table(IDS)?,
+            scores: db.table(SCORES)?,
+            recipes: db.table(RECIPES)?,
+            users: db.table(USERS)?,
+            meta: db.table("shot_meta")?,
+            db,
+        })
+    }
+
+    // Writes a shot and its index entries. Every write goes through a
+    // batch, so the indexes never disagree with the shots
+    fn store(
+        batch: &mut Batch,
+        key: &[u8],
+        metrics: &ExtractionMetrics,
+    ) -> Result<(), RepositoryError> {
+        batch.insert(storage::SHOTS, key, &serde_json::to_vec(metrics)?);
+        batch.insert(IDS, metrics.id.as_bytes(), key);
+        batch.insert(SCORES, &score_key(metrics.quality_score, key), &[]);
+        if let Some(recipe_id) = &metrics.recipe_id {
+            batch.insert(RECIPES, &index_key(recipe_id, key), &[]);
+        }
+        if let Some(user) = &metrics.user {
+            batch.insert(USERS, &index_key(user, key), &[]);
+        }
+        Ok(())
+    }
+
+    fn unstore(batch: &mut Batch, key: &[u8], metrics: &ExtractionMetrics) {
+        batch.remove(storage::SHOTS, key);
+        batch.remove(IDS, metrics.id.as_bytes());
+        batch.remove(SCORES, &score_key(metrics.quality_score, key));
+        if let Some(recipe_id) = &metrics.recipe_id {
+            batch.remove(RECIPES, &index_key(recipe_id, key));
+        }
+        if let Some(user) = &metrics.user {
+            batch.remove(USERS, &index_key(user, key));
+        }
+    }
+
+    // Stores a new shot under a fresh id, which the returned shot carries
+    pub fn create(&self, metrics: ExtractionMetrics) -> Result<ExtractionMetrics, RepositoryError> {
+        let metrics = ExtractionMetrics {
+            id: Uuid::new_v4().to_string(),
+            ..metrics
+        };
+        let key = TimeKey::now(self.db.as_ref())?.to_bytes();
+        let mut batch = Batch::default();
+        Self::store(&mut batch, &key, &metrics)?;
+        self.db.apply(&batch)?;
+        Ok(metrics)
+    }
+
+    // The shot's key, its stored bytes and the shot they hold
+    fn read(&self, id: &str) -> Result<(Vec<u8>, Vec<u8>, ExtractionMetrics), RepositoryError> {
+        let key = self
+            .ids
+            .get(id.as_bytes())?
+            .ok_or(RepositoryError::NotFound)?;
+        let stored = self.shots.get(&key)?.ok_or(RepositoryError::NotFound)?;
+        let metrics = serde_json::from_slice(&stored)?;
+        Ok((key, stored, metrics))
+    }
+
+    pub fn get(&self, id: &str) -> Result<ExtractionMetrics, RepositoryError> {
+        Ok(self.read(id)?.2)
+    }
+
+    // Writes only over the shot it read, reading again when another
+    // change got there first, so concurrent patches never undo each other
+    pub fn annotate(
+        &self,
+        id: &str,
+        patch: AnnotationPatch,
+    ) -> Result<ExtractionMetrics, RepositoryError> {
+        loop {
+            let (key, stored, metrics) = self.read(id)?;
+            let metrics = ExtractionMetrics {
+                annotation: patch.apply(metrics.annotation.clone()),
+                ..metrics
+            };
+            let mut batch = Batch::default();
+            batch.check(storage::SHOTS, &key, Some(&stored));
+            batch.insert(storage::SHOTS, &key, &serde_json::to_vec(&metrics)?);
+            if self.db.apply(&batch)? {
+                return Ok(metrics);
+            }
+        }
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), RepositoryError> {
+        loop {
+            let (key, stored, metrics) = self.read(id)?;
+            let mut batch = Batch::default();
+            batch.check(storage::SHOTS, &key, Some(&stored));
+            batch.check(IDS, id.as_bytes(), Some(&key));
+            Self::unstore(&mut batch, &key, &metrics);
+            if self.db.apply(&batch)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // Rebuilds the indexes when they were built by an older version. Shots
+    // from before ids existed get one. Returns how many shots were indexed
+    pub fn index_stored_shots(&self) -> Result<usize, RepositoryError> {
+        if let Some(version) = self.meta.get(b"index_version")? {
+            if version.as_slice() >= INDEX_VERSION.to_be_bytes().as_slice() {
+                return Ok(0);
+            }
+        }
+        self.ids.clear()?;
+        self.scores.clear()?;
+        self.recipes.clear()?;
+        self.users.clear()?;
+        let mut indexed = 0;
+        for entry in self.shots.iter() {
+            let (key, value) = entry?;
+            let mut metrics: ExtractionMetrics = serde_json::from_slice(&value)?;
+            if metrics.id.is_empty() {
+                metrics.id = Uuid::new_v4().to_string();
+            }
+            let mut batch = Batch::default();
+            Self::store(&mut batch, &key, &metrics)?;
+            self.db.apply(&batch)?;
+            indexed += 1;
+        }
+        self.meta
+            .insert(b"index_version", &INDEX_VERSION.to_be_bytes())?;
+        Ok(indexed)
+    }
+
+    // One page of the shots matching `query`. Each sort walks its own key
+    // range, so a page only reads as far as it needs to fill up. A time
+    // sort by recipe or user walks that value's index entries alone; the
+    // other filters are checked on each shot read
+    pub fn query(&self, query: &ShotQuery) -> Result<ShotPage, RepositoryError> {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        // Index entries start with `prefix`; the rest is the shot's key, or
+        // for scores the score and then the key. Cursors leave the prefix out
+        let (tree, prefix, mut lower, mut upper) = match query.sort {
+            ShotSort::Timestamp => {
+                let (tree, prefix) = match (&query.recipe_id, &query.user) {
+                    (Some(recipe_id), _) => (&self.recipes, index_prefix(recipe_id)),
+                    (None, Some(user)) => (&self.users, index_prefix(user)),
+                    (None, None) => (&self.shots, Vec::new()),
+                };
+                let lower = match query.from {
+                    Some(from) => TimeKey::at(from * 1000).to_bytes().to_vec(),
+                    None => Vec::new(),
+                };
+                let upper = match query.to {
+                    Some(to) => {
+                        let end = TimeKey::at((to + 1 + STORE_SLACK_SECONDS) * 1000);
+                        Bound::Excluded([&prefix[..], &end.to_bytes()].concat())
+                    }
+                    None if prefix.is_empty() => Bound::Unbounded,
+                    None => storage::prefix_end(&prefix),
+                };
+                let lower = Bound::Included([&prefix[..], &lower].concat());
+                (tree, prefix, lower, upper)
+            }
+            ShotSort::QualityScore => (
+                &self.scores,
+                Vec::new(),
+                Bound::Included(vec![query.min_score.unwrap_or(0)]),
+                Bound::Included(score_key(
+                    query.max_score.unwrap_or(u8::MAX),
+                    &[u8::MAX; TimeKey::LEN],
+                )),
+            ),
+        };
+        // Picks up right after the previous page's last shot
+        if let Some(cursor) = query.cursor.as_deref().and_then(from_hex) {
+            let cursor = Bound::Excluded([&prefix[..], &cursor].concat());
+            match query.order {
+                SortOrder::Asc => lower = cursor,
+                SortOrder::Desc => upper = cursor,
+            }
+        }
+
+        let entries = tree.range(lower, upper, query.order == SortOrder::Desc);
+        let mut shots = Vec::new();
+        for entry in entries {
+            let (position, value) = entry?;
+            let position = &position[prefix.len()..];
+            let value = match query.sort {
+                ShotSort::Timestamp if prefix.is_empty() => value,
+                // Entries of a longer value that shares the prefix, such as
+                // user `ana/b` under `ana/`, are not this value's
+                ShotSort::Timestamp if position.len() != TimeKey::LEN => continue,
+                // Index entries point at the shot's key
+                ShotSort::Timestamp => match self.shots.get(position)? {
+                    Some(value) => value,
+                    None => continue,
+                },
+                ShotSort::QualityScore => match self.shots.get(&position[1..])? {
+                    Some(value) => value,
+                    None => continue,
+                },
+            };
+            let shot: ExtractionMetrics = serde_json::from_slice(&value)?;
+            if !query.matches(&shot) {
+                continue;
+            }
+            shots.push(shot);
+            if shots.len() == limit {
+                return Ok(ShotPage {
+                    shots,
+                    next_cursor: Some(to_hex(position)),
+                });
+            }
+        }
+        Ok(ShotPage {
+            shots,
+            next_cursor: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_shot_ids_and_annotations() {
+        let db: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        // A shot stored before ids existed
+        db.table(storage::SHOTS)
+            .unwrap()
+            .insert(
+                &TimeKey::at(1_700_000_000_000).to_bytes(),
+                &serde_json::to_vec(&ExtractionMetrics::default()).unwrap(),
+            )
+            .unwrap();
+        let shots = ShotRepository::new(db.clone()).unwrap();
+        assert_eq!(shots.index_stored_shots().unwrap(), 1);
+        assert_eq!(shots.index_stored_shots().unwrap(), 0);
+
+        // Shots in the same millisecond still get keys of their own
+        let first = shots.create(ExtractionMetrics::default()).unwrap();
+        let second = shots.create(ExtractionMetrics::default()).unwrap();
+        assert_ne!(first.id, second.id);
+        let stored = shots.query(&ShotQuery::default()).unwrap().shots;
+        assert_eq!(stored.len(), 3);
+        assert!(stored.iter().all(|shot| !shot.id.is_empty()));
+        // The old shot keeps its place, ahead of the new ones
+        assert!(stored[0].id != first.id && stored[0].id != second.id);
+
+        let patch = AnnotationPatch {
+            notes: Some(Some("Syrupy".to_string())),
+            tags: Some(vec![" keeper ".to_string()]),
+            rating: Some(Some(8)),
+        };
+        shots.annotate(&first.id, patch).unwrap();
+        // Fields left out keep their value
+        let patch = AnnotationPatch {
+            rating: Some(Some(9)),
+            ..Default::default()
+        };
+        let annotated = shots.annotate(&first.id, patch).unwrap();
+        assert_eq!(annotated.annotation.notes, "Syrupy");
+        assert_eq!(annotated.annotation.tags, ["keeper"]);
+        assert_eq!(shots.get(&first.id).unwrap().annotation.rating, Some(9));
+        // Null clears a field, where leaving it out keeps it
+        let clear: AnnotationPatch = serde_json::from_str(r#"{"rating": null}"#).unwrap();
+        let cleared = shots.annotate(&first.id, clear).unwrap();
+        assert_eq!(cleared.annotation.rating, None);
+        assert_eq!(cleared.annotation.notes, "Syrupy");
+        let clear: AnnotationPatch = serde_json::from_str(r#"{"notes": null}"#).unwrap();
+        assert!(shots
+            .annotate(&first.id, clear)
+            .unwrap()
+            .annotation
+            .notes
+            .is_empty());
+        let too_high = AnnotationPatch {
+            rating: Some(Some(11)),
+            ..Default::default()
+        };
+        assert!(too_high.validate().is_err());
+
+        shots.delete(&first.id).unwrap();
+        assert!(matches!(
+            shots.get(&first.id),
+            Err(RepositoryError::NotFound)
+        ));
+        assert!(shots.get(&second.id).is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_annotations_keep_every_change() {
+        let shots = Arc::new(ShotRepository::new(Arc::new(MemoryStorage::default())).unwrap());
+        let id = shots.create(ExtractionMetrics::default()).unwrap().id;
+        // One writer edits the notes while another rates; neither may write
+        // back the other's field as it read it
+        let writers: Vec<_> = [true, false]
+            .into_iter()
+            .map(|notes| {
+                let (shots, id) = (shots.clone(), id.clone());
+                std::thread::spawn(move || {
+                    for i in 0..200u8 {
+                        let patch = if notes {
+                            AnnotationPatch {
+                                notes: Some(Some(format!("pass {}", i))),
+                                ..Default::default()
+                            }
+                        } else {
+                            AnnotationPatch {
+                                rating: Some(Some(i % 11)),
+                                ..Default::default()
+                            }
+                        };
+                        shots.annotate(&id, patch).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let annotation = shots.get(&id).unwrap().annotation;
+        assert_eq!(annotation.notes, "pass 199");
+        assert_eq!(annotation.rating, Some(199 % 11));
+
+        shots.delete(&id).unwrap();
+        assert!(matches!(shots.delete(&id), Err(RepositoryError::NotFound)));
+    }
+
+    #[test]
+    fn test_shot_queries_page_through_matches() {
+        let shots = ShotRepository::new(Arc::new(MemoryStorage::default())).unwrap();
+        for i in 0..10u8 {
+            shots
+                .create(ExtractionMetrics {
+                    timestamp: 1_000 + i as u64,
+                    quality_score: 50 + i * 5,
+                    user: Some(if i % 2 == 0 { "ana" } else { "bo" }.to_string()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let page = |query: &ShotQuery| shots.query(query).unwrap();
+
+        // Walking the pages visits every match once, in order
+        let mut query = ShotQuery {
+            user: Some("ana".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        loop {
+            let result = page(&query);
+            seen.extend(result.shots.iter().map(|shot| shot.quality_score));
+            match result.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seen, [50, 60, 70, 80, 90]);
+
+        // Recipe and user filters walk their own index, which must keep a
+        // value apart from longer ones sharing its prefix
+        let recipe_id = "5f0c2a4e-8d61-4b3a-9c57-1e2f3a4b5c6d".to_string();
+        for user in ["ana/b", "ana"] {
+            shots
+                .create(ExtractionMetrics {
+                    timestamp: 1_100,
+                    quality_score: 40,
+                    user: Some(user.to_string()),
+                    recipe_id: Some(recipe_id.clone()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let newest = page(&ShotQuery {
+            user: Some("ana".to_string()),
+            order: SortOrder::Desc,
+            limit: Some(2),
+            ..Default::default()
+        });
+        let scores: Vec<u8> = newest.shots.iter().map(|shot| shot.quality_score).collect();
+        assert_eq!(scores, [40, 90]);
+        let by_recipe = page(&ShotQuery {
+            recipe_id: Some(recipe_id.clone()),
+            from: Some(1_050),
+            ..Default::default()
+        });
+        assert_eq!(by_recipe.shots.len(), 2);
+        let one_user = page(&ShotQuery {
+            recipe_id: Some(recipe_id),
+            user: Some("ana/b".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(one_user.shots.len(), 1);
+        shots.delete(&one_user.shots[0].id).unwrap();
+        let gone = page(&ShotQuery {
+            user: Some("ana/b".to_string()),
+            ..Default::default()
+        });
+        assert!(gone.shots.is_empty());
+
+        let best = page(&ShotQuery {
+            sort: ShotSort::QualityScore,
+            order: SortOrder::Desc,
+            min_score: Some(60),
+            max_score: Some(80),
+            limit: Some(2),
+            ..Default::default()
+        });
+        let scores: Vec<u8> = best.shots.iter().map(|shot| shot.quality_score).collect();
+        assert_eq!(scores, [80, 75]);
+        let rest = page(&ShotQuery {
+            sort: ShotSort::QualityScore,
+            order: SortOrder::Desc,
+            min_score: Some(60),
+            max_score: Some(80),
+            cursor: best.next_cursor,
+            ..Default::default()
+        });
+        let scores: Vec<u8> = rest.shots.iter().map(|shot| shot.quality_score).collect();
+        assert_eq!(scores, [70, 65, 60]);
+
+        // Nothing matching is an empty page, not an error
+        let empty = page(&ShotQuery {
+            from: Some(2_000),
+            ..Default::default()
+        });
+        assert!(empty.shots.is_empty() && empty.next_cursor.is_none());
+
+        let mismatched = ShotQuery {
+            sort: ShotSort::QualityScore,
+            cursor: Some(to_hex(&TimeKey::at(1_000).to_bytes())),
+            ..Default::default()
+        };
+        assert!(mismatched.validate().is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::simulation::engine::ModelCoefficients;
+use crate::simulation::flavor::{balance_window, TasteBalance};
+use crate::simulation::optimizer::nelder_mead;
+use crate::simulation::{
+    simulate, CoffeeType, ExtractionMetrics, GrindSize, RoastLevel, ShotConfig, ML_PER_OZ,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+pub const MAX_SHOTS: usize = 200;
+// Range each coefficient is fitted within, as (min, max)
+const EXTRACTION_RATE: (f64, f64) = (0.2, 1.2);
+const EXTRACTION_RATE_PER_DEGREE: (f64, f64) = (0.0, 0.1);
+const RESISTANCE_FACTOR: (f64, f64) = (0.4, 2.5);
+// EY points an over- or under-extracted shot should land past the balanced
+// window, so a shot right on its edge doesn't count as a fit
+const LABEL_MARGIN: f64 = 0.5;
+// EY points of error per 100% the yield or water volume is off by
+const YIELD_WEIGHT: f64 = 10.0;
+// Pull towards the textbook coefficients, so a handful of shots can't drag
+// the ones they say nothing about to the ends of their ranges
+const REGULARIZATION: f64 = 0.5;
+
+// How a shot tasted, in the words of the shot logs
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShotLabel {
+    Balanced,
+    OverExtracted,
+    UnderExtracted,
+}
+
+impl ShotLabel {
+    pub fn balance(&self) -> TasteBalance {
+        match self {
+            ShotLabel::Balanced => TasteBalance::Balanced,
+            ShotLabel::OverExtracted => TasteBalance::Bitter,
+            ShotLabel::UnderExtracted => TasteBalance::Sour,
+        }
+    }
+}
+
+// A shot pulled on the real machine; anything not logged is taken to be the
+// simulator's default
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct LoggedShot {
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    pub temperature: f64,
+    pub pressure: f64,
+    pub time_seconds: u64,
+    #[serde(default)]
+    pub dose_g: Option<f64>,
+    #[serde(default)]
+    pub grind_microns: Option<f64>,
+    // Beverage weight and water dispensed, as the simulator reports them; the
+    // only things that tell the fit how fast the puck lets water through
+    #[serde(default)]
+    pub yield_g: Option<f64>,
+    #[serde(default)]
+    pub water_volume_oz: Option<f64>,
+    #[serde(default)]
+    pub coffee_type: Option<CoffeeType>,
+    #[serde(default)]
+    pub roast_level: Option<RoastLevel>,
+    pub result: ShotLabel,
+}
+
+impl LoggedShot {
+    pub fn validate(&self) -> Result<(), String> {
+        let dose_g = self.dose_g.unwrap_or(ShotConfig::default().dose_g);
+        let checks = [
+            ("Temperature", self.temperature, 70.0, 100.0),
+            ("Pressure", self.pressure, 1.0, 15.0),
+            ("Time", self.time_seconds as f64, 5.0, 120.0),
+            ("Dose", dose_g, 6.0, 25.0),
+            (
+                "Grind",
+                self.grind_microns
+                    .unwrap_or_else(|| GrindSize::default().microns()),
+                150.0,
+                1200.0,
+            ),
+            (
+                "Brew ratio",
+                self.yield_g.map_or(2.0, |y| y / dose_g),
+                1.0,
+                4.0,
+            ),
+            (
+                "Water to dose ratio",
+                self.water_volume_oz
+                    .map_or(2.5, |oz| oz * ML_PER_OZ / dose_g),
+                1.0,
+                6.0,
+            ),
+        ];
+        for (name, value, min, max) in checks {
+            if !(min..=max).contains(&value) {
+                return Err(format!(
+                    "{} must be between {} and {}, got {}",
+                    name, min, max, value
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the shot says how fast the puck let water through
+    fn logs_flow(&self) -> bool {
+        self.yield_g.is_some() || self.water_volume_oz.is_some()
+    }
+
+    fn shot_config(&self, coefficients: ModelCoefficients) -> ShotConfig {
+        let defaults = ShotConfig::default();
+        ShotConfig {
+            temperature: self.temperature,
+            pressure: self.pressure,
+            time_seconds: self.time_seconds,
+            dose_g: self.dose_g.unwrap_or(defaults.dose_g),
+            grind_microns: self.grind_microns,
+            coffee_type: self.coffee_type.unwrap_or_default(),
+            roast_level: self.roast_level.unwrap_or_default(),
+            coefficients,
+            ..defaults
+        }
+    }
+
+    // How far the simulated shot is from what was logged, in EY points
+    fn miss(&self, metrics: &ExtractionMetrics) -> f64 {
+        let (sour_below, bitter_above) = balance_window(
+            &metrics.ideal_ranges(),
+            metrics.roast_level,
+            metrics.coffee_type,
+        );
+        let ey = metrics.extraction_yield_percent;
+        let taste = match self.result {
+            ShotLabel::Balanced => (sour_below - ey).max(ey - bitter_above).max(0.0),
+            ShotLabel::OverExtracted => (bitter_above + LABEL_MARGIN - ey).max(0.0),
+            ShotLabel::UnderExtracted => (ey - sour_below + LABEL_MARGIN).max(0.0),
+        };
+        let flow = self.yield_g.map_or(0.0, |yield_g| {
+            (metrics.beverage_weight_g - yield_g) / yield_g * YIELD_WEIGHT
+        });
+        let water = self
+            .water_volume_oz
+            .map_or(0.0, |oz| (metrics.water_volume_oz - oz) / oz * YIELD_WEIGHT);
+        (taste.powi(2) + flow.powi(2) + water.powi(2)).sqrt()
+    }
+}
+
+// Shot logs as the machine exports them
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct ShotLog {
+    pub extractions: Vec<LoggedShot>,
+}
+
+// What the simulator makes of one logged shot
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct ShotPrediction {
+    pub timestamp: Option<String>,
+    pub result: ShotLabel,
+    pub predicted: TasteBalance,
+    pub extraction_yield_percent: f64,
+    pub beverage_weight_g: f64,
+    pub yield_g: Option<f64>,
+    pub water_dispensed_oz: f64,
+    pub water_volume_oz: Option<f64>,
+    pub error: f64,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct FitError {
+    // Root mean square of how far each shot misses, in EY points
+    pub rms_error: f64,
+    // Share of shots the simulator tastes the way they were labeled
+    pub accuracy: f64,
+}
+
+// A named set of coefficients fitted to one machine's shots
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Calibration {
+    pub name: String,
+    pub coefficients: ModelCoefficients,
+    pub shots: usize,
+    // The textbook coefficients against the same shots, for comparison
+    pub default_fit: FitError,
+    pub fit: FitError,
+    pub predictions: Vec<ShotPrediction>,
+    pub evaluations: usize,
+    pub fitted_at: u64,
+    // What the fit had to leave out, such as the puck's resistance when no
+    // shot logged how much it poured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+fn scale((min, max): (f64, f64), share: f64) -> f64 {
+    min + (max - min) * share.clamp(0.0, 1.0)
+}
+
+fn share((min, max): (f64, f64), value: f64) -> f64 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+fn coefficients(point: &[f64]) -> ModelCoefficients {
+    ModelCoefficients {
+        extraction_rate: scale(EXTRACTION_RATE, point[0]),
+        extraction_rate_per_degree: scale(EXTRACTION_RATE_PER_DEGREE, point[1]),
+        resistance_factor: scale(RESISTANCE_FACTOR, point[2]),
+    }
+}
+
+fn point(coefficients: &ModelCoefficients) -> Vec<f64> {
+    vec![
+        share(EXTRACTION_RATE, coefficients.extraction_rate),
+        share(
+            EXTRACTION_RATE_PER_DEGREE,
+            coefficients.extraction_rate_per_degree,
+        ),
+        share(RESISTANCE_FACTOR, coefficients.resistance_factor),
+    ]
+}
+
+fn predict(shots: &[LoggedShot], coefficients: ModelCoefficients) -> Vec<ShotPrediction> {
+    shots
+        .iter()
+        .map(|shot| {
+            let metrics = simulate(&shot.shot_config(coefficients));
+            ShotPrediction {
+                timestamp: shot.timestamp.clone(),
+                result: shot.result,
+                predicted: metrics.flavor.balance,
+                extraction_yield_percent: metrics.extraction_yield_percent,
+                beverage_weight_g: metrics.beverage_weight_g,
+                yield_g: shot.yield_g,
+                water_dispensed_oz: metrics.water_volume_oz,
+                water_volume_oz: shot.water_volume_oz,
+                error: shot.miss(&metrics),
+            }
+        })
+        .collect()
+}
+
+fn fit_error(predictions: &[ShotPrediction]) -> FitError {
+    let shots = predictions.len() as f64;
+    FitError {
+        rms_error: (predictions.iter().map(|p| p.error.powi(2)).sum::<f64>() / shots).sqrt(),
+        accuracy: predictions
+            .iter()
+            .filter(|p| p.predicted == p.result.balance())
+            .count() as f64
+            / shots,
+    }
+}
+
+// Fits the coefficients to `log` by Nelder-Mead, started from the textbook
+// values, so the simulator tastes each shot the way it was labeled and pours
+// the yield and water that were logged
+pub fn calibrate(name: &str, log: &ShotLog) -> Result<Calibration, String> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err("Calibration name must be between 1 and 64 characters".to_string());
+    }
+    if !(1..=MAX_SHOTS).contains(&log.extractions.len()) {
+        return Err(format!(
+            "A calibration takes between 1 and {} shots",
+            MAX_SHOTS
+        ));
+    }
+    for (i, shot) in log.extractions.iter().enumerate() {
+        shot.validate()
+            .map_err(|message| format!("Shot {}: {}", i + 1, message))?;
+    }
+
+    let shots = &log.extractions;
+    let start = point(&ModelCoefficients::default());
+    // Only a logged yield or water volume says how fast the puck runs, so
+    // without one the resistance is left out of the search at its textbook
+    // value rather than drifting wherever the labels push it
+    let mut warnings = Vec::new();
+    let searched = if shots.iter().any(LoggedShot::logs_flow) {
+        start.len()
+    } else {
+        warnings.push(
+            "No shot logs its yield_g or water_volume_oz, so resistance_factor keeps its textbook value"
+                .to_string(),
+        );
+        start.len() - 1
+    };
+    let full = |point: &[f64]| [point, &start[point.len()..]].concat();
+    let mut evaluations = 0;
+    let mut value = |point: &[f64]| {
+        evaluations += 1;
+        let fit = fit_error(&predict(shots, coefficients(&full(point))));
+        let drift = point
+            .iter()
+            .zip(&start)
+            .map(|(x, s)| (x - s).powi(2))
+            .sum::<f64>();
+        -(fit.rms_error.powi(2) + REGULARIZATION * drift)
+    };
+    let (best, _) = nelder_mead(&mut value, start[..searched].to_vec());
+
+    let fitted = coefficients(&full(&best));
+    let default_fit = fit_error(&predict(shots, ModelCoefficients::default()));
+    let predictions = predict(shots, fitted);
+    let fit = fit_error(&predictions);
+    debug!(
+        name = name,
+        shots = shots.len(),
+        evaluations = evaluations,
+        default_error = default_fit.rms_error,
+        error = fit.rms_error,
+        "Calibrated model coefficients"
+    );
+    Ok(Calibration {
+        name: name.to_string(),
+        coefficients: fitted,
+        shots: shots.len(),
+        default_fit,
+        fit,
+        predictions,
+        evaluations,
+        fitted_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        warnings,
+    })
+}
@@ -0,0 +1,39 @@
+// Roast age the model is calibrated against, used when a shot names no bean
+pub const REFERENCE_DAYS_OFF_ROAST: f64 = 10.0;
+// Days for the CO2 trapped in the beans to fall by a factor of e
+const DEGASSING_DAYS: f64 = 14.0;
+// Extra resistance of a puck still full of CO2
+const CO2_RESISTANCE: f64 = 0.5;
+// Share of the grounds CO2 bubbles keep away from the water
+const CO2_CONTACT_LOSS: f64 = 0.15;
+
+// Share of the roast's CO2 still in the beans
+pub fn co2_remaining(days_off_roast: f64) -> f64 {
+    (-days_off_roast.max(0.0) / DEGASSING_DAYS).exp()
+}
+
+// Gas escaping the bed pushes back against the water, so fresh beans run
+// slower and stale ones faster than the reference
+pub fn resistance_factor(days_off_roast: f64) -> f64 {
+    (1.0 + CO2_RESISTANCE * co2_remaining(days_off_roast))
+        / (1.0 + CO2_RESISTANCE * co2_remaining(REFERENCE_DAYS_OFF_ROAST))
+}
+
+// Bubbles clinging to the grounds keep water off part of their surface
+pub fn extraction_factor(days_off_roast: f64) -> f64 {
+    (1.0 - CO2_CONTACT_LOSS * co2_remaining(days_off_roast))
+        / (1.0 - CO2_CONTACT_LOSS * co2_remaining(REFERENCE_DAYS_OFF_ROAST))
+}
+
+// Gas pockets open channels as they burst through the puck
+pub fn channeling_factor(days_off_roast: f64) -> f64 {
+    (1.0 + co2_remaining(days_off_roast)) / (1.0 + co2_remaining(REFERENCE_DAYS_OFF_ROAST))
+}
+
+// Crema on a 0-10 scale: CO2 forced into the coffee under pressure foams up
+// in the cup, so stale beans and low pressure both leave it thin
+pub fn crema(days_off_roast: f64, peak_pressure: f64) -> f64 {
+    let foam = 1.0 - (-4.0 * co2_remaining(days_off_roast)).exp();
+    let emulsion = (peak_pressure / 9.0).clamp(0.0, 1.0);
+    ((10.0 * foam * emulsion) * 10.0).round() / 10.0
+}
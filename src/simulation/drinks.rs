@@ -0,0 +1,196 @@
+use crate::simulation::methods::window_score;
+use crate::simulation::milk::{SteamedMilk, MILK_HEAT_CAPACITY};
+use crate::simulation::ExtractionMetrics;
+use serde::{Deserialize, Serialize};
+
+const COFFEE_HEAT_CAPACITY: f64 = 4.18;
+// Degrees the coffee loses to the cup if the shot carries no cup temperature
+const CUP_DROP: f64 = 10.0;
+// Temperatures a milk drink is pleasant to drink at straight away
+const SERVE_MIN: f64 = 55.0;
+const SERVE_MAX: f64 = 65.0;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum DrinkKind {
+    Cortado,
+    FlatWhite,
+    Cappuccino,
+    Latte,
+}
+
+// What a drink should look like: its cup, how much milk it gets for each
+// gram of espresso and how far the foam lifts the milk
+struct DrinkStyle {
+    cup_ml: f64,
+    milk_ratio: (f64, f64),
+    expansion_percent: (f64, f64),
+}
+
+impl DrinkKind {
+    fn style(&self) -> DrinkStyle {
+        let (cup_ml, milk_ratio, expansion_percent) = match self {
+            DrinkKind::Cortado => (120.0, (1.0, 2.0), (5.0, 15.0)),
+            DrinkKind::FlatWhite => (180.0, (2.5, 4.5), (8.0, 20.0)),
+            DrinkKind::Cappuccino => (220.0, (2.0, 4.0), (35.0, 70.0)),
+            DrinkKind::Latte => (300.0, (4.5, 8.0), (15.0, 30.0)),
+        };
+        DrinkStyle {
+            cup_ml,
+            milk_ratio,
+            expansion_percent,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Drink {
+    pub kind: DrinkKind,
+    pub shots: usize,
+    pub espresso_g: f64,
+    // Mean quality score of the shots that went in
+    pub espresso_quality: f64,
+    pub milk: SteamedMilk,
+    // Grams of milk for each gram of espresso
+    pub milk_ratio: f64,
+    pub volume_ml: f64,
+    // Coffee solids in the whole drink
+    pub tds_percent: f64,
+    pub temperature: f64,
+    pub quality_score: u8,
+    pub recommendations: Vec<String>,
+}
+
+impl Drink {
+    pub fn build(kind: DrinkKind, shots: &[ExtractionMetrics], milk: SteamedMilk) -> Self {
+        let style = kind.style();
+        let espresso_g: f64 = shots.iter().map(|s| s.beverage_weight_g).sum();
+        let solubles: f64 = shots
+            .iter()
+            .map(|s| s.beverage_weight_g * s.tds_percent / 100.0)
+            .sum();
+        let espresso_quality = if shots.is_empty() {
+            0.0
+        } else {
+            shots.iter().map(|s| s.quality_score as f64).sum::<f64>() / shots.len() as f64
+        };
+
+        // Shots and milk settle at a common temperature in the cup
+        let espresso_heat: f64 = shots
+            .iter()
+            .map(|s| {
+                let cup = if s.cup_temperature > 0.0 {
+                    s.cup_temperature
+                } else {
+                    s.temperature - CUP_DROP
+                };
+                cup * s.beverage_weight_g * COFFEE_HEAT_CAPACITY
+            })
+            .sum();
+        let temperature = (espresso_heat
+            + milk.final_temperature * milk.milk_g * MILK_HEAT_CAPACITY)
+            / (espresso_g * COFFEE_HEAT_CAPACITY + milk.milk_g * MILK_HEAT_CAPACITY);
+
+        let milk_ratio = milk.milk_g / espresso_g;
+        let volume_ml = espresso_g + milk.milk_g + milk.foam_ml;
+        let drink = Self {
+            kind,
+            shots: shots.len(),
+            espresso_g,
+            espresso_quality,
+            milk_ratio,
+            volume_ml,
+            tds_percent: solubles / (espresso_g + milk.milk_g) * 100.0,
+            temperature,
+            milk,
+            quality_score: 0,
+            recommendations: Vec::new(),
+        };
+
+        let score = drink.espresso_quality * 0.35
+            + drink.milk.microfoam_quality * 2.5
+            + window_score(
+                drink.milk.expansion_percent,
+                style.expansion_percent.0,
+                style.expansion_percent.1,
+                15.0,
+            )
+            + window_score(milk_ratio, style.milk_ratio.0, style.milk_ratio.1, 10.0)
+            + window_score(temperature, SERVE_MIN, SERVE_MAX, 15.0);
+        Self {
+            quality_score: score.round().clamp(0.0, 100.0) as u8,
+            recommendations: drink.recommend(&style),
+            ..drink
+        }
+    }
+
+    fn recommend(&self, style: &DrinkStyle) -> Vec<String> {
+        let mut recommendations = Vec::new();
+        let name = format!("{:?}", self.kind);
+
+        if self.espresso_quality < 80.0 {
+            recommendations.push(format!(
+                "Espresso scored {:.0}: dial in the shots first, milk won't hide them",
+                self.espresso_quality
+            ));
+        }
+
+        if self.milk.scalded {
+            recommendations.push(format!(
+                "Milk scalded at {:.1} degrees: stop steaming by {:.0}",
+                self.milk.final_temperature,
+                self.milk.milk_type.scald_temperature()
+            ));
+        }
+        if self.temperature < SERVE_MIN {
+            recommendations.push(format!(
+                "{} is served cool at {:.1} degrees: steam the milk hotter or warm the cup",
+                name, self.temperature
+            ));
+        } else if self.temperature > SERVE_MAX {
+            recommendations.push(format!(
+                "{} is served hot at {:.1} degrees: steam the milk cooler",
+                name, self.temperature
+            ));
+        }
+
+        if self.milk.late_aeration > 0.3 {
+            recommendations.push(
+                "Stretch the milk earlier, before it reaches body temperature, for finer bubbles"
+                    .to_string(),
+            );
+        }
+        let (foam_min, foam_max) = style.expansion_percent;
+        if self.milk.expansion_percent < foam_min {
+            recommendations.push(format!(
+                "Too little foam for a {} ({:.0}%): aerate longer, aim for {:.0}-{:.0}%",
+                name, self.milk.expansion_percent, foam_min, foam_max
+            ));
+        } else if self.milk.expansion_percent > foam_max {
+            recommendations.push(format!(
+                "Too much foam for a {} ({:.0}%): aerate for less time, aim for {:.0}-{:.0}%",
+                name, self.milk.expansion_percent, foam_min, foam_max
+            ));
+        }
+
+        let (ratio_min, ratio_max) = style.milk_ratio;
+        if self.milk_ratio < ratio_min {
+            recommendations.push(format!(
+                "Not enough milk for a {} (1:{:.1}): use more milk or fewer shots",
+                name, self.milk_ratio
+            ));
+        } else if self.milk_ratio > ratio_max {
+            recommendations.push(format!(
+                "Too much milk for a {} (1:{:.1}): use less milk or add a shot",
+                name, self.milk_ratio
+            ));
+        }
+        if self.volume_ml > style.cup_ml {
+            recommendations.push(format!(
+                "{:.0}ml won't fit a {:.0}ml {} cup: steam less milk",
+                self.volume_ml, style.cup_ml, name
+            ));
+        }
+
+        recommendations
+    }
+}
@@ -0,0 +1,219 @@
+use crate::simulation::machine::Boiler;
+use crate::simulation::profile::{
+    ExitCondition, ProfileStage, ShotProfile, StageTarget, Transition, MAX_PUMP_PRESSURE,
+};
+use crate::simulation::puck::Puck;
+use crate::simulation::trace::{ShotSample, ShotTrace};
+use crate::simulation::variance::{ShotEvent, CHANNEL_EFFICIENCY, DRIFT_SECONDS};
+use crate::simulation::{CoffeeType, RoastLevel, ShotConfig};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+// Length of one simulation step
+pub const TICK_SECONDS: f64 = 0.5;
+
+// How fast the pump can raise or bleed pressure in the group head
+const PUMP_RAMP_BAR_PER_SECOND: f64 = 3.0;
+// A dry puck lets water through this many times more easily while it soaks
+const DRY_PUCK_CONDUCTANCE_FACTOR: f64 = 9.0;
+// Share of the solubles each gram of water (per gram of coffee) dissolves at 93°C
+const EXTRACTION_RATE: f64 = 0.55;
+// Extraction speeds up with hotter water
+const EXTRACTION_RATE_PER_DEGREE: f64 = 0.04;
+// Weight-targeted shots still give up after this long
+pub const MAX_SHOT_SECONDS: u64 = 90;
+
+// The model's fitted constants; the defaults are the textbook values and a
+// calibration replaces them with ones fitted to a particular machine
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ModelCoefficients {
+    pub extraction_rate: f64,
+    pub extraction_rate_per_degree: f64,
+    // Scales the puck's resistance, so shots run faster or slower overall
+    pub resistance_factor: f64,
+}
+
+impl Default for ModelCoefficients {
+    fn default() -> Self {
+        Self {
+            extraction_rate: EXTRACTION_RATE,
+            extraction_rate_per_degree: EXTRACTION_RATE_PER_DEGREE,
+            resistance_factor: 1.0,
+        }
+    }
+}
+
+// Steps the shot tick by tick through the profile stages and records
+// what the machine would see, including whatever `events` went wrong
+pub fn run(config: &ShotConfig, profile: &ShotProfile, events: &[ShotEvent]) -> ShotTrace {
+    let mut trace = ShotTrace::new(TICK_SECONDS);
+    let time_limit = match config.target_yield_g {
+        Some(_) => MAX_SHOT_SECONDS,
+        None => config.time_seconds,
+    };
+    let max_ticks = (time_limit as f64 / TICK_SECONDS).round() as usize;
+
+    let coefficients = config.coefficients;
+    let puck = Puck::from_config(config);
+    let absorption = puck.absorption_ml();
+    let solubles = config.dose_g * extractable_fraction(config.roast_level, config.coffee_type);
+    let mut solubles_left = solubles;
+    let mut dissolved = 0.0;
+
+    let mut pressure: f64 = 0.0;
+    let mut flow: f64 = 0.0;
+    let mut absorbed = 0.0;
+    let mut cup_weight = 0.0;
+
+    let mut stage_index = 0;
+    let mut stage_started = 0.0;
+    let mut stage_from = (pressure, flow);
+
+    let ambient = config.environment.ambient_temperature;
+    let max_temperature = config.environment.max_brew_temperature();
+    let mut boiler = config
+        .machine
+        .map(|state| Boiler::new(state, config.temperature, ambient));
+
+    let drift = events.iter().find_map(|event| match *event {
+        ShotEvent::TemperatureDrift { degrees } => Some(degrees),
+        _ => None,
+    });
+    let channel = events.iter().find_map(|event| match *event {
+        ShotEvent::Channeling {
+            at_seconds,
+            severity,
+        } => Some((at_seconds, severity)),
+        _ => None,
+    });
+
+    for tick in 1..=max_ticks {
+        let now = (tick - 1) as f64 * TICK_SECONDS;
+
+        while let Some(stage) = profile.stages.get(stage_index) {
+            if !stage_exited(stage, now - stage_started, pressure, cup_weight) {
+                break;
+            }
+            debug!(stage = %stage.name, elapsed = now, "Profile stage exited");
+            stage_index += 1;
+            stage_started = now;
+            stage_from = (pressure, flow);
+        }
+        let Some(stage) = profile.stages.get(stage_index) else {
+            break;
+        };
+
+        let progress = match stage.transition {
+            Transition::Fast => 1.0,
+            Transition::Smooth(seconds) => {
+                ((now - stage_started + TICK_SECONDS) / seconds).min(1.0)
+            }
+        };
+        // Water can't reach the puck any hotter than it boils at this altitude
+        let temperature = (config.temperature
+            + boiler.as_ref().map_or(0.0, Boiler::offset)
+            + drift.map_or(0.0, |degrees| degrees * (now / DRIFT_SECONDS).min(1.0)))
+        .min(max_temperature);
+        let channeled = channel
+            .filter(|&(at_seconds, _)| now >= at_seconds)
+            .map_or(0.0, |(_, severity)| severity);
+
+        // A channel is an easy path that most of the water rushes through
+        let resistance = puck.eroded_resistance(temperature, dissolved / solubles)
+            * coefficients.resistance_factor
+            * (1.0 - channeled);
+        let conductance = puck_conductance(resistance, absorbed / absorption);
+        let wanted = match stage.target {
+            StageTarget::Pressure(bar) => lerp(stage_from.0, bar, progress),
+            StageTarget::Flow(ml) => {
+                (lerp(stage_from.1, ml, progress) / conductance).min(MAX_PUMP_PRESSURE)
+            }
+        };
+
+        let max_step = PUMP_RAMP_BAR_PER_SECOND * TICK_SECONDS;
+        pressure = (pressure + (wanted - pressure).clamp(-max_step, max_step)).max(0.0);
+        flow = pressure * conductance;
+
+        let water = flow * TICK_SECONDS;
+        if let Some(boiler) = boiler.as_mut() {
+            boiler.draw(water, TICK_SECONDS);
+        }
+        let soaked = water.min(absorption - absorbed);
+        absorbed += soaked;
+
+        // Solubles wash out with every gram of water that leaves the puck
+        let drained = water - soaked;
+        let extraction_rate = coefficients.extraction_rate
+            * puck.extraction_factor()
+            * config.water.extraction_factor()
+            * (1.0 + coefficients.extraction_rate_per_degree * (temperature - 93.0)).max(0.1)
+            * (1.0 - channeled * (1.0 - CHANNEL_EFFICIENCY));
+        let extracted = solubles_left * (1.0 - (-extraction_rate * drained / config.dose_g).exp());
+        solubles_left -= extracted;
+        dissolved += extracted;
+        if drained > 0.0 {
+            cup_weight += drained + extracted;
+        }
+
+        trace.push(ShotSample {
+            elapsed: now + TICK_SECONDS,
+            pressure,
+            flow,
+            temperature,
+            cup_weight,
+            extraction_yield: dissolved / config.dose_g * 100.0,
+            stage: stage_index,
+        });
+
+        if config
+            .target_yield_g
+            .is_some_and(|target| cup_weight >= target)
+        {
+            debug!(cup_weight = cup_weight, "Target yield reached");
+            break;
+        }
+    }
+
+    debug!(
+        profile = %profile.name,
+        samples = trace.samples.len(),
+        cup_weight = cup_weight,
+        "Simulated shot trace"
+    );
+
+    trace
+}
+
+fn stage_exited(stage: &ProfileStage, stage_elapsed: f64, pressure: f64, cup_weight: f64) -> bool {
+    stage.exit.iter().any(|condition| match *condition {
+        ExitCondition::Time(seconds) => stage_elapsed >= seconds,
+        ExitCondition::Weight(grams) => cup_weight >= grams,
+        ExitCondition::PressureAbove(bar) => pressure >= bar,
+        ExitCondition::PressureBelow(bar) => pressure <= bar,
+    })
+}
+
+// ml/s per bar; a soaking puck offers little resistance until it is saturated
+fn puck_conductance(resistance: f64, saturation: f64) -> f64 {
+    (1.0 + DRY_PUCK_CONDUCTANCE_FACTOR * (1.0 - saturation)) / resistance
+}
+
+// Share of the dry coffee that water can dissolve at all
+pub fn extractable_fraction(roast_level: RoastLevel, coffee_type: CoffeeType) -> f64 {
+    let roast = match roast_level {
+        RoastLevel::Light => 0.27,
+        RoastLevel::Medium => 0.30,
+        RoastLevel::Dark => 0.32,
+        RoastLevel::ExtraDark => 0.33,
+    };
+    let species = match coffee_type {
+        CoffeeType::Robusta => 0.02,
+        _ => 0.0,
+    };
+    roast + species
+}
+
+fn lerp(from: f64, to: f64, progress: f64) -> f64 {
+    from + (to - from) * progress
+}
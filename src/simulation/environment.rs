@@ -0,0 +1,118 @@
+use crate::simulation::trace::ShotTrace;
+use crate::simulation::{first_error, FieldError};
+use serde::{Deserialize, Serialize};
+
+const SEA_LEVEL_PRESSURE: f64 = 1013.25;
+// Latent heat of vaporization of water in J/mol, and the gas constant
+const VAPORIZATION_ENTHALPY: f64 = 40_660.0;
+const GAS_CONSTANT: f64 = 8.314;
+// Brew water stays this far below boiling so it doesn't flash to steam in the puck
+const FLASH_MARGIN: f64 = 2.0;
+// Newton cooling rate of an espresso in still, saturated air, per second
+const CUP_COOLING_RATE: f64 = 0.0018;
+// Extra cooling from evaporation in perfectly dry air
+const EVAPORATIVE_COOLING: f64 = 0.6;
+// Degrees the coffee loses between the spouts and the cup
+const POUR_DROP: f64 = 10.0;
+// Hottest a cup is comfortable to drink at
+pub const DRINKABLE_TEMPERATURE: f64 = 60.0;
+
+// Where the shot is pulled
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct Environment {
+    pub altitude_m: f64,
+    pub ambient_temperature: f64,
+    // Relative humidity in percent
+    pub humidity: f64,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            altitude_m: 0.0,
+            ambient_temperature: 20.0,
+            humidity: 50.0,
+        }
+    }
+}
+
+impl Environment {
+    pub fn validate(&self) -> Result<(), String> {
+        first_error(self.field_errors())
+    }
+
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if !(-500.0..=5500.0).contains(&self.altitude_m) {
+            errors.push(FieldError::new(
+                "altitude_m",
+                "Altitude must be between -500 and 5500 meters",
+            ));
+        }
+        if !(-10.0..=45.0).contains(&self.ambient_temperature) {
+            errors.push(FieldError::new(
+                "ambient_temperature",
+                "Ambient temperature must be between -10 and 45 degrees",
+            ));
+        }
+        if !(0.0..=100.0).contains(&self.humidity) {
+            errors.push(FieldError::new(
+                "humidity",
+                "Humidity must be between 0 and 100 percent",
+            ));
+        }
+        errors
+    }
+
+    // Barometric formula for the standard atmosphere, in hPa
+    pub fn air_pressure(&self) -> f64 {
+        SEA_LEVEL_PRESSURE * (1.0 - 2.25577e-5 * self.altitude_m).powf(5.25588)
+    }
+
+    // Clausius-Clapeyron from water's 100°C boiling point at sea level
+    pub fn boiling_point(&self) -> f64 {
+        let ratio = (self.air_pressure() / SEA_LEVEL_PRESSURE).ln();
+        1.0 / (1.0 / 373.15 - GAS_CONSTANT * ratio / VAPORIZATION_ENTHALPY) - 273.15
+    }
+
+    // Hottest the water can reach the puck without boiling off as it leaves
+    pub fn max_brew_temperature(&self) -> f64 {
+        self.boiling_point() - FLASH_MARGIN
+    }
+
+    // Dry air cools the cup faster through evaporation
+    pub fn cup_cooling_rate(&self) -> f64 {
+        CUP_COOLING_RATE * (1.0 + EVAPORATIVE_COOLING * (1.0 - self.humidity / 100.0))
+    }
+
+    // Temperature of the drink as the shot ends: every drop lands a little
+    // cooler than the brew water, mixes in and then loses heat to the room
+    pub fn cup_temperature(&self, trace: &ShotTrace) -> f64 {
+        let cooling = (-self.cup_cooling_rate() * trace.tick_seconds).exp();
+        let (mut weight, mut temperature) = (0.0, self.ambient_temperature);
+        for sample in &trace.samples {
+            let added = sample.cup_weight - weight;
+            if added > 0.0 {
+                temperature = (temperature * weight + (sample.temperature - POUR_DROP) * added)
+                    / sample.cup_weight;
+                weight = sample.cup_weight;
+            }
+            temperature =
+                self.ambient_temperature + (temperature - self.ambient_temperature) * cooling;
+        }
+        temperature
+    }
+
+    // Seconds a cup at `cup_temperature` takes to cool enough to drink
+    pub fn seconds_to_drinkable(&self, cup_temperature: f64) -> f64 {
+        if cup_temperature <= DRINKABLE_TEMPERATURE
+            || self.ambient_temperature >= DRINKABLE_TEMPERATURE
+        {
+            return 0.0;
+        }
+        ((cup_temperature - self.ambient_temperature)
+            / (DRINKABLE_TEMPERATURE - self.ambient_temperature))
+            .ln()
+            / self.cup_cooling_rate()
+    }
+}
@@ -0,0 +1,123 @@
+use crate::simulation::water::WaterProfile;
+use crate::simulation::{CoffeeType, IdealRanges, RoastLevel};
+use serde::{Deserialize, Serialize};
+
+// Bottom of the EY window the balance windows below are set for
+const REFERENCE_EY_MIN: f64 = 18.0;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum TasteBalance {
+    Sour,
+    #[default]
+    Balanced,
+    Bitter,
+}
+
+// Predicted cup on a 0-10 scale per attribute
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct FlavorProfile {
+    pub acidity: f64,
+    pub sweetness: f64,
+    pub bitterness: f64,
+    pub body: f64,
+    pub astringency: f64,
+    pub balance: TasteBalance,
+}
+
+impl FlavorProfile {
+    // Roast sets the baseline, extraction yield pulls it towards sour or
+    // bitter, strength sets the body, uneven extraction dries it out and the
+    // water's minerals buffer acidity and round out the body. `ranges` are
+    // the brew method's, so a filter coffee isn't judged by espresso strength.
+    pub fn predict(
+        extraction_yield: f64,
+        tds: f64,
+        ranges: &IdealRanges,
+        roast_level: RoastLevel,
+        coffee_type: CoffeeType,
+        channeling: f64,
+        water: &WaterProfile,
+    ) -> Self {
+        let (mut acidity, mut sweetness, mut bitterness, mut body) = match roast_level {
+            RoastLevel::Light => (7.0, 5.0, 2.0, 4.0),
+            RoastLevel::Medium => (5.0, 6.0, 3.5, 5.0),
+            RoastLevel::Dark => (3.0, 4.5, 5.5, 6.0),
+            RoastLevel::ExtraDark => (2.0, 3.0, 7.0, 6.5),
+        };
+        match coffee_type {
+            CoffeeType::Robusta => {
+                acidity -= 1.5;
+                sweetness -= 1.0;
+                bitterness += 1.5;
+                body += 1.0;
+            }
+            CoffeeType::SingleOrigin => acidity += 1.0,
+            CoffeeType::Blend => body += 0.5,
+            CoffeeType::Arabica => {}
+        }
+
+        let (sour_below, bitter_above) = balance_window(ranges, roast_level, coffee_type);
+        let mut astringency = 1.0 + channeling * 8.0;
+        if extraction_yield < sour_below {
+            let under = sour_below - extraction_yield;
+            acidity += under * 0.6;
+            sweetness -= under * 0.5;
+        } else if extraction_yield > bitter_above {
+            let over = extraction_yield - bitter_above;
+            bitterness += over * 0.8;
+            sweetness -= over * 0.5;
+            astringency += over * 0.8;
+        }
+        // Sugars come through best in the middle of the window
+        let middle = (sour_below + bitter_above) / 2.0;
+        sweetness += (2.0 * (1.0 - (extraction_yield - middle).abs() / 2.0)).max(0.0);
+        let strength =
+            (tds - (ranges.tds_min + ranges.tds_max) / 2.0) / (ranges.tds_max - ranges.tds_min);
+        body += strength * 2.4;
+        acidity -= water.acidity_buffering();
+        body += water.body_boost();
+
+        let balance = if extraction_yield < sour_below {
+            TasteBalance::Sour
+        } else if extraction_yield > bitter_above {
+            TasteBalance::Bitter
+        } else {
+            TasteBalance::Balanced
+        };
+
+        Self {
+            acidity: score(acidity),
+            sweetness: score(sweetness),
+            bitterness: score(bitterness),
+            body: score(body),
+            astringency: score(astringency),
+            balance,
+        }
+    }
+}
+
+// EY window that tastes balanced: light roasts need more extraction before
+// they stop tasting sour, dark roasts and robusta turn bitter sooner
+pub(crate) fn balance_window(
+    ranges: &IdealRanges,
+    roast_level: RoastLevel,
+    coffee_type: CoffeeType,
+) -> (f64, f64) {
+    // Methods with a lower EY target taste balanced lower down too
+    let shift = ranges.extraction_yield_min - REFERENCE_EY_MIN;
+    let (sour_below, bitter_above) = match roast_level {
+        RoastLevel::Light => (19.0, 23.0),
+        RoastLevel::Medium => (18.0, 22.0),
+        RoastLevel::Dark => (17.0, 21.0),
+        RoastLevel::ExtraDark => (16.5, 20.5),
+    };
+    let bitter_above = match coffee_type {
+        CoffeeType::Robusta => bitter_above - 1.0,
+        _ => bitter_above,
+    };
+    (sour_below + shift, bitter_above + shift)
+}
+
+fn score(value: f64) -> f64 {
+    (value.clamp(0.0, 10.0) * 10.0).round() / 10.0
+}
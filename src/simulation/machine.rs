@@ -0,0 +1,189 @@
+use crate::simulation::trace::ShotTrace;
+use serde::{Deserialize, Serialize};
+
+// Energy to heat one ml of fresh water by one degree, in J
+const WATER_HEAT_CAPACITY: f64 = 4.184;
+// Room temperature the machine models are calibrated in
+const REFERENCE_AMBIENT: f64 = 20.0;
+// Degrees the group head loses per degree the room is colder than the reference
+const GROUP_HEAT_LOSS: f64 = 0.04;
+// Water it takes to flush superheated water out of a heat exchanger
+const FLASH_FLUSH_ML: f64 = 60.0;
+// Idle time after which a heat exchanger has fully overheated
+const FLASH_IDLE_SECONDS: f64 = 300.0;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MachineKind {
+    SingleBoiler,
+    HeatExchanger,
+    #[default]
+    DualBoiler,
+    Thermoblock,
+    Lever,
+}
+
+// How a machine's brew water holds its temperature
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct MachineSpec {
+    // Heat the brew boiler and group hold per degree, in J/°C
+    pub thermal_mass: f64,
+    // Time constant the heater pulls a temperature deficit back in with
+    pub recovery_seconds: f64,
+    // Degrees the water loses passing through the group head
+    pub group_drop: f64,
+    // Degrees a long idle leaves the first water above the setpoint
+    pub idle_overshoot: f64,
+    // Time constant of a cold machine heating up to its setpoint
+    pub warm_up_seconds: f64,
+}
+
+impl MachineKind {
+    pub fn spec(&self) -> MachineSpec {
+        let (thermal_mass, recovery_seconds, group_drop, idle_overshoot, warm_up_seconds) =
+            match self {
+                MachineKind::SingleBoiler => (3500.0, 60.0, 0.8, 0.0, 600.0),
+                MachineKind::HeatExchanger => (4000.0, 60.0, 1.0, 5.0, 1500.0),
+                MachineKind::DualBoiler => (12000.0, 20.0, 0.3, 0.0, 900.0),
+                MachineKind::Thermoblock => (1500.0, 5.0, 1.0, 0.0, 90.0),
+                MachineKind::Lever => (8000.0, 180.0, 1.5, 0.0, 1200.0),
+            };
+        MachineSpec {
+            thermal_mass,
+            recovery_seconds,
+            group_drop,
+            idle_overshoot,
+            warm_up_seconds,
+        }
+    }
+}
+
+// Thermal state of one machine at a point in time, carried from shot to shot
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct MachineState {
+    pub kind: MachineKind,
+    // Seconds since the epoch this state describes
+    pub at: f64,
+    // Degrees the boiler is still below its setpoint from earlier draws
+    pub deficit: f64,
+    // Set while the machine may still be warming up from cold
+    pub powered_on_at: Option<f64>,
+    pub last_shot_at: Option<f64>,
+}
+
+impl MachineState {
+    // A machine that has been on long enough to be at temperature
+    pub fn warm(kind: MachineKind, at: f64) -> Self {
+        Self {
+            kind,
+            at,
+            deficit: 0.0,
+            powered_on_at: None,
+            last_shot_at: None,
+        }
+    }
+
+    // A machine switched on from room temperature at `at`
+    pub fn cold(kind: MachineKind, at: f64) -> Self {
+        Self {
+            powered_on_at: Some(at),
+            ..Self::warm(kind, at)
+        }
+    }
+
+    // The same machine left alone until `now`
+    pub fn at(&self, now: f64) -> Self {
+        let idle = (now - self.at).max(0.0);
+        Self {
+            at: self.at + idle,
+            deficit: self.deficit * (-idle / self.kind.spec().recovery_seconds).exp(),
+            ..*self
+        }
+    }
+
+    // Degrees a machine that is still heating up from room temperature
+    // falls short of `setpoint`
+    pub fn warm_up_deficit(&self, setpoint: f64, ambient: f64) -> f64 {
+        self.powered_on_at.map_or(0.0, |on| {
+            (setpoint - ambient).max(0.0)
+                * (-(self.at - on) / self.kind.spec().warm_up_seconds).exp()
+        })
+    }
+
+    // Degrees above the setpoint the first water comes out at after idling
+    pub fn idle_overshoot(&self) -> f64 {
+        let idle = self
+            .last_shot_at
+            .or(self.powered_on_at)
+            .map_or(FLASH_IDLE_SECONDS, |t| self.at - t);
+        self.kind.spec().idle_overshoot * (idle / FLASH_IDLE_SECONDS).clamp(0.0, 1.0)
+    }
+}
+
+// The machine's brew water during one shot: every ml drawn is replaced by
+// water at room temperature the heater has to catch up with
+#[derive(Debug, Clone, Copy)]
+pub struct Boiler {
+    state: MachineState,
+    setpoint: f64,
+    ambient: f64,
+    overshoot: f64,
+    drawn_ml: f64,
+}
+
+impl Boiler {
+    pub fn new(state: MachineState, setpoint: f64, ambient: f64) -> Self {
+        Self {
+            state,
+            setpoint,
+            ambient,
+            overshoot: state.idle_overshoot(),
+            drawn_ml: 0.0,
+        }
+    }
+
+    // Brew water at the puck relative to the setpoint
+    pub fn offset(&self) -> f64 {
+        let spec = self.state.kind.spec();
+        let flash = self.overshoot * (-self.drawn_ml / FLASH_FLUSH_ML).exp();
+        let room = GROUP_HEAT_LOSS * (REFERENCE_AMBIENT - self.ambient);
+        flash
+            - spec.group_drop
+            - room
+            - self.state.deficit
+            - self.state.warm_up_deficit(self.setpoint, self.ambient)
+    }
+
+    pub fn draw(&mut self, water_ml: f64, seconds: f64) {
+        let spec = self.state.kind.spec();
+        let cooling =
+            water_ml * WATER_HEAT_CAPACITY * (self.setpoint - self.ambient) / spec.thermal_mass;
+        self.drawn_ml += water_ml;
+        self.state = MachineState {
+            deficit: self.state.deficit + cooling,
+            ..self.state
+        }
+        .at(self.state.at + seconds);
+    }
+
+    // State the machine is left in once the shot is over
+    pub fn finish(self) -> MachineState {
+        MachineState {
+            last_shot_at: Some(self.state.at),
+            ..self.state
+        }
+    }
+}
+
+// Replays the water a finished shot drew to find the state it left the machine in
+pub fn after_shot(
+    state: MachineState,
+    setpoint: f64,
+    ambient: f64,
+    trace: &ShotTrace,
+) -> MachineState {
+    let mut boiler = Boiler::new(state, setpoint, ambient);
+    for sample in &trace.samples {
+        boiler.draw(sample.flow * trace.tick_seconds, trace.tick_seconds);
+    }
+    boiler.finish()
+}
@@ -0,0 +1,90 @@
+use crate::simulation::environment::Environment;
+use crate::simulation::methods::{BrewConfig, BrewMethod, BrewMethodKind, SearchSpace};
+use crate::simulation::scoring::ScorerKind;
+use crate::simulation::water::WaterProfile;
+use crate::simulation::{
+    check_espresso_temperature, simulate, CoffeeType, ExtractionMetrics, RoastLevel, ShotConfig,
+};
+
+// Espresso through the full shot simulator, stopping once `water_g` is in the cup
+pub struct Espresso;
+
+impl BrewMethod for Espresso {
+    fn kind(&self) -> BrewMethodKind {
+        BrewMethodKind::Espresso
+    }
+
+    fn defaults(&self) -> BrewConfig {
+        BrewConfig {
+            method: BrewMethodKind::Espresso,
+            dose_g: 18.0,
+            water_g: 36.0,
+            grind_microns: 300.0,
+            temperature: 93.0,
+            brew_seconds: 25.0,
+            bloom_seconds: 0.0,
+            pours: 0,
+            coffee_type: CoffeeType::default(),
+            roast_level: RoastLevel::default(),
+            water: WaterProfile::default(),
+            environment: Environment::default(),
+            days_off_roast: None,
+            scorer: ScorerKind::default(),
+        }
+    }
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        let ratio = config.water_g / config.dose_g;
+        if !(6.0..=25.0).contains(&config.dose_g) {
+            return Err("Dose must be between 6.0 and 25.0 grams".to_string());
+        }
+        if !(1.0..=4.0).contains(&ratio) {
+            return Err(format!(
+                "Yield must give a brew ratio between 1:1 and 1:4, got 1:{:.1}",
+                ratio
+            ));
+        }
+        if !(150.0..=1200.0).contains(&config.grind_microns) {
+            return Err("Grind must be between 150 and 1200 microns".to_string());
+        }
+        check_espresso_temperature(
+            config.temperature,
+            config.coffee_type,
+            config.roast_level,
+            &config.environment,
+        )
+    }
+
+    fn search_space(&self) -> SearchSpace {
+        SearchSpace {
+            temperature: (88.0, 96.0),
+            grind_microns: (180.0, 450.0),
+            ratio: (1.5, 3.0),
+            brew_seconds: None,
+        }
+    }
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics {
+        simulate(&ShotConfig {
+            temperature: config.temperature,
+            coffee_type: config.coffee_type,
+            roast_level: config.roast_level,
+            grind_microns: Some(config.grind_microns),
+            dose_g: config.dose_g,
+            target_yield_g: Some(config.water_g),
+            water: config.water.clone(),
+            days_off_roast: config.days_off_roast,
+            environment: config.environment,
+            scorer: config.scorer,
+            ..Default::default()
+        })
+    }
+
+    fn quality_score(&self, metrics: &ExtractionMetrics) -> u8 {
+        metrics.calculate_quality_score()
+    }
+
+    fn recommendations(&self, metrics: &ExtractionMetrics) -> Vec<String> {
+        metrics.generate_recommendations()
+    }
+}
@@ -0,0 +1,257 @@
+use crate::simulation::environment::Environment;
+use crate::simulation::methods::{
+    cool, validate_brew, Bed, BrewConfig, BrewMethod, BrewMethodKind, SearchSpace,
+    GROUNDS_HEAT_RATIO,
+};
+use crate::simulation::scoring::ScorerKind;
+use crate::simulation::trace::ShotTrace;
+use crate::simulation::water::WaterProfile;
+use crate::simulation::{CoffeeType, ExtractionMetrics, RoastLevel};
+
+// French press: a coarse steep, then plunge and pour
+pub struct FrenchPress;
+// AeroPress: a short, stirred steep pushed through paper by hand
+pub struct AeroPress;
+// Cold brew: cool water over many hours, then filtered
+pub struct ColdBrew;
+
+// How one immersion brewer steeps and then separates the coffee
+struct Steep {
+    tick_seconds: f64,
+    // Agitation from the opening stir, and for how long
+    stir: f64,
+    stir_seconds: f64,
+    // Separating the grounds: how long it takes, how hard it is pushed and
+    // how much extra it churns the bed
+    separate_seconds: f64,
+    separate_pressure: f64,
+    separate_agitation: f64,
+    retention_ratio: f64,
+    // Cooling time constant of the full brewer; None keeps it at temperature
+    cooling_seconds: Option<f64>,
+}
+
+fn steep(method: &dyn BrewMethod, config: &BrewConfig, steep: &Steep) -> ExtractionMetrics {
+    let tick = steep.tick_seconds;
+    let mut trace = ShotTrace::new(tick);
+    let mut bed = Bed::new(config, steep.retention_ratio);
+
+    // All the water goes in at once and warms the dry grounds a little
+    let heat_mass = config.dose_g * GROUNDS_HEAT_RATIO;
+    let mut temperature = match steep.cooling_seconds {
+        Some(_) => {
+            (config.temperature * config.water_g
+                + config.environment.ambient_temperature * heat_mass)
+                / (config.water_g + heat_mass)
+        }
+        None => config.temperature,
+    };
+    bed.add_water(config.water_g);
+
+    let steps = (config.brew_seconds / tick).round().max(1.0) as usize;
+    let separate_steps = (steep.separate_seconds / tick).round().max(1.0) as usize;
+    for step in 1..=steps + separate_steps {
+        let elapsed = step as f64 * tick;
+        if let Some(cooling_seconds) = steep.cooling_seconds {
+            temperature = cool(temperature, &config.environment, tick, cooling_seconds);
+        }
+
+        let separating = step > steps;
+        let agitation = if separating {
+            steep.separate_agitation
+        } else if elapsed <= steep.stir_seconds {
+            steep.stir
+        } else {
+            1.0
+        };
+        bed.steep(tick, temperature, agitation);
+
+        let (pressure, flow) = if separating {
+            let left = (steps + separate_steps + 1 - step) as f64;
+            let out = bed.free_water() / left;
+            bed.drain(out);
+            (steep.separate_pressure, out / tick)
+        } else {
+            (0.0, 0.0)
+        };
+        trace.push(bed.sample(elapsed, pressure, flow, temperature));
+    }
+
+    super::summarize(method, config, trace)
+}
+
+fn defaults(
+    method: BrewMethodKind,
+    dose_g: f64,
+    water_g: f64,
+    grind_microns: f64,
+    temperature: f64,
+    brew_seconds: f64,
+) -> BrewConfig {
+    BrewConfig {
+        method,
+        dose_g,
+        water_g,
+        grind_microns,
+        temperature,
+        brew_seconds,
+        bloom_seconds: 0.0,
+        pours: 0,
+        coffee_type: CoffeeType::default(),
+        roast_level: RoastLevel::default(),
+        water: WaterProfile::default(),
+        environment: Environment::default(),
+        days_off_roast: None,
+        scorer: ScorerKind::default(),
+    }
+}
+
+impl BrewMethod for FrenchPress {
+    fn kind(&self) -> BrewMethodKind {
+        BrewMethodKind::FrenchPress
+    }
+
+    fn defaults(&self) -> BrewConfig {
+        defaults(
+            BrewMethodKind::FrenchPress,
+            30.0,
+            500.0,
+            1000.0,
+            94.0,
+            240.0,
+        )
+    }
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        if !(60.0..=1200.0).contains(&config.brew_seconds) {
+            return Err("Steep must be between 60 and 1200 seconds".to_string());
+        }
+        validate_brew(config)
+    }
+
+    fn search_space(&self) -> SearchSpace {
+        SearchSpace {
+            temperature: (85.0, 98.0),
+            grind_microns: (600.0, 1400.0),
+            ratio: (12.0, 18.0),
+            brew_seconds: Some((180.0, 600.0)),
+        }
+    }
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics {
+        steep(
+            self,
+            config,
+            &Steep {
+                tick_seconds: 5.0,
+                stir: 1.5,
+                stir_seconds: 30.0,
+                separate_seconds: 20.0,
+                separate_pressure: 0.0,
+                separate_agitation: 1.2,
+                retention_ratio: 2.2,
+                cooling_seconds: Some(3000.0),
+            },
+        )
+    }
+}
+
+impl BrewMethod for AeroPress {
+    fn kind(&self) -> BrewMethodKind {
+        BrewMethodKind::AeroPress
+    }
+
+    fn defaults(&self) -> BrewConfig {
+        defaults(BrewMethodKind::AeroPress, 15.0, 220.0, 500.0, 85.0, 90.0)
+    }
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        if !(10.0..=600.0).contains(&config.brew_seconds) {
+            return Err("Steep must be between 10 and 600 seconds".to_string());
+        }
+        // The chamber holds about 250g of water with the coffee in it
+        if config.water_g + config.dose_g > 270.0 {
+            return Err("AeroPress holds at most 270 grams of coffee and water".to_string());
+        }
+        validate_brew(config)
+    }
+
+    fn search_space(&self) -> SearchSpace {
+        SearchSpace {
+            temperature: (75.0, 96.0),
+            grind_microns: (300.0, 900.0),
+            ratio: (10.0, 16.0),
+            brew_seconds: Some((30.0, 240.0)),
+        }
+    }
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics {
+        steep(
+            self,
+            config,
+            &Steep {
+                tick_seconds: 1.0,
+                stir: 1.5,
+                stir_seconds: 10.0,
+                separate_seconds: 30.0,
+                separate_pressure: 0.4,
+                separate_agitation: 2.0,
+                retention_ratio: 1.2,
+                cooling_seconds: Some(1500.0),
+            },
+        )
+    }
+}
+
+impl BrewMethod for ColdBrew {
+    fn kind(&self) -> BrewMethodKind {
+        BrewMethodKind::ColdBrew
+    }
+
+    fn defaults(&self) -> BrewConfig {
+        defaults(
+            BrewMethodKind::ColdBrew,
+            70.0,
+            1000.0,
+            1000.0,
+            20.0,
+            57_600.0,
+        )
+    }
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        if !(3600.0..=172_800.0).contains(&config.brew_seconds) {
+            return Err("Steep must be between 1 and 48 hours".to_string());
+        }
+        if config.temperature > 30.0 {
+            return Err("Cold brew water must be at most 30 degrees".to_string());
+        }
+        validate_brew(config)
+    }
+
+    fn search_space(&self) -> SearchSpace {
+        SearchSpace {
+            temperature: (4.0, 25.0),
+            grind_microns: (700.0, 1500.0),
+            ratio: (8.0, 16.0),
+            brew_seconds: Some((28_800.0, 86_400.0)),
+        }
+    }
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics {
+        steep(
+            self,
+            config,
+            &Steep {
+                tick_seconds: 600.0,
+                stir: 1.5,
+                stir_seconds: 600.0,
+                separate_seconds: 1800.0,
+                separate_pressure: 0.0,
+                separate_agitation: 1.0,
+                retention_ratio: 2.5,
+                cooling_seconds: None,
+            },
+        )
+    }
+}
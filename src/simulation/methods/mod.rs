@@ -0,0 +1,428 @@
+pub mod espresso;
+pub mod immersion;
+pub mod moka_pot;
+pub mod pour_over;
+
+use crate::simulation::engine::extractable_fraction;
+use crate::simulation::environment::Environment;
+use crate::simulation::flavor::{FlavorProfile, TasteBalance};
+use crate::simulation::scoring::ScorerKind;
+use crate::simulation::trace::{ShotSample, ShotTrace};
+use crate::simulation::water::WaterProfile;
+use crate::simulation::{
+    degassing, standards, CoffeeType, ExtractionMetrics, GrindSize, RoastLevel, ML_PER_OZ,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use self::espresso::Espresso;
+use self::immersion::{AeroPress, ColdBrew, FrenchPress};
+use self::moka_pot::MokaPot;
+use self::pour_over::PourOver;
+
+// Share of the solubles the liquid around the grounds dissolves per second at
+// 93°C, for a 300µm grind
+const EXTRACTION_RATE: f64 = 0.022;
+// Activation energy over the gas constant, for how extraction slows in cooler water
+const ACTIVATION_TEMPERATURE: f64 = 4200.0;
+// Heat the dry grounds take up, as grams of water per gram of coffee
+const GROUNDS_HEAT_RATIO: f64 = 0.4;
+
+#[derive(
+    Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default,
+)]
+pub enum BrewMethodKind {
+    #[default]
+    Espresso,
+    V60,
+    Kalita,
+    FrenchPress,
+    AeroPress,
+    MokaPot,
+    ColdBrew,
+}
+
+// Everything a brew method needs; each method reads the parameters that
+// apply to it and starts from its own defaults
+#[derive(Debug, Clone)]
+pub struct BrewConfig {
+    pub method: BrewMethodKind,
+    pub dose_g: f64,
+    // Brew water; the beverage weight to stop at for espresso
+    pub water_g: f64,
+    pub grind_microns: f64,
+    // Temperature of the brew water as it meets the coffee
+    pub temperature: f64,
+    // Steep or contact time; espresso stops on weight instead
+    pub brew_seconds: f64,
+    // Pour-over bloom, and how many pours the rest of the water goes in
+    pub bloom_seconds: f64,
+    pub pours: u32,
+    pub coffee_type: CoffeeType,
+    pub roast_level: RoastLevel,
+    pub water: WaterProfile,
+    pub environment: Environment,
+    pub days_off_roast: Option<f64>,
+    pub scorer: ScorerKind,
+}
+
+impl BrewMethodKind {
+    pub const ALL: [BrewMethodKind; 7] = [
+        BrewMethodKind::Espresso,
+        BrewMethodKind::V60,
+        BrewMethodKind::Kalita,
+        BrewMethodKind::FrenchPress,
+        BrewMethodKind::AeroPress,
+        BrewMethodKind::MokaPot,
+        BrewMethodKind::ColdBrew,
+    ];
+
+    pub fn method(&self) -> &'static dyn BrewMethod {
+        match self {
+            BrewMethodKind::Espresso => &Espresso,
+            BrewMethodKind::V60 => &PourOver::V60,
+            BrewMethodKind::Kalita => &PourOver::KALITA,
+            BrewMethodKind::FrenchPress => &FrenchPress,
+            BrewMethodKind::AeroPress => &AeroPress,
+            BrewMethodKind::MokaPot => &MokaPot,
+            BrewMethodKind::ColdBrew => &ColdBrew,
+        }
+    }
+}
+
+// Limits the optimizer searches within for a method; `ratio` is grams of
+// water (or beverage, for espresso) per gram of coffee
+#[derive(Debug, Clone, Copy)]
+pub struct SearchSpace {
+    pub temperature: (f64, f64),
+    pub grind_microns: (f64, f64),
+    pub ratio: (f64, f64),
+    // None for methods whose brew time follows from the others
+    pub brew_seconds: Option<(f64, f64)>,
+}
+
+// A way of brewing coffee: its parameters and how it gets to the cup; what a
+// good brew looks like comes from the extraction standards
+pub trait BrewMethod: Sync {
+    fn kind(&self) -> BrewMethodKind;
+
+    // Parameters a brew starts from when the caller leaves them out
+    fn defaults(&self) -> BrewConfig;
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        validate_brew(config)
+    }
+
+    fn search_space(&self) -> SearchSpace;
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics;
+
+    // SCA brewing control chart: extraction and strength matter most
+    fn quality_score(&self, metrics: &ExtractionMetrics) -> u8 {
+        let ranges = metrics.ideal_ranges();
+        let score = window_score(
+            metrics.extraction_yield_percent,
+            ranges.extraction_yield_min,
+            ranges.extraction_yield_max,
+            40.0,
+        ) + window_score(metrics.tds_percent, ranges.tds_min, ranges.tds_max, 30.0)
+            + window_score(
+                metrics.time_seconds as f64,
+                ranges.time_min as f64,
+                ranges.time_max as f64,
+                15.0,
+            )
+            + window_score(
+                metrics.temperature,
+                ranges.temperature_min,
+                ranges.temperature_max,
+                15.0,
+            );
+        score.round() as u8
+    }
+
+    fn recommendations(&self, metrics: &ExtractionMetrics) -> Vec<String> {
+        brew_recommendations(metrics)
+    }
+}
+
+// Limits every method other than espresso shares
+fn validate_brew(config: &BrewConfig) -> Result<(), String> {
+    let ratio = config.water_g / config.dose_g;
+    if !(3.0..=2000.0).contains(&config.dose_g) {
+        return Err("Dose must be between 3 and 2000 grams".to_string());
+    }
+    if !(5.0..=25.0).contains(&ratio) {
+        return Err(format!(
+            "Water must give a brew ratio between 1:5 and 1:25, got 1:{:.1}",
+            ratio
+        ));
+    }
+    if !(150.0..=2000.0).contains(&config.grind_microns) {
+        return Err("Grind must be between 150 and 2000 microns".to_string());
+    }
+    if !(0.0..=100.0).contains(&config.temperature) {
+        return Err("Temperature must be between 0 and 100 degrees".to_string());
+    }
+    Ok(())
+}
+
+// Full points inside the window, losing them over one window width outside it
+pub(crate) fn window_score(value: f64, min: f64, max: f64, points: f64) -> f64 {
+    let distance = if value < min {
+        min - value
+    } else if value > max {
+        value - max
+    } else {
+        0.0
+    };
+    points * (1.0 - distance / (max - min)).max(0.0)
+}
+
+// Recommendations that hold for any method that isn't espresso
+fn brew_recommendations(metrics: &ExtractionMetrics) -> Vec<String> {
+    let mut recommendations = Vec::new();
+    let ranges = metrics.ideal_ranges();
+
+    if metrics.temperature < ranges.temperature_min {
+        recommendations.push(format!(
+            "Brew water is too cool at {:.1} degrees: aim for {} to {}",
+            metrics.temperature, ranges.temperature_min, ranges.temperature_max
+        ));
+    } else if metrics.temperature > ranges.temperature_max {
+        recommendations.push(format!(
+            "Brew water is too hot at {:.1} degrees: aim for {} to {}",
+            metrics.temperature, ranges.temperature_min, ranges.temperature_max
+        ));
+    }
+
+    if metrics.time_seconds < ranges.time_min {
+        recommendations.push(format!(
+            "Brew finished in {} seconds: grind finer or brew for at least {} seconds",
+            metrics.time_seconds, ranges.time_min
+        ));
+    } else if metrics.time_seconds > ranges.time_max {
+        recommendations.push(format!(
+            "Brew took {} seconds: grind coarser or brew for at most {} seconds",
+            metrics.time_seconds, ranges.time_max
+        ));
+    }
+
+    match metrics.flavor.balance {
+        TasteBalance::Sour => recommendations.push(format!(
+            "Brew tastes sour (under-extracted at {:.1}% EY): grind finer, use hotter water or brew longer",
+            metrics.extraction_yield_percent
+        )),
+        TasteBalance::Bitter => recommendations.push(format!(
+            "Brew tastes bitter (over-extracted at {:.1}% EY): grind coarser, use cooler water or brew shorter",
+            metrics.extraction_yield_percent
+        )),
+        TasteBalance::Balanced => {}
+    }
+
+    if metrics.tds_percent < ranges.tds_min {
+        recommendations.push(format!(
+            "Brew is weak at {:.2}% TDS: use more coffee for the same water",
+            metrics.tds_percent
+        ));
+    } else if metrics.tds_percent > ranges.tds_max {
+        recommendations.push(format!(
+            "Brew is strong at {:.2}% TDS: use more water for the same coffee",
+            metrics.tds_percent
+        ));
+    }
+
+    recommendations
+}
+
+// Relative extraction speed at `temperature` against 93°C (Arrhenius)
+fn temperature_factor(temperature: f64) -> f64 {
+    (ACTIVATION_TEMPERATURE * (1.0 / 366.15 - 1.0 / (temperature + 273.15))).exp()
+}
+
+// Cold water never gets at some of what hot water dissolves
+fn accessible_share(temperature: f64) -> f64 {
+    0.65 + 0.35 * (temperature / 93.0).clamp(0.0, 1.0)
+}
+
+// The wet coffee bed and the liquid around it: grounds give up their solubles
+// to that liquid, and whatever drains off carries its share of them to the cup
+struct Bed {
+    dose_g: f64,
+    // Water the grounds keep for themselves
+    retained_g: f64,
+    solubles_left: f64,
+    liquid: f64,
+    dissolved: f64,
+    cup_water: f64,
+    cup_solubles: f64,
+    rate: f64,
+}
+
+impl Bed {
+    fn new(config: &BrewConfig, retention_ratio: f64) -> Self {
+        let days_off_roast = config
+            .days_off_roast
+            .unwrap_or(degassing::REFERENCE_DAYS_OFF_ROAST);
+        Self {
+            dose_g: config.dose_g,
+            retained_g: config.dose_g * retention_ratio,
+            solubles_left: config.dose_g
+                * extractable_fraction(config.roast_level, config.coffee_type)
+                * accessible_share(config.temperature),
+            liquid: 0.0,
+            dissolved: 0.0,
+            cup_water: 0.0,
+            cup_solubles: 0.0,
+            rate: EXTRACTION_RATE
+                * (300.0 / config.grind_microns)
+                * config.water.extraction_factor()
+                * degassing::extraction_factor(days_off_roast),
+        }
+    }
+
+    fn add_water(&mut self, grams: f64) {
+        self.liquid += grams;
+    }
+
+    // Stirring or pushing water through speeds things up by `agitation`
+    fn steep(&mut self, seconds: f64, temperature: f64, agitation: f64) {
+        let wet = (self.liquid / self.retained_g).min(1.0);
+        let rate = self.rate * temperature_factor(temperature) * agitation * wet;
+        let extracted = self.solubles_left * (1.0 - (-rate * seconds).exp());
+        self.solubles_left -= extracted;
+        self.dissolved += extracted;
+    }
+
+    // Water free to drain, beyond what the grounds hold on to
+    fn free_water(&self) -> f64 {
+        (self.liquid - self.retained_g).max(0.0)
+    }
+
+    fn drain(&mut self, grams: f64) {
+        let out = grams.min(self.free_water());
+        if out <= 0.0 {
+            return;
+        }
+        let solubles = self.dissolved * out / self.liquid;
+        self.liquid -= out;
+        self.dissolved -= solubles;
+        self.cup_water += out;
+        self.cup_solubles += solubles;
+    }
+
+    fn cup_weight(&self) -> f64 {
+        self.cup_water + self.cup_solubles
+    }
+
+    // What is in the cup so far, or dissolved in the brewer before anything drains
+    fn extraction_yield(&self) -> f64 {
+        let solubles = if self.cup_water > 0.0 {
+            self.cup_solubles
+        } else {
+            self.dissolved
+        };
+        solubles / self.dose_g * 100.0
+    }
+
+    fn sample(&self, elapsed: f64, pressure: f64, flow: f64, temperature: f64) -> ShotSample {
+        ShotSample {
+            elapsed,
+            pressure,
+            flow,
+            temperature,
+            cup_weight: self.cup_weight(),
+            extraction_yield: self.extraction_yield(),
+            stage: 0,
+        }
+    }
+}
+
+// Newton cooling of a brewer left standing in the room
+fn cool(temperature: f64, environment: &Environment, seconds: f64, time_constant: f64) -> f64 {
+    let ambient = environment.ambient_temperature;
+    ambient + (temperature - ambient) * (-seconds / time_constant).exp()
+}
+
+// Builds the metrics for a finished brew the way espresso shots report theirs
+fn summarize(method: &dyn BrewMethod, config: &BrewConfig, trace: ShotTrace) -> ExtractionMetrics {
+    let ranges = standards::current()
+        .ranges(method.kind(), config.coffee_type, config.roast_level)
+        .adjusted_for(&config.environment);
+    let beverage_weight = trace.final_cup_weight();
+    let extraction_yield = trace.final_extraction_yield();
+    let tds = if beverage_weight > 0.0 {
+        extraction_yield * config.dose_g / beverage_weight
+    } else {
+        0.0
+    };
+    let cup_temperature = config.environment.cup_temperature(&trace);
+
+    let metrics = ExtractionMetrics {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        temperature: trace.mean_temperature(),
+        pressure: trace.peak_pressure(),
+        time_seconds: trace.duration().round() as u64,
+        water_volume_oz: config.water_g / ML_PER_OZ,
+        coffee_type: config.coffee_type,
+        roast_level: config.roast_level,
+        grind_size: GrindSize::from_microns(config.grind_microns),
+        extraction_time: trace.first_drip().map_or(0.0, |t| trace.duration() - t),
+        grind_microns: config.grind_microns,
+        dose_g: config.dose_g,
+        beverage_weight_g: beverage_weight,
+        brew_ratio: beverage_weight / config.dose_g,
+        tds_percent: tds,
+        extraction_yield_percent: extraction_yield,
+        water: config.water.clone(),
+        scale_mg: config.water.scale_mg(config.water_g),
+        flavor: FlavorProfile::predict(
+            extraction_yield,
+            tds,
+            &ranges,
+            config.roast_level,
+            config.coffee_type,
+            0.0,
+            &config.water,
+        ),
+        days_off_roast: config.days_off_roast,
+        temperature_setpoint: config.temperature,
+        environment: config.environment,
+        cup_temperature,
+        seconds_to_drinkable: config.environment.seconds_to_drinkable(cup_temperature),
+        brew_method: method.kind(),
+        scorer: config.scorer,
+        trace,
+        ..Default::default()
+    };
+
+    let is_perfect = metrics.is_perfect();
+    let quality_score = config.scorer.scorer().score(&metrics);
+    let recommendations = method.recommendations(&metrics);
+
+    debug!(
+        method = ?metrics.brew_method,
+        extraction_yield = metrics.extraction_yield_percent,
+        tds = metrics.tds_percent,
+        "Simulated brew"
+    );
+
+    ExtractionMetrics {
+        result: if is_perfect {
+            "Perfect Extraction".to_string()
+        } else {
+            "Suboptimal Extraction".to_string()
+        },
+        quality_score,
+        recommendations,
+        ..metrics
+    }
+}
+
+pub fn brew(config: &BrewConfig) -> ExtractionMetrics {
+    config.method.method().simulate(config)
+}
@@ -0,0 +1,134 @@
+use crate::simulation::environment::Environment;
+use crate::simulation::methods::{
+    validate_brew, Bed, BrewConfig, BrewMethod, BrewMethodKind, SearchSpace,
+};
+use crate::simulation::puck::Puck;
+use crate::simulation::scoring::ScorerKind;
+use crate::simulation::trace::ShotTrace;
+use crate::simulation::water::WaterProfile;
+use crate::simulation::{degassing, CoffeeType, ExtractionMetrics, RoastLevel};
+
+const TICK_SECONDS: f64 = 1.0;
+// Heat the stove puts into the pot, in watts
+const STOVE_WATTS: f64 = 300.0;
+// Heat capacity of an aluminium three-cup pot, in J/°C
+const POT_HEAT_CAPACITY: f64 = 450.0;
+const WATER_HEAT_CAPACITY: f64 = 4.184;
+// Air sealed in above the water when the pot is screwed shut
+const HEADSPACE_ML: f64 = 50.0;
+// Latent heat of vaporization over the gas constant, for the vapor pressure
+const VAPORIZATION_TEMPERATURE: f64 = 4890.0;
+// The coffee sits loose in the funnel, nothing like a tamped puck
+const FUNNEL_TAMP_KG: f64 = 1.0;
+// Degrees the water loses rising through the funnel
+const FUNNEL_DROP: f64 = 10.0;
+// Water rising through the loose bed finds its way around part of it
+const CONTACT: f64 = 0.8;
+// The pot sputters once the water is below the bottom of the funnel tube
+const SPUTTER_SHARE: f64 = 0.1;
+const RETENTION_RATIO: f64 = 1.0;
+const MAX_SECONDS: f64 = 900.0;
+
+// Stovetop moka pot: steam and heated air in the sealed boiler push the
+// water up through the coffee until the pot sputters
+pub struct MokaPot;
+
+impl BrewMethod for MokaPot {
+    fn kind(&self) -> BrewMethodKind {
+        BrewMethodKind::MokaPot
+    }
+
+    fn defaults(&self) -> BrewConfig {
+        BrewConfig {
+            method: BrewMethodKind::MokaPot,
+            dose_g: 15.0,
+            water_g: 150.0,
+            grind_microns: 500.0,
+            temperature: 70.0,
+            brew_seconds: 0.0,
+            bloom_seconds: 0.0,
+            pours: 0,
+            coffee_type: CoffeeType::default(),
+            roast_level: RoastLevel::default(),
+            water: WaterProfile::default(),
+            environment: Environment::default(),
+            days_off_roast: None,
+            scorer: ScorerKind::default(),
+        }
+    }
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        let ratio = config.water_g / config.dose_g;
+        if !(5.0..=15.0).contains(&ratio) {
+            return Err(format!(
+                "Water must give a brew ratio between 1:5 and 1:15, got 1:{:.1}",
+                ratio
+            ));
+        }
+        validate_brew(config)
+    }
+
+    // `temperature` is the water the pot is filled with
+    fn search_space(&self) -> SearchSpace {
+        SearchSpace {
+            temperature: (20.0, 95.0),
+            grind_microns: (300.0, 800.0),
+            ratio: (7.0, 12.0),
+            brew_seconds: None,
+        }
+    }
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics {
+        let mut trace = ShotTrace::new(TICK_SECONDS);
+        let mut bed = Bed::new(config, RETENTION_RATIO);
+        let puck = Puck {
+            grind_microns: config.grind_microns,
+            dose_g: config.dose_g,
+            tamp_kg: FUNNEL_TAMP_KG,
+            days_off_roast: config
+                .days_off_roast
+                .unwrap_or(degassing::REFERENCE_DAYS_OFF_ROAST),
+        };
+
+        let atmosphere = config.environment.air_pressure() / 1000.0;
+        let boiling_point = config.environment.boiling_point() + 273.15;
+        let vapor_pressure = |celsius: f64| {
+            atmosphere
+                * (VAPORIZATION_TEMPERATURE * (1.0 / boiling_point - 1.0 / (celsius + 273.15)))
+                    .exp()
+        };
+        // Air sealed in with the water pushes harder as it heats and eases off
+        // as the water leaves room for it, on top of the steam
+        let start = config.temperature;
+        let air = (atmosphere - vapor_pressure(start)).max(0.0);
+
+        let mut boiler = config.water_g;
+        let mut temperature = start;
+        let mut elapsed = 0.0;
+        while elapsed < MAX_SECONDS && boiler > config.water_g * SPUTTER_SHARE {
+            let heat_capacity = POT_HEAT_CAPACITY + boiler * WATER_HEAT_CAPACITY;
+            temperature += STOVE_WATTS * TICK_SECONDS / heat_capacity;
+
+            let headspace = HEADSPACE_ML + config.water_g - boiler;
+            let pressure = (air * (temperature + 273.15) / (start + 273.15) * HEADSPACE_ML
+                / headspace
+                + vapor_pressure(temperature)
+                - atmosphere)
+                .max(0.0);
+            // Anything hotter than boiling flashes to steam once it's out of the boiler
+            let coffee_temperature = (temperature - FUNNEL_DROP).min(boiling_point - 273.15);
+            let flow = (pressure / puck.resistance(coffee_temperature))
+                .min((boiler - config.water_g * SPUTTER_SHARE) / TICK_SECONDS);
+            boiler -= flow * TICK_SECONDS;
+
+            bed.add_water(flow * TICK_SECONDS);
+            bed.steep(TICK_SECONDS, coffee_temperature, CONTACT);
+            bed.drain(bed.free_water());
+
+            elapsed += TICK_SECONDS;
+            trace.push(bed.sample(elapsed, pressure, flow, coffee_temperature));
+        }
+
+        super::summarize(self, config, trace)
+    }
+}
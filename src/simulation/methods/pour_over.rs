@@ -0,0 +1,152 @@
+use crate::simulation::environment::Environment;
+use crate::simulation::methods::{
+    cool, validate_brew, Bed, BrewConfig, BrewMethod, BrewMethodKind, SearchSpace,
+    GROUNDS_HEAT_RATIO,
+};
+use crate::simulation::scoring::ScorerKind;
+use crate::simulation::trace::ShotTrace;
+use crate::simulation::water::WaterProfile;
+use crate::simulation::{CoffeeType, ExtractionMetrics, RoastLevel};
+
+const TICK_SECONDS: f64 = 1.0;
+// Kettle pour rate in g/s
+const POUR_RATE: f64 = 5.0;
+// Rest between pours while the bed drains a little
+const POUR_PAUSE_SECONDS: f64 = 15.0;
+// Bloom water per gram of coffee
+const BLOOM_RATIO: f64 = 2.5;
+// Water a bed of paper-filtered grounds holds on to, per gram
+const RETENTION_RATIO: f64 = 2.0;
+// Cooling time constant of a few hundred grams of slurry in a cone
+const COOLING_SECONDS: f64 = 900.0;
+// Pours that still haven't drained give up after this long
+const MAX_SECONDS: f64 = 900.0;
+
+// Percolation through a paper filter: a bloom, then the rest of the water in
+// a few pours, draining through the bed as it goes
+pub struct PourOver {
+    kind: BrewMethodKind,
+    // Share of the free water above a 600µm bed that drains each second
+    drain_rate: f64,
+    // How much the pour stirs the bed
+    agitation: f64,
+}
+
+impl PourOver {
+    // Conical with one large hole: drains fast and churns the bed
+    pub const V60: PourOver = PourOver {
+        kind: BrewMethodKind::V60,
+        drain_rate: 0.045,
+        agitation: 1.4,
+    };
+    // Flat bed with three small holes: slower and more even
+    pub const KALITA: PourOver = PourOver {
+        kind: BrewMethodKind::Kalita,
+        drain_rate: 0.036,
+        agitation: 1.25,
+    };
+}
+
+impl BrewMethod for PourOver {
+    fn kind(&self) -> BrewMethodKind {
+        self.kind
+    }
+
+    fn defaults(&self) -> BrewConfig {
+        BrewConfig {
+            method: self.kind,
+            dose_g: 15.0,
+            water_g: 250.0,
+            grind_microns: 550.0,
+            temperature: 94.0,
+            brew_seconds: 0.0,
+            bloom_seconds: 40.0,
+            pours: 3,
+            coffee_type: CoffeeType::default(),
+            roast_level: RoastLevel::Light,
+            water: WaterProfile::default(),
+            environment: Environment::default(),
+            days_off_roast: None,
+            scorer: ScorerKind::default(),
+        }
+    }
+
+    fn validate(&self, config: &BrewConfig) -> Result<(), String> {
+        if !(0.0..=120.0).contains(&config.bloom_seconds) {
+            return Err("Bloom must be between 0 and 120 seconds".to_string());
+        }
+        if !(1..=10).contains(&config.pours) {
+            return Err("Pours must be between 1 and 10".to_string());
+        }
+        if config.water_g < config.dose_g * (BLOOM_RATIO + RETENTION_RATIO) {
+            return Err("Not enough water to bloom and brew this dose".to_string());
+        }
+        validate_brew(config)
+    }
+
+    fn search_space(&self) -> SearchSpace {
+        SearchSpace {
+            temperature: (85.0, 98.0),
+            grind_microns: (350.0, 900.0),
+            ratio: (13.0, 18.0),
+            brew_seconds: None,
+        }
+    }
+
+    fn simulate(&self, config: &BrewConfig) -> ExtractionMetrics {
+        let mut trace = ShotTrace::new(TICK_SECONDS);
+        let mut bed = Bed::new(config, RETENTION_RATIO);
+        let drain_rate = self.drain_rate * (config.grind_microns / 600.0).powi(2);
+
+        // Bloom first, then the rest split evenly over the pours
+        let bloom = config.dose_g * BLOOM_RATIO;
+        let pour = (config.water_g - bloom) / config.pours as f64;
+        let mut schedule = vec![(0.0, bloom)];
+        let mut start = config.bloom_seconds.max(bloom / POUR_RATE);
+        for _ in 0..config.pours {
+            schedule.push((start, pour));
+            start += pour / POUR_RATE + POUR_PAUSE_SECONDS;
+        }
+
+        // The dry grounds take some heat from the first water
+        let mut temperature = config.environment.ambient_temperature;
+        let mut heat_mass = config.dose_g * GROUNDS_HEAT_RATIO;
+        let mut poured = 0.0;
+        let mut elapsed = 0.0;
+        while elapsed < MAX_SECONDS {
+            let due: f64 = schedule
+                .iter()
+                .filter(|&&(at, _)| at <= elapsed)
+                .map(|&(_, grams)| grams)
+                .sum();
+            let added = (due - poured).clamp(0.0, POUR_RATE * TICK_SECONDS);
+            if added > 0.0 {
+                temperature =
+                    (temperature * heat_mass + config.temperature * added) / (heat_mass + added);
+                heat_mass += added;
+                poured += added;
+                bed.add_water(added);
+            }
+            temperature = cool(
+                temperature,
+                &config.environment,
+                TICK_SECONDS,
+                COOLING_SECONDS,
+            );
+
+            let agitation = if added > 0.0 { self.agitation } else { 1.0 };
+            bed.steep(TICK_SECONDS, temperature, agitation);
+            bed.drain(bed.free_water() * (1.0 - (-drain_rate * TICK_SECONDS).exp()));
+
+            elapsed += TICK_SECONDS;
+            trace.push(bed.sample(elapsed, 0.0, added / TICK_SECONDS, temperature));
+
+            // Done once the last pour has drained through
+            if poured >= config.water_g - 1e-9 && bed.free_water() < 1.0 {
+                break;
+            }
+        }
+
+        super::summarize(self, config, trace)
+    }
+}
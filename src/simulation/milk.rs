@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+const TICK_SECONDS: f64 = 0.5;
+// Heat a home or café steam wand puts into the jug, in watts
+const STEAM_WATTS: f64 = 1500.0;
+// Energy each gram of steam gives up condensing and cooling into the milk, J/g
+const STEAM_ENERGY: f64 = 2600.0;
+// Specific heat of milk, J/g°C
+pub const MILK_HEAT_CAPACITY: f64 = 3.9;
+// Air the wand tip draws in while it sits at the surface, ml/s
+const AIR_RATE: f64 = 8.0;
+// Above body temperature the proteins have set and new air makes big bubbles
+const STRETCH_LIMIT: f64 = 37.0;
+// Lactose tastes sweetest in this window
+const SWEET_MIN: f64 = 55.0;
+const SWEET_MAX: f64 = 65.0;
+// Give up on a wand that can't reach the target
+const MAX_SECONDS: f64 = 180.0;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum MilkType {
+    #[default]
+    Whole,
+    SemiSkimmed,
+    Skim,
+    Oat,
+    Soy,
+    Almond,
+}
+
+impl MilkType {
+    // Protein builds the bubble walls, fat softens them into a silky texture
+    // and plant milks lean on the stabilizers in their barista blends
+    fn foaming(&self) -> (f64, f64) {
+        // (protein %, fat %)
+        match self {
+            MilkType::Whole => (3.3, 3.6),
+            MilkType::SemiSkimmed => (3.4, 1.7),
+            MilkType::Skim => (3.5, 0.1),
+            MilkType::Oat => (1.0, 3.0),
+            MilkType::Soy => (3.0, 1.8),
+            MilkType::Almond => (0.6, 1.1),
+        }
+    }
+
+    // 0-1: how much of the drawn-in air stays as foam
+    pub fn foam_stability(&self) -> f64 {
+        let (protein, _) = self.foaming();
+        let stabilizers = match self {
+            MilkType::Oat | MilkType::Almond => 0.25,
+            _ => 0.0,
+        };
+        ((protein / 3.5) * 0.75 + stabilizers).min(0.9)
+    }
+
+    // 0-1: how fine and glossy the foam it forms is
+    pub fn silkiness(&self) -> f64 {
+        let (_, fat) = self.foaming();
+        0.6 + 0.4 * (fat / 3.6).min(1.0)
+    }
+
+    // Hottest the milk gets before it scalds, splits or tastes cooked
+    pub fn scald_temperature(&self) -> f64 {
+        match self {
+            MilkType::Whole | MilkType::SemiSkimmed | MilkType::Skim => 70.0,
+            MilkType::Oat | MilkType::Soy => 65.0,
+            MilkType::Almond => 60.0,
+        }
+    }
+}
+
+// How the milk is steamed: the wand stretches it (draws in air) for
+// `aeration_seconds`, then spins it until it reaches `target_temperature`
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Steaming {
+    pub milk_type: MilkType,
+    pub milk_g: f64,
+    pub start_temperature: f64,
+    pub target_temperature: f64,
+    pub aeration_seconds: f64,
+}
+
+impl Default for Steaming {
+    fn default() -> Self {
+        Self {
+            milk_type: MilkType::default(),
+            milk_g: 200.0,
+            start_temperature: 4.0,
+            target_temperature: 60.0,
+            aeration_seconds: 5.0,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct SteamedMilk {
+    pub milk_type: MilkType,
+    // Milk plus the steam that condensed into it
+    pub milk_g: f64,
+    pub final_temperature: f64,
+    pub steaming_seconds: f64,
+    pub foam_ml: f64,
+    // Volume added by the foam, as a share of the milk's
+    pub expansion_percent: f64,
+    // 0-10: small, even bubbles that pour glossy and hold together
+    pub microfoam_quality: f64,
+    // 0-10, from how the lactose comes through at this temperature
+    pub sweetness: f64,
+    // Share of the air that went in after the milk passed body temperature
+    pub late_aeration: f64,
+    pub scalded: bool,
+}
+
+impl Steaming {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(50.0..=1000.0).contains(&self.milk_g) {
+            return Err("Milk must be between 50 and 1000 grams".to_string());
+        }
+        if !(0.0..=30.0).contains(&self.start_temperature) {
+            return Err("Milk must start between 0 and 30 degrees".to_string());
+        }
+        if !(self.start_temperature..=85.0).contains(&self.target_temperature) {
+            return Err(format!(
+                "Target temperature must be between {:.1} and 85 degrees",
+                self.start_temperature
+            ));
+        }
+        if !(0.0..=30.0).contains(&self.aeration_seconds) {
+            return Err("Aeration must be between 0 and 30 seconds".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn steam(&self) -> SteamedMilk {
+        let mut milk_g = self.milk_g;
+        let mut temperature = self.start_temperature;
+        let (mut air, mut late_air) = (0.0, 0.0);
+        let mut elapsed = 0.0;
+
+        while temperature < self.target_temperature && elapsed < MAX_SECONDS {
+            if elapsed < self.aeration_seconds {
+                let drawn = AIR_RATE * TICK_SECONDS;
+                air += drawn;
+                if temperature > STRETCH_LIMIT {
+                    late_air += drawn;
+                }
+            }
+            // Steam condenses into the milk, heating it and watering it down
+            let condensed = STEAM_WATTS * TICK_SECONDS / STEAM_ENERGY;
+            temperature += STEAM_WATTS * TICK_SECONDS / (milk_g * MILK_HEAT_CAPACITY);
+            milk_g += condensed;
+            elapsed += TICK_SECONDS;
+        }
+
+        let foam_ml = air * self.milk_type.foam_stability();
+        let expansion = foam_ml / milk_g;
+        let late_aeration = if air > 0.0 { late_air / air } else { 0.0 };
+        let scalded = temperature > self.milk_type.scald_temperature();
+
+        // Late air makes big bubbles; far too much dries the foam out
+        let body = 0.5 + 0.5 * (self.milk_type.foam_stability() / 0.75).min(1.0);
+        let mut quality = 10.0 * body * self.milk_type.silkiness();
+        quality -= 6.0 * late_aeration;
+        quality -= 10.0 * (expansion - 0.8).max(0.0);
+        if scalded {
+            quality -= 3.0;
+        }
+
+        let sweetness = if temperature < SWEET_MIN {
+            8.0 - (SWEET_MIN - temperature) * 0.15
+        } else if temperature > SWEET_MAX {
+            8.0 - (temperature - SWEET_MAX) * 0.4
+        } else {
+            8.0
+        };
+
+        SteamedMilk {
+            milk_type: self.milk_type,
+            milk_g,
+            final_temperature: temperature,
+            steaming_seconds: elapsed,
+            foam_ml,
+            expansion_percent: expansion * 100.0,
+            microfoam_quality: quality.clamp(0.0, 10.0),
+            sweetness: sweetness.clamp(0.0, 10.0),
+            late_aeration,
+            scalded,
+        }
+    }
+}
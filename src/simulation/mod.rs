@@ -0,0 +1,1880 @@
+pub mod calibration;
+pub mod degassing;
+pub mod drinks;
+pub mod engine;
+pub mod environment;
+pub mod flavor;
+pub mod machine;
+pub mod methods;
+pub mod milk;
+pub mod optimizer;
+pub mod profile;
+pub mod puck;
+pub mod robustness;
+pub mod scoring;
+pub mod standards;
+pub mod sweep;
+pub mod trace;
+pub mod variance;
+pub mod water;
+
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, instrument};
+
+use self::engine::ModelCoefficients;
+use self::environment::Environment;
+use self::flavor::{FlavorProfile, TasteBalance};
+use self::machine::MachineState;
+use self::methods::BrewMethodKind;
+use self::profile::{ShotProfile, StageKind};
+use self::puck::Puck;
+use self::scoring::ScorerKind;
+use self::trace::ShotTrace;
+use self::variance::ShotEvent;
+use self::water::WaterProfile;
+
+const ML_PER_OZ: f64 = 29.5735;
+
+// Windows a shot is judged against, at sea level; the values come from the
+// extraction standards
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct IdealRanges {
+    pub temperature_min: f64,
+    pub temperature_max: f64,
+    pub pressure_min: f64,
+    pub pressure_max: f64,
+    pub time_min: u64,
+    pub time_max: u64,
+    pub extraction_yield_min: f64,
+    pub extraction_yield_max: f64,
+    pub tds_min: f64,
+    pub tds_max: f64,
+}
+
+impl IdealRanges {
+    // Where water boils too low for the usual window, the window slides down
+    // to end at the hottest brew temperature still reachable
+    pub fn adjusted_for(self, environment: &Environment) -> Self {
+        let shift = (self.temperature_max - environment.max_brew_temperature()).max(0.0);
+        Self {
+            temperature_min: self.temperature_min - shift,
+            temperature_max: self.temperature_max - shift,
+            ..self
+        }
+    }
+}
+
+// The brew temperatures an espresso can be pulled at, checked the same way by
+// `/start` and `/brew`: up to the top of the window, and down to its bottom as
+// that follows the boiling point down
+pub fn check_espresso_temperature(
+    temperature: f64,
+    coffee_type: CoffeeType,
+    roast_level: RoastLevel,
+    environment: &Environment,
+) -> Result<(), String> {
+    let ranges = standards::current().ranges(BrewMethodKind::Espresso, coffee_type, roast_level);
+    let temperature_min = ranges.adjusted_for(environment).temperature_min;
+    if !(temperature_min..=ranges.temperature_max).contains(&temperature) {
+        return Err(format!(
+            "Temperature must be between {:.1} and {:.1}",
+            temperature_min, ranges.temperature_max
+        ));
+    }
+    Ok(())
+}
+
+// Coffee Types could be defined in a separate module
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum CoffeeType {
+    Arabica,
+    Robusta,
+    Blend,
+    SingleOrigin,
+}
+
+impl Default for CoffeeType {
+    fn default() -> Self {
+        CoffeeType::Arabica
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum RoastLevel {
+    Light,
+    Medium,
+    Dark,
+    ExtraDark,
+}
+
+impl Default for RoastLevel {
+    fn default() -> Self {
+        RoastLevel::Medium
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum GrindSize {
+    Coarse,
+    Medium,
+    Fine,
+}
+
+impl Default for GrindSize {
+    fn default() -> Self {
+        GrindSize::Medium
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Default)]
+pub struct ExtractionMetrics {
+    // Given when the shot is stored; links to `/metrics/{id}`
+    #[serde(default)]
+    pub id: String,
+    pub timestamp: u64,
+    pub temperature: f64,
+    pub pressure: f64,
+    pub time_seconds: u64,
+    pub water_volume_oz: f64,
+    pub coffee_type: CoffeeType,
+    pub roast_level: RoastLevel,
+    pub grind_size: GrindSize,
+    pub result: String,
+    pub extraction_time: f64,
+    pub perfect_extraction_rate: f64,
+    pub quality_score: u8,
+    pub recommendations: Vec<String>,
+    #[serde(default)]
+    pub grind_microns: f64,
+    #[serde(default)]
+    pub tamp_kg: f64,
+    // Saturated puck resistance at brew temperature, in bar·s/ml
+    #[serde(default)]
+    pub puck_resistance: f64,
+    #[serde(default)]
+    pub dose_g: f64,
+    #[serde(default)]
+    pub beverage_weight_g: f64,
+    #[serde(default)]
+    pub brew_ratio: f64,
+    #[serde(default)]
+    pub tds_percent: f64,
+    #[serde(default)]
+    pub extraction_yield_percent: f64,
+    #[serde(default)]
+    pub water: WaterProfile,
+    // Limescale this shot left in the machine
+    #[serde(default)]
+    pub scale_mg: f64,
+    #[serde(default)]
+    pub flavor: FlavorProfile,
+    #[serde(default)]
+    pub profile: ShotProfile,
+    // Replays the same shot, events included, when passed back in
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub events: Vec<ShotEvent>,
+    #[serde(default)]
+    pub trace: ShotTrace,
+    // Catalog bean the shot was pulled with, if any
+    #[serde(default)]
+    pub bean_id: Option<String>,
+    #[serde(default)]
+    pub days_off_roast: Option<f64>,
+    // 0-10, from the CO2 left in the beans and the pressure it was pulled at
+    #[serde(default)]
+    pub crema: f64,
+    // Temperature asked for; `temperature` is what the puck actually saw
+    #[serde(default)]
+    pub temperature_setpoint: f64,
+    // State the shot left the machine in
+    #[serde(default)]
+    pub machine: Option<MachineState>,
+    #[serde(default)]
+    pub environment: Environment,
+    // Temperature of the drink as the shot finishes
+    #[serde(default)]
+    pub cup_temperature: f64,
+    #[serde(default)]
+    pub seconds_to_drinkable: f64,
+    #[serde(default)]
+    pub brew_method: BrewMethodKind,
+    // Scorer that produced `quality_score`
+    #[serde(default)]
+    pub scorer: ScorerKind,
+    // Calibration profile the shot was simulated with, if any
+    #[serde(default)]
+    pub calibration: Option<String>,
+    // Recipe version the shot was pulled from, if any
+    #[serde(default)]
+    pub recipe_id: Option<String>,
+    #[serde(default)]
+    pub recipe_version: Option<u32>,
+    #[serde(default)]
+    pub annotation: ShotAnnotation,
+    // Who pulled the shot, if given
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+// What the barista noted about a shot after pulling it
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Default)]
+pub struct ShotAnnotation {
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // 0-10, like the flavor scores
+    #[serde(default)]
+    pub rating: Option<u8>,
+}
+
+// One invalid input, named by its path in the request, such as
+// `profile.stages[1].target`
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    // The same error, for a field nested under `parent`; an error with no
+    // field of its own is about `parent` as a whole
+    pub fn within(self, parent: &str) -> Self {
+        let field = match self.field.as_str() {
+            "" => parent.to_string(),
+            field => format!("{}.{}", parent, field),
+        };
+        Self { field, ..self }
+    }
+}
+
+// The first error's message, as the `validate` functions report it
+pub fn first_error(errors: Vec<FieldError>) -> Result<(), String> {
+    match errors.into_iter().next() {
+        Some(error) => Err(error.message),
+        None => Ok(()),
+    }
+}
+
+// Everything the simulator needs to pull one shot
+#[derive(Debug, Clone)]
+pub struct ShotConfig {
+    pub temperature: f64,
+    pub pressure: f64,
+    pub time_seconds: u64,
+    pub coffee_type: CoffeeType,
+    pub roast_level: RoastLevel,
+    pub grind_size: GrindSize,
+    // Overrides the typical size for `grind_size` when set
+    pub grind_microns: Option<f64>,
+    pub tamp_kg: f64,
+    pub dose_g: f64,
+    // Stop on cup weight instead of `time_seconds` when set
+    pub target_yield_g: Option<f64>,
+    // Flat profile at `pressure` when not set
+    pub profile: Option<ShotProfile>,
+    // Enables shot-to-shot variance, reproducible for a given seed
+    pub seed: Option<u64>,
+    pub water: WaterProfile,
+    // Age of the beans; the model's reference age when not set
+    pub days_off_roast: Option<f64>,
+    // Machine as it is when the shot starts; the brew water holds the
+    // setpoint exactly when not set
+    pub machine: Option<MachineState>,
+    pub environment: Environment,
+    pub scorer: ScorerKind,
+    pub coefficients: ModelCoefficients,
+}
+
+impl Default for ShotConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 93.0,
+            pressure: 9.0,
+            time_seconds: 25,
+            coffee_type: CoffeeType::default(),
+            roast_level: RoastLevel::default(),
+            grind_size: GrindSize::default(),
+            grind_microns: None,
+            tamp_kg: 15.0,
+            dose_g: 18.0,
+            target_yield_g: None,
+            profile: None,
+            seed: None,
+            water: WaterProfile::default(),
+            days_off_roast: None,
+            machine: None,
+            environment: Environment::default(),
+            scorer: ScorerKind::default(),
+            coefficients: ModelCoefficients::default(),
+        }
+    }
+}
+
+impl ExtractionMetrics {
+    // Ranges this shot is judged against, given where it was pulled
+    pub fn ideal_ranges(&self) -> IdealRanges {
+        standards::current()
+            .ranges(self.brew_method, self.coffee_type, self.roast_level)
+            .adjusted_for(&self.environment)
+    }
+
+    pub fn is_perfect(&self) -> bool {
+        let ranges = self.ideal_ranges();
+        let is_perfect = (ranges.temperature_min..=ranges.temperature_max)
+            .contains(&self.temperature)
+            && (ranges.pressure_min..=ranges.pressure_max).contains(&self.pressure)
+            && (ranges.time_min..=ranges.time_max).contains(&self.time_seconds);
+
+        debug!(
+            temp = self.temperature,
+            pressure = self.pressure,
+            time = self.time_seconds,
+            perfect = is_perfect,
+            "Evaluated extraction quality"
+        );
+
+        is_perfect
+    }
+
+    pub(crate) fn is_good(&self) -> bool {
+        let ranges = self.ideal_ranges();
+        self.is_perfect()
+            || ((ranges.temperature_min..=ranges.temperature_max).contains(&self.temperature)
+                && (ranges.pressure_min..=ranges.pressure_max).contains(&self.pressure))
+            || ((ranges.temperature_min..=ranges.temperature_max).contains(&self.temperature)
+                && (ranges.pressure_min..=ranges.pressure_max).contains(&self.pressure)
+                && (ranges.time_min..=ranges.time_max).contains(&self.time_seconds))
+    }
+
+    // New algorithm for quality score
+    pub fn calculate_quality_score(&self) -> u8 {
+        let ranges = self.ideal_ranges();
+        let temp_score = window_points(
+            self.temperature,
+            ranges.temperature_min,
+            ranges.temperature_max,
+        );
+        let press_score = window_points(self.pressure, ranges.pressure_min, ranges.pressure_max);
+        let time_score = window_points(
+            self.time_seconds as f64,
+            ranges.time_min as f64,
+            ranges.time_max as f64,
+        );
+
+        // Add bonus points for perfect extraction
+        let mut bonus = 0;
+
+        // Bonus for coffee type and roast level optimization
+        if let Some(pairing) = standards::current().pairing(self.coffee_type, self.roast_level) {
+            bonus += pairing.bonus;
+        }
+
+        // Bonus for a grind that suits where the time lands in its window
+        match (&self.grind_size, self.time_seconds) {
+            (GrindSize::Coarse, t) if t < ranges.time_min => bonus += 5,
+            (GrindSize::Medium, t) if (ranges.time_min..=ranges.time_max).contains(&t) => {
+                bonus += 5
+            }
+            (GrindSize::Fine, t) if t > ranges.time_max => bonus += 5,
+            _ => {}
+        }
+
+        // Bonus for a pre-infusion stage that actually ran
+        if self.ran_pre_infusion() {
+            bonus += 5;
+        }
+
+        let final_score = (temp_score + press_score + time_score).saturating_add(bonus);
+        final_score
+            .min(100)
+            .saturating_sub(self.extraction_yield_penalty(&ranges))
+    }
+
+    // Three points per percent of EY outside the ideal window, when EY was modeled
+    fn extraction_yield_penalty(&self, ranges: &IdealRanges) -> u8 {
+        let ey = self.extraction_yield_percent;
+        let distance = if ey <= 0.0 {
+            0.0
+        } else if ey < ranges.extraction_yield_min {
+            ranges.extraction_yield_min - ey
+        } else if ey > ranges.extraction_yield_max {
+            ey - ranges.extraction_yield_max
+        } else {
+            0.0
+        };
+        (distance * 3.0).round().min(30.0) as u8
+    }
+
+    fn ran_pre_infusion(&self) -> bool {
+        self.trace.samples.iter().any(|s| {
+            self.profile
+                .stages
+                .get(s.stage)
+                .is_some_and(|stage| stage.kind == StageKind::PreInfusion)
+        })
+    }
+
+    // Recommendations Generator based on quality score
+    pub fn generate_recommendations(&self) -> Vec<String> {
+        let mut recommendations = Vec::new();
+        let ranges = self.ideal_ranges();
+
+        // Recommendations based on temperature
+        if self.temperature < ranges.temperature_min {
+            recommendations.push(format!(
+                "Increase temperature to {} degrees",
+                ranges.temperature_min
+            ));
+        } else if self.temperature > ranges.temperature_max {
+            recommendations.push(format!(
+                "Decrease temperature to {} degrees",
+                ranges.temperature_max
+            ));
+        }
+
+        // Recommendations based on altitude
+        let max_brew = self.environment.max_brew_temperature();
+        if self.temperature_setpoint > max_brew {
+            recommendations.push(format!(
+                "At {:.0} m water boils at {:.1} degrees: brew at {:.1} or below and grind finer to make up for the cooler water",
+                self.environment.altitude_m,
+                self.environment.boiling_point(),
+                max_brew
+            ));
+        }
+
+        // Recommendations based on how well the machine held its temperature
+        if let Some(machine) = &self.machine {
+            let deviation = self.temperature - self.temperature_setpoint;
+            if deviation < -2.0 {
+                recommendations.push(format!(
+                    "The {:?} ran {:.1} degrees below its setpoint: give it time to recover between shots",
+                    machine.kind, -deviation
+                ));
+            } else if deviation > 2.0 {
+                recommendations.push(format!(
+                    "The {:?} ran {:.1} degrees above its setpoint: run a cooling flush before the shot",
+                    machine.kind, deviation
+                ));
+            }
+        }
+
+        // Recommendations based on pressure
+        if self.pressure < ranges.pressure_min {
+            recommendations.push(format!("Increase pressure to {} psi", ranges.pressure_min));
+        } else if self.pressure > ranges.pressure_max {
+            recommendations.push(format!("Decrease pressure to {} psi", ranges.pressure_max));
+        }
+
+        // Recommendations based on time
+        if self.time_seconds < ranges.time_min {
+            recommendations.push(format!(
+                "Increase extraction time to {} seconds",
+                ranges.time_min
+            ));
+        } else if self.time_seconds > ranges.time_max {
+            recommendations.push(format!(
+                "Decrease extraction time to {} seconds",
+                ranges.time_max
+            ));
+        }
+
+        // Recommendations based on how the shot tastes, the way baristas dial in
+        match self.flavor.balance {
+            TasteBalance::Sour => recommendations.push(format!(
+                "Shot tastes sour (under-extracted at {:.1}% EY): grind finer, raise the temperature or pull a longer ratio",
+                self.extraction_yield_percent
+            )),
+            TasteBalance::Bitter => recommendations.push(format!(
+                "Shot tastes bitter (over-extracted at {:.1}% EY): grind coarser, lower the temperature or pull a shorter ratio",
+                self.extraction_yield_percent
+            )),
+            TasteBalance::Balanced => {}
+        }
+        if self.flavor.astringency >= 5.0 {
+            recommendations.push(
+                "Shot tastes dry and astringent: tamp level and use pre-infusion to avoid channeling"
+                    .to_string(),
+            );
+        }
+        if self.tds_percent > 0.0 && self.flavor.body < 3.0 {
+            recommendations
+                .push("Shot tastes thin: increase the dose or pull a shorter ratio".to_string());
+        }
+
+        // Recommendations based on water chemistry
+        if self.tds_percent > 0.0 && self.water.alkalinity > 100.0 {
+            recommendations.push(format!(
+                "Water alkalinity of {:.0} ppm flattens acidity and scales the boiler: aim for 40 ppm",
+                self.water.alkalinity
+            ));
+        }
+        if self.tds_percent > 0.0 && self.water.general_hardness < 20.0 {
+            recommendations.push(format!(
+                "Water hardness of {:.0} ppm is too soft to extract well: add minerals up to about 70 ppm",
+                self.water.general_hardness
+            ));
+        }
+
+        // Recommendations based on how long ago the beans were roasted
+        match self.days_off_roast {
+            Some(days) if days < 4.0 => recommendations.push(format!(
+                "Beans are only {:.0} days off roast: let them degas for a few more days to avoid gushing shots and channeling",
+                days
+            )),
+            Some(days) if days > 35.0 => recommendations.push(format!(
+                "Beans are {:.0} days off roast and going stale: grind finer or use a fresher bag",
+                days
+            )),
+            _ => {}
+        }
+
+        // Recommendations based on grind size and time
+        match self.grind_size {
+            GrindSize::Fine if self.time_seconds > ranges.time_max => {
+                recommendations
+                    .push("Consider using a coarser grind size for better extraction".to_string());
+            }
+            GrindSize::Coarse if self.time_seconds < ranges.time_min => {
+                recommendations
+                    .push("Consider using a finer grind size for better extraction".to_string());
+            }
+            _ => {}
+        }
+
+        // Recommendations based on coffee type and roast level
+        if let Some(pairing) = standards::current().pairing(self.coffee_type, self.roast_level) {
+            recommendations.extend(pairing.recommendation.clone());
+        }
+
+        recommendations
+    }
+}
+
+// The shot the original `/start` pulled, still what the older tests check
+#[cfg(test)]
+pub fn simulate_extraction(
+    temperature: Option<f64>,
+    pressure: Option<f64>,
+    time_seconds: Option<u64>,
+    coffee_type: Option<CoffeeType>,
+    roast_level: Option<RoastLevel>,
+    grind_size: Option<GrindSize>,
+) -> ExtractionMetrics {
+    simulate(&ShotConfig {
+        temperature: temperature.unwrap_or(98.6),
+        pressure: pressure.unwrap_or(1013.25),
+        time_seconds: time_seconds.unwrap_or(60),
+        coffee_type: coffee_type.unwrap_or_default(),
+        roast_level: roast_level.unwrap_or_default(),
+        grind_size: grind_size.unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+#[instrument]
+pub fn simulate(config: &ShotConfig) -> ExtractionMetrics {
+    let profile = config
+        .profile
+        .clone()
+        .unwrap_or_else(|| ShotProfile::flat(config.pressure));
+
+    debug!(
+        temperature = config.temperature,
+        pressure = config.pressure,
+        time = config.time_seconds,
+        coffee_type = ?config.coffee_type,
+        roast_level = ?config.roast_level,
+        grind_size = ?config.grind_size,
+        dose = config.dose_g,
+        water = %config.water.label(),
+        target_yield = ?config.target_yield_g,
+        profile = %profile.name,
+        seed = ?config.seed,
+        days_off_roast = ?config.days_off_roast,
+        machine = ?config.machine.map(|m| m.kind),
+        altitude = config.environment.altitude_m,
+        "Simulating extraction with parameters"
+    );
+
+    let pre_infusion = profile
+        .stages
+        .iter()
+        .any(|stage| stage.kind == StageKind::PreInfusion);
+    let events = config
+        .seed
+        .map(|seed| variance::roll_events(seed, config, pre_infusion))
+        .unwrap_or_default();
+    if !events.is_empty() {
+        debug!(events = ?events, "Rolled shot variance");
+    }
+
+    // What actually landed in the basket
+    let retained: f64 = events
+        .iter()
+        .map(|event| match *event {
+            ShotEvent::GrinderRetention { grams } => grams,
+            _ => 0.0,
+        })
+        .sum();
+    let config = &ShotConfig {
+        dose_g: config.dose_g + retained,
+        ..config.clone()
+    };
+
+    let puck = Puck::from_config(config);
+    let trace = engine::run(config, &profile, &events);
+    let beverage_weight = trace.final_cup_weight();
+    let extraction_yield = trace.final_extraction_yield();
+    let tds = if beverage_weight > 0.0 {
+        extraction_yield * config.dose_g / beverage_weight
+    } else {
+        0.0
+    };
+    let channeling = events
+        .iter()
+        .map(|event| match *event {
+            ShotEvent::Channeling { severity, .. } => severity,
+            _ => 0.0,
+        })
+        .fold(0.0, f64::max);
+
+    let ranges = standards::current()
+        .ranges(
+            BrewMethodKind::Espresso,
+            config.coffee_type,
+            config.roast_level,
+        )
+        .adjusted_for(&config.environment);
+    let cup_temperature = config.environment.cup_temperature(&trace);
+
+    let metrics = ExtractionMetrics {
+        id: String::new(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        temperature: trace.mean_temperature(),
+        pressure: trace.peak_pressure(),
+        time_seconds: trace.duration().round() as u64,
+        water_volume_oz: trace.water_dispensed() / ML_PER_OZ,
+        result: String::new(),
+        extraction_time: trace.first_drip().map_or(0.0, |t| trace.duration() - t),
+        perfect_extraction_rate: perfect_extraction_rate(&trace, &ranges),
+        coffee_type: config.coffee_type,
+        roast_level: config.roast_level,
+        grind_size: GrindSize::from_microns(puck.grind_microns),
+        quality_score: 0,
+        recommendations: Vec::new(),
+        grind_microns: puck.grind_microns,
+        tamp_kg: puck.tamp_kg,
+        puck_resistance: puck.resistance(config.temperature)
+            * config.coefficients.resistance_factor,
+        dose_g: config.dose_g,
+        beverage_weight_g: beverage_weight,
+        brew_ratio: beverage_weight / config.dose_g,
+        tds_percent: tds,
+        extraction_yield_percent: extraction_yield,
+        water: config.water.clone(),
+        scale_mg: config.water.scale_mg(trace.water_dispensed()),
+        flavor: FlavorProfile::predict(
+            extraction_yield,
+            tds,
+            &ranges,
+            config.roast_level,
+            config.coffee_type,
+            channeling,
+            &config.water,
+        ),
+        profile,
+        seed: config.seed,
+        events,
+        bean_id: None,
+        days_off_roast: config.days_off_roast,
+        crema: degassing::crema(puck.days_off_roast, trace.peak_pressure()),
+        temperature_setpoint: config.temperature,
+        machine: config.machine.map(|state| {
+            machine::after_shot(
+                state,
+                config.temperature,
+                config.environment.ambient_temperature,
+                &trace,
+            )
+        }),
+        environment: config.environment,
+        cup_temperature,
+        seconds_to_drinkable: config.environment.seconds_to_drinkable(cup_temperature),
+        brew_method: BrewMethodKind::Espresso,
+        scorer: config.scorer,
+        calibration: None,
+        recipe_id: None,
+        recipe_version: None,
+        annotation: ShotAnnotation::default(),
+        user: None,
+        trace,
+    };
+
+    let is_perfect = metrics.is_perfect();
+    let quality_score = config.scorer.scorer().score(&metrics);
+    let recommendations = metrics.generate_recommendations();
+
+    ExtractionMetrics {
+        result: if is_perfect {
+            "Perfect Extraction".to_string()
+        } else {
+            "Suboptimal Extraction".to_string()
+        },
+        quality_score,
+        recommendations,
+        ..metrics
+    }
+}
+
+// 30 points inside the window, scaled down outside it and never more than 30
+// or less than 0, however far off the value is
+fn window_points(value: f64, min: f64, max: f64) -> u8 {
+    let points = if (min..=max).contains(&value) {
+        30.0
+    } else if value < min {
+        30.0 * (1.0 - (value / min))
+    } else {
+        30.0 * ((max - value) / (max - min))
+    };
+    points.clamp(0.0, 30.0) as u8
+}
+
+// Share of the dripping phase spent inside the ideal temperature and pressure window
+fn perfect_extraction_rate(trace: &ShotTrace, ranges: &IdealRanges) -> f64 {
+    let (total, perfect) = trace.dripping().fold((0, 0), |(total, perfect), s| {
+        let in_range = (ranges.temperature_min..=ranges.temperature_max).contains(&s.temperature)
+            && (ranges.pressure_min..=ranges.pressure_max).contains(&s.pressure);
+        (total + 1, perfect + in_range as usize)
+    });
+
+    if total == 0 {
+        0.0
+    } else {
+        perfect as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::water::WaterRecipe;
+    use super::*;
+
+    #[test]
+    fn test_metrics_is_perfect() {
+        let perfect_metrics = ExtractionMetrics {
+            timestamp: 0,
+            temperature: 92.0,
+            pressure: 9.0,
+            time_seconds: 25,
+            water_volume_oz: 8.0,
+            result: String::new(),
+            extraction_time: 0.0,
+            perfect_extraction_rate: 1.0,
+            coffee_type: CoffeeType::Arabica,
+            roast_level: RoastLevel::Medium,
+            grind_size: GrindSize::Medium,
+            quality_score: 0,
+            recommendations: Vec::new(),
+            ..Default::default()
+        };
+        assert!(perfect_metrics.is_perfect());
+    }
+
+    #[test]
+    fn test_simulate_extraction() {
+        let metrics = simulate_extraction(Some(95.0), Some(9.0), Some(25), None, None, None);
+        assert_eq!(metrics.result, "Perfect Extraction");
+
+        let metrics = simulate_extraction(Some(100.0), Some(9.0), Some(25), None, None, None);
+        assert_eq!(metrics.result, "Suboptimal Extraction");
+
+        let metrics = simulate_extraction(Some(92.0), Some(12.0), Some(25), None, None, None);
+        assert_eq!(metrics.result, "Suboptimal Extraction");
+
+        let metrics = simulate_extraction(Some(92.0), Some(9.0), Some(15), None, None, None);
+        assert_eq!(metrics.result, "Suboptimal Extraction");
+        let metrics = simulate_extraction(Some(92.0), Some(9.0), Some(35), None, None, None);
+        assert_eq!(metrics.result, "Suboptimal Extraction");
+    }
+
+    #[test]
+    fn test_quality_score() {
+        let perfect_metrics = ExtractionMetrics {
+            timestamp: 0,
+            temperature: 92.0,
+            pressure: 9.0,
+            time_seconds: 25,
+            water_volume_oz: 8.0,
+            coffee_type: CoffeeType::Arabica,
+            roast_level: RoastLevel::Medium,
+            grind_size: GrindSize::Medium,
+            result: String::new(),
+            extraction_time: 0.0,
+            perfect_extraction_rate: 1.0,
+            quality_score: 0,
+            recommendations: Vec::new(),
+            ..Default::default()
+        };
+
+        // 30 each for temperature, pressure and time, 5 for the Arabica and
+        // medium roast pairing and 5 for a medium grind run in 20-30 s
+        assert_eq!(perfect_metrics.calculate_quality_score(), 100);
+        let coarse = ExtractionMetrics {
+            grind_size: GrindSize::Coarse,
+            ..perfect_metrics.clone()
+        };
+        assert_eq!(coarse.calculate_quality_score(), 95);
+
+        // The grind bonus follows the method's time window, not espresso's
+        let pour_over = ExtractionMetrics {
+            brew_method: BrewMethodKind::V60,
+            time_seconds: 200,
+            ..perfect_metrics
+        };
+        let bonus = |metrics: &ExtractionMetrics| {
+            metrics.calculate_quality_score() as i32
+                - ExtractionMetrics {
+                    grind_size: GrindSize::Fine,
+                    ..metrics.clone()
+                }
+                .calculate_quality_score() as i32
+        };
+        assert_eq!(bonus(&pour_over), 5);
+        assert_eq!(
+            bonus(&ExtractionMetrics {
+                time_seconds: 25,
+                ..pour_over
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn test_recommendations() {
+        let suboptimal_metrics = ExtractionMetrics {
+            timestamp: 0,
+            temperature: 88.0,
+            pressure: 7.0,
+            time_seconds: 35,
+            water_volume_oz: 8.0,
+            coffee_type: CoffeeType::Robusta,
+            roast_level: RoastLevel::Light,
+            grind_size: GrindSize::Coarse,
+            result: String::new(),
+            extraction_time: 0.0,
+            perfect_extraction_rate: 0.0,
+            quality_score: 0,
+            recommendations: Vec::new(),
+            ..Default::default()
+        };
+
+        let recommendations = suboptimal_metrics.generate_recommendations();
+        assert!(recommendations.len() >= 3);
+
+        // A 35 s pour-over is far too quick for a coarse grind
+        let finer = |time_seconds| {
+            ExtractionMetrics {
+                brew_method: BrewMethodKind::V60,
+                time_seconds,
+                ..suboptimal_metrics.clone()
+            }
+            .generate_recommendations()
+            .iter()
+            .any(|r| r.contains("finer grind"))
+        };
+        assert!(finer(35));
+        assert!(!finer(200));
+    }
+
+    #[test]
+    fn test_summary_derived_from_trace() {
+        let metrics = simulate_extraction(Some(93.0), Some(9.0), Some(25), None, None, None);
+
+        assert_eq!(metrics.trace.duration(), 25.0);
+        assert_eq!(metrics.time_seconds, 25);
+        assert_eq!(metrics.pressure, 9.0);
+        assert!(metrics.extraction_time > 0.0 && metrics.extraction_time < 25.0);
+        assert!(metrics.perfect_extraction_rate > 0.9);
+        assert!(metrics
+            .trace
+            .samples
+            .windows(2)
+            .all(|w| w[1].cup_weight >= w[0].cup_weight));
+        assert!(metrics.beverage_weight_g > 25.0);
+        assert_eq!(metrics.quality_score, metrics.calculate_quality_score());
+    }
+
+    #[test]
+    fn test_extraction_yield_model() {
+        let metrics = simulate(&ShotConfig {
+            target_yield_g: Some(36.0),
+            ..Default::default()
+        });
+        assert!(metrics.beverage_weight_g >= 36.0 && metrics.beverage_weight_g < 38.0);
+        assert!((metrics.brew_ratio - 2.0).abs() < 0.1);
+        let ranges = metrics.ideal_ranges();
+        assert!((ranges.extraction_yield_min..=ranges.extraction_yield_max)
+            .contains(&metrics.extraction_yield_percent));
+        assert!((8.0..=12.0).contains(&metrics.tds_percent));
+
+        // A ristretto leaves solubles behind, a lungo pulls more out
+        let ristretto = simulate(&ShotConfig {
+            target_yield_g: Some(18.0),
+            ..Default::default()
+        });
+        let lungo = simulate(&ShotConfig {
+            target_yield_g: Some(60.0),
+            ..Default::default()
+        });
+        assert!(ristretto.extraction_yield_percent < ranges.extraction_yield_min);
+        assert!(ristretto.tds_percent > metrics.tds_percent);
+        assert!(lungo.extraction_yield_percent > ranges.extraction_yield_max);
+        assert!(ristretto
+            .recommendations
+            .iter()
+            .any(|r| r.starts_with("Shot tastes sour")));
+        assert!(lungo.quality_score < metrics.quality_score);
+    }
+
+    #[test]
+    fn test_flavor_follows_extraction() {
+        let shot = |grind_microns: f64, roast_level: RoastLevel| {
+            simulate(&ShotConfig {
+                grind_microns: Some(grind_microns),
+                roast_level,
+                target_yield_g: Some(36.0),
+                ..Default::default()
+            })
+        };
+
+        let sour = shot(450.0, RoastLevel::Medium);
+        let balanced = shot(300.0, RoastLevel::Medium);
+        let bitter = shot(200.0, RoastLevel::Dark);
+
+        assert_eq!(sour.flavor.balance, TasteBalance::Sour);
+        assert_eq!(balanced.flavor.balance, TasteBalance::Balanced);
+        assert_eq!(bitter.flavor.balance, TasteBalance::Bitter);
+        assert!(sour.flavor.acidity > balanced.flavor.acidity);
+        assert!(balanced.flavor.sweetness > sour.flavor.sweetness);
+        assert!(bitter.flavor.bitterness > balanced.flavor.bitterness);
+
+        assert!(sour
+            .recommendations
+            .iter()
+            .any(|r| r.starts_with("Shot tastes sour") && r.contains("grind finer")));
+        assert!(bitter
+            .recommendations
+            .iter()
+            .any(|r| r.starts_with("Shot tastes bitter") && r.contains("grind coarser")));
+        assert!(!balanced
+            .recommendations
+            .iter()
+            .any(|r| r.starts_with("Shot tastes")));
+    }
+
+    #[test]
+    fn test_water_chemistry_shapes_the_shot() {
+        let shot = |recipe: WaterRecipe| {
+            simulate(&ShotConfig {
+                target_yield_g: Some(36.0),
+                water: recipe.profile(),
+                ..Default::default()
+            })
+        };
+        let sca = shot(WaterRecipe::Sca);
+        let distilled = shot(WaterRecipe::Distilled);
+        let hard = shot(WaterRecipe::HardTap);
+
+        assert!(distilled.extraction_yield_percent < sca.extraction_yield_percent);
+        assert!(hard.flavor.acidity < sca.flavor.acidity);
+        assert!(distilled.flavor.acidity > sca.flavor.acidity);
+        assert_eq!(distilled.scale_mg, 0.0);
+        assert!(hard.scale_mg > sca.scale_mg * 3.0);
+        assert!(hard
+            .recommendations
+            .iter()
+            .any(|r| r.starts_with("Water alkalinity")));
+        assert!(distilled
+            .recommendations
+            .iter()
+            .any(|r| r.starts_with("Water hardness")));
+    }
+
+    #[test]
+    fn test_grind_changes_how_the_shot_runs() {
+        let shot = |grind_microns: f64| {
+            simulate(&ShotConfig {
+                grind_microns: Some(grind_microns),
+                target_yield_g: Some(36.0),
+                ..Default::default()
+            })
+        };
+        let fine = shot(240.0);
+        let medium = shot(300.0);
+        let coarse = shot(400.0);
+
+        assert!(fine.puck_resistance > medium.puck_resistance);
+        assert!(medium.puck_resistance > coarse.puck_resistance);
+        assert!(fine.time_seconds > medium.time_seconds);
+        assert!(medium.time_seconds > coarse.time_seconds);
+        assert!(fine.extraction_yield_percent > coarse.extraction_yield_percent);
+        assert_eq!(fine.grind_size, GrindSize::Fine);
+        assert_eq!(coarse.grind_size, GrindSize::Coarse);
+
+        // A 36g shot from the reference puck lands in the classic window
+        assert!((25..=32).contains(&medium.time_seconds));
+
+        // More coffee makes a deeper bed that resists more
+        let bigger_dose = simulate(&ShotConfig {
+            dose_g: 20.0,
+            ..Default::default()
+        });
+        assert!(bigger_dose.puck_resistance > medium.puck_resistance);
+    }
+
+    #[test]
+    fn test_seeded_variance_replays() {
+        let seeded = |seed: u64| {
+            simulate(&ShotConfig {
+                seed: Some(seed),
+                ..Default::default()
+            })
+        };
+
+        let first = seeded(42);
+        let replay = seeded(42);
+        assert_eq!(first.seed, Some(42));
+        assert_eq!(first.events, replay.events);
+        assert_eq!(
+            first.extraction_yield_percent,
+            replay.extraction_yield_percent
+        );
+        assert_eq!(first.trace.samples, replay.trace.samples);
+
+        // Across many seeds shots spread out and every kind of event shows up
+        let shots: Vec<ExtractionMetrics> = (0..200).map(seeded).collect();
+        let distinct_yields = shots
+            .iter()
+            .map(|m| (m.extraction_yield_percent * 1000.0).round() as i64)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(distinct_yields.len() > 100);
+        for kind in [
+            |e: &ShotEvent| matches!(e, ShotEvent::Channeling { .. }),
+            |e: &ShotEvent| matches!(e, ShotEvent::TemperatureDrift { .. }),
+            |e: &ShotEvent| matches!(e, ShotEvent::GrinderRetention { .. }),
+        ] {
+            assert!(shots.iter().any(|m| m.events.iter().any(kind)));
+        }
+
+        // Channeled shots run faster and extract less than their clean replay
+        let channeled = shots
+            .iter()
+            .find(|m| m.events.len() == 1 && matches!(m.events[0], ShotEvent::Channeling { .. }))
+            .unwrap();
+        let clean = simulate(&ShotConfig::default());
+        assert!(channeled.beverage_weight_g > clean.beverage_weight_g);
+        assert!(channeled.tds_percent < clean.tds_percent);
+
+        // Without a seed every shot is the ideal one
+        assert!(clean.events.is_empty());
+        assert_eq!(clean.seed, None);
+    }
+
+    #[test]
+    fn test_profile_stages_drive_trace() {
+        let lever = ShotProfile::preset("lever", 9.0).unwrap();
+        let metrics = simulate(&ShotConfig {
+            time_seconds: 30,
+            coffee_type: CoffeeType::Robusta,
+            roast_level: RoastLevel::Light,
+            profile: Some(lever),
+            ..Default::default()
+        });
+
+        let stages: Vec<usize> = metrics.trace.samples.iter().map(|s| s.stage).collect();
+        assert!(stages.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(*stages.last().unwrap(), 2);
+
+        // Pre-infusion holds around 3 bar before the ramp to 9
+        let pre_infusion_peak = metrics
+            .trace
+            .samples
+            .iter()
+            .filter(|s| s.stage == 0)
+            .map(|s| s.pressure)
+            .fold(0.0, f64::max);
+        assert!(pre_infusion_peak <= 3.0);
+        assert!((metrics.pressure - 9.0).abs() < 0.5);
+
+        // Decline ends well below the peak
+        assert!(metrics.trace.samples.last().unwrap().pressure < 7.0);
+
+        // The same shot scores lower without the pre-infusion that was run
+        let without_pre_infusion = ExtractionMetrics {
+            profile: ShotProfile::flat(9.0),
+            ..metrics.clone()
+        };
+        assert!(metrics.quality_score > without_pre_infusion.calculate_quality_score());
+    }
+
+    #[test]
+    fn test_roast_age_changes_flow_and_crema() {
+        let shot = |days_off_roast| {
+            simulate(&ShotConfig {
+                days_off_roast,
+                ..Default::default()
+            })
+        };
+        let fresh = shot(Some(2.0));
+        let rested = shot(Some(10.0));
+        let stale = shot(Some(45.0));
+
+        // Unknown roast age is modeled as the reference age
+        let unknown = shot(None);
+        assert!((unknown.beverage_weight_g - rested.beverage_weight_g).abs() < 1e-9);
+        assert!((unknown.crema - rested.crema).abs() < 1e-9);
+
+        // CO2 slows the shot down, and it runs faster once it's gone
+        assert!(fresh.beverage_weight_g < rested.beverage_weight_g);
+        assert!(stale.beverage_weight_g > rested.beverage_weight_g);
+
+        // Crema fades with age
+        assert!(fresh.crema > rested.crema);
+        assert!(rested.crema > stale.crema);
+
+        assert!(fresh.recommendations.iter().any(|r| r.contains("degas")));
+        assert!(stale.recommendations.iter().any(|r| r.contains("stale")));
+        assert!(!rested
+            .recommendations
+            .iter()
+            .any(|r| r.contains("off roast")));
+    }
+
+    #[test]
+    fn test_machine_temperature_stability() {
+        use super::machine::{MachineKind, MachineState};
+        use crate::analytics::alerts::{AlertCategory, AlertGenerator};
+
+        // Pulls shots 20s apart and returns each shot's mean brew temperature
+        let back_to_back = |kind| {
+            let mut machine = MachineState::warm(kind, 0.0).at(1000.0);
+            (0..4)
+                .map(|_| {
+                    let metrics = simulate(&ShotConfig {
+                        machine: Some(machine),
+                        ..Default::default()
+                    });
+                    machine = metrics.machine.unwrap();
+                    machine = machine.at(machine.at + 20.0);
+                    metrics
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // A dual boiler holds its temperature shot after shot
+        let dual = back_to_back(MachineKind::DualBoiler);
+        assert!(dual.iter().all(|m| (m.temperature - 93.0).abs() < 1.0));
+
+        // A single boiler can't keep up, and the deviation gets flagged
+        let single = back_to_back(MachineKind::SingleBoiler);
+        let temperature_min = single[0].ideal_ranges().temperature_min;
+        assert!(single[0].temperature >= temperature_min);
+        assert!(single[3].temperature < single[1].temperature);
+        assert!(single[3].temperature < temperature_min);
+        let alerts = AlertGenerator::new().generate_alerts(&single[3]);
+        assert!(alerts
+            .iter()
+            .any(|a| matches!(a.category, AlertCategory::ParameterDeviation)));
+        assert!(single[3]
+            .recommendations
+            .iter()
+            .any(|r| r.contains("recover")));
+
+        // An idle heat exchanger starts hot until the overheated water is flushed
+        let heat_exchanger = back_to_back(MachineKind::HeatExchanger);
+        let first = &heat_exchanger[0].trace.samples;
+        assert!(first[0].temperature > 95.0);
+        assert!(first.last().unwrap().temperature < first[0].temperature);
+
+        // A machine switched on a few minutes ago is still cold
+        let cold = simulate(&ShotConfig {
+            machine: Some(MachineState::cold(MachineKind::DualBoiler, 0.0).at(300.0)),
+            ..Default::default()
+        });
+        assert!(cold.temperature < 80.0);
+        assert!((cold.temperature_setpoint - 93.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_altitude_and_room_shape_the_shot() {
+        // At sea level the usual windows apply
+        let ranges = standards::current().ranges(
+            BrewMethodKind::Espresso,
+            CoffeeType::default(),
+            RoastLevel::default(),
+        );
+        assert_eq!(ranges.adjusted_for(&Environment::default()), ranges);
+
+        // In La Paz water boils well below the usual brew temperature
+        let la_paz = Environment {
+            altitude_m: 3640.0,
+            ..Default::default()
+        };
+        assert!((la_paz.boiling_point() - 87.6).abs() < 0.5);
+        let capped = simulate(&ShotConfig {
+            environment: la_paz,
+            ..Default::default()
+        });
+        assert!(capped
+            .trace
+            .samples
+            .iter()
+            .all(|s| s.temperature <= la_paz.max_brew_temperature()));
+        assert!(capped.recommendations.iter().any(|r| r.contains("boils")));
+
+        // ...so the ideal window slides down with it
+        let ranges = capped.ideal_ranges();
+        assert!(ranges.temperature_max <= la_paz.max_brew_temperature());
+        let local = simulate(&ShotConfig {
+            temperature: 85.0,
+            environment: la_paz,
+            ..Default::default()
+        });
+        assert!(local.is_perfect());
+        assert!(!local.recommendations.iter().any(|r| r.contains("boils")));
+
+        // Espresso on `/brew` takes the temperatures `/start` takes
+        let espresso = BrewMethodKind::Espresso.method();
+        let brew_at = |temperature, environment| {
+            espresso.validate(&methods::BrewConfig {
+                temperature,
+                environment,
+                ..espresso.defaults()
+            })
+        };
+        let start_at = |temperature, environment: &Environment| {
+            check_espresso_temperature(
+                temperature,
+                CoffeeType::default(),
+                RoastLevel::default(),
+                environment,
+            )
+        };
+        for (temperature, environment) in [
+            (85.0, la_paz),
+            (85.0, Environment::default()),
+            (93.0, la_paz),
+            (99.0, Environment::default()),
+        ] {
+            assert_eq!(
+                brew_at(temperature, environment),
+                start_at(temperature, &environment)
+            );
+        }
+        assert!(brew_at(85.0, la_paz).is_ok());
+        assert!(brew_at(85.0, Environment::default()).is_err());
+        assert!(brew_at(99.0, Environment::default()).is_err());
+
+        // A dry, cold room cools the cup faster than a warm, humid one
+        let cup = |ambient_temperature, humidity| {
+            simulate(&ShotConfig {
+                environment: Environment {
+                    ambient_temperature,
+                    humidity,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let cold = cup(5.0, 10.0);
+        let warm = cup(30.0, 90.0);
+        assert!(cold.cup_temperature < warm.cup_temperature);
+        assert!(cold.seconds_to_drinkable < warm.seconds_to_drinkable);
+        assert!(warm.cup_temperature < warm.temperature);
+    }
+
+    #[test]
+    fn test_brew_methods() {
+        use super::methods::{brew, BrewConfig, BrewMethodKind};
+
+        let methods = [
+            BrewMethodKind::Espresso,
+            BrewMethodKind::V60,
+            BrewMethodKind::Kalita,
+            BrewMethodKind::FrenchPress,
+            BrewMethodKind::AeroPress,
+            BrewMethodKind::MokaPot,
+            BrewMethodKind::ColdBrew,
+        ];
+        // Every method's defaults land in its own ideal window
+        for kind in methods {
+            let config = kind.method().defaults();
+            assert_eq!(kind.method().validate(&config), Ok(()));
+            let metrics = brew(&config);
+            let ranges = metrics.ideal_ranges();
+            assert_eq!(metrics.brew_method, kind);
+            assert!(
+                (ranges.extraction_yield_min..=ranges.extraction_yield_max)
+                    .contains(&metrics.extraction_yield_percent),
+                "{:?} extracted {:.1}%",
+                kind,
+                metrics.extraction_yield_percent
+            );
+            assert!(
+                (ranges.tds_min..=ranges.tds_max).contains(&metrics.tds_percent),
+                "{:?} brewed {:.2}% TDS",
+                kind,
+                metrics.tds_percent
+            );
+            assert!(metrics.quality_score >= 90, "{:?}", kind);
+        }
+
+        // A filter brew is judged by its own ranges, not espresso's
+        let v60 = brew(&BrewMethodKind::V60.method().defaults());
+        assert!(v60.pressure < 1.0);
+        assert!(v60.beverage_weight_g > 200.0);
+
+        // Finer grounds drain slower and extract more
+        let pour = |grind_microns| {
+            brew(&BrewConfig {
+                grind_microns,
+                ..BrewMethodKind::V60.method().defaults()
+            })
+        };
+        let (fine, coarse) = (pour(450.0), pour(700.0));
+        assert!(fine.time_seconds > coarse.time_seconds);
+        assert!(fine.extraction_yield_percent > coarse.extraction_yield_percent);
+
+        // A longer steep keeps extracting
+        let press = |brew_seconds| {
+            brew(&BrewConfig {
+                brew_seconds,
+                ..BrewMethodKind::FrenchPress.method().defaults()
+            })
+        };
+        assert!(press(480.0).extraction_yield_percent > press(180.0).extraction_yield_percent);
+
+        // Cold brew needs the time hot water doesn't
+        let cold = brew(&BrewConfig {
+            brew_seconds: 240.0,
+            ..BrewMethodKind::ColdBrew.method().defaults()
+        });
+        assert!(cold.extraction_yield_percent < press(240.0).extraction_yield_percent);
+        assert!(BrewMethodKind::ColdBrew
+            .method()
+            .validate(&BrewConfig {
+                temperature: 90.0,
+                ..BrewMethodKind::ColdBrew.method().defaults()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_milk_drinks() {
+        use super::drinks::{Drink, DrinkKind};
+        use super::milk::{MilkType, Steaming};
+
+        let shots = [simulate(&ShotConfig::default())];
+        let milk = Steaming::default().steam();
+        assert!((59.0..62.0).contains(&milk.final_temperature));
+        // Condensed steam waters the milk down a little
+        assert!(milk.milk_g > 200.0 && milk.milk_g < 220.0);
+        assert!(!milk.scalded);
+
+        let latte = Drink::build(DrinkKind::Latte, &shots, milk.clone());
+        assert!(latte.quality_score >= 85, "{:?}", latte.recommendations);
+        assert!(latte.temperature > milk.final_temperature);
+        assert!(latte.temperature < shots[0].cup_temperature);
+        assert!(latte.tds_percent < shots[0].tds_percent);
+
+        // Stretching after the milk is warm leaves big bubbles
+        let late = Steaming {
+            aeration_seconds: 30.0,
+            ..Default::default()
+        }
+        .steam();
+        assert!(late.late_aeration > 0.3);
+        assert!(late.microfoam_quality < milk.microfoam_quality);
+        assert!(late.foam_ml > milk.foam_ml);
+
+        // The same latte milk is too thin for a cappuccino
+        let cappuccino = Drink::build(DrinkKind::Cappuccino, &shots, milk);
+        assert!(cappuccino.quality_score < latte.quality_score);
+        assert!(cappuccino
+            .recommendations
+            .iter()
+            .any(|r| r.contains("Too little foam")));
+
+        // Plant milks scald sooner than dairy
+        let oat = Steaming {
+            milk_type: MilkType::Oat,
+            target_temperature: 68.0,
+            ..Default::default()
+        }
+        .steam();
+        assert!(oat.scalded);
+        let drink = Drink::build(DrinkKind::Latte, &shots, oat);
+        assert!(drink.recommendations.iter().any(|r| r.contains("scalded")));
+    }
+
+    #[test]
+    fn test_standards_table() {
+        use super::standards::Standards;
+
+        let standards = Standards::default();
+        assert!(standards.validate().is_ok());
+        let espresso = |coffee_type, roast_level| {
+            standards.ranges(BrewMethodKind::Espresso, coffee_type, roast_level)
+        };
+        assert_eq!(
+            espresso(CoffeeType::Arabica, RoastLevel::Medium).temperature_min,
+            90.0
+        );
+        assert_eq!(
+            espresso(CoffeeType::Robusta, RoastLevel::Light).temperature_min,
+            92.0
+        );
+        assert_eq!(
+            espresso(CoffeeType::Arabica, RoastLevel::Dark).temperature_max,
+            94.0
+        );
+        assert_eq!(
+            standards
+                .pairing(CoffeeType::Robusta, RoastLevel::Dark)
+                .map(|p| p.bonus),
+            Some(5)
+        );
+        assert!(standards
+            .pairing(CoffeeType::Robusta, RoastLevel::Light)
+            .is_none());
+
+        // A coffee type row is more specific than a roast level row
+        let mut edited = standards.clone();
+        let mut robusta = edited.ranges[0].clone();
+        robusta.coffee_type = Some(CoffeeType::Robusta);
+        robusta.ranges.temperature_min = 91.0;
+        edited.ranges.push(robusta);
+        assert!(edited.validate().is_ok());
+        assert_eq!(
+            edited
+                .ranges(
+                    BrewMethodKind::Espresso,
+                    CoffeeType::Robusta,
+                    RoastLevel::Light
+                )
+                .temperature_min,
+            91.0
+        );
+
+        // Tables that can't be judged against are rejected
+        let mut backwards = standards.clone();
+        backwards.ranges[0].ranges.pressure_min = 11.0;
+        assert!(backwards.validate().is_err());
+        let mut duplicated = standards.clone();
+        duplicated.ranges.push(standards.ranges[1].clone());
+        assert!(duplicated.validate().is_err());
+        let mut missing = standards.clone();
+        missing
+            .ranges
+            .retain(|r| r.brew_method != BrewMethodKind::ColdBrew);
+        assert!(missing.validate().is_err());
+
+        for file in [
+            "espressia_standards_test.toml",
+            "espressia_standards_test.json",
+        ] {
+            let path = std::env::temp_dir().join(file);
+            edited.save(&path).unwrap();
+            assert_eq!(Standards::load(&path).unwrap(), edited);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_quality_scorers() {
+        use super::scoring::{Cupping, QualityScorer, ShotScores};
+
+        let metrics = simulate(&ShotConfig::default());
+        assert_eq!(metrics.scorer, ScorerKind::Classic);
+        assert_eq!(metrics.quality_score, metrics.calculate_quality_score());
+
+        let charted = simulate(&ShotConfig {
+            scorer: ScorerKind::ControlChart,
+            ..Default::default()
+        });
+        assert_eq!(charted.scorer, ScorerKind::ControlChart);
+        assert_eq!(
+            charted.quality_score,
+            ScorerKind::ControlChart.scorer().score(&charted)
+        );
+
+        // A sour, thin ristretto cups worse than a balanced shot
+        let ristretto = simulate(&ShotConfig {
+            target_yield_g: Some(18.0),
+            ..Default::default()
+        });
+        let cupped = Cupping.score(&metrics);
+        assert!((60..=100).contains(&cupped));
+        assert!(Cupping.score(&ristretto) < cupped);
+
+        // Values far outside every window used to overflow the score
+        let wild = ExtractionMetrics {
+            temperature: -1000.0,
+            pressure: -1000.0,
+            time_seconds: u64::MAX,
+            extraction_yield_percent: f64::NAN,
+            ..metrics.clone()
+        };
+        let scores = ShotScores::of(&wild);
+        assert_eq!(scores.scores.len(), ScorerKind::ALL.len());
+        assert!(scores.scores.values().all(|score| *score <= 100));
+    }
+
+    #[test]
+    fn test_optimizer() {
+        use super::methods::{brew, BrewConfig};
+        use super::optimizer::{optimize, suggest, Taste, TastedShot};
+
+        for kind in BrewMethodKind::ALL {
+            let base = kind.method().defaults();
+            let suggestion = optimize(&base);
+            let config = BrewConfig {
+                temperature: suggestion.settings.temperature,
+                grind_microns: suggestion.settings.grind_microns,
+                water_g: suggestion.settings.water_g,
+                brew_seconds: suggestion.settings.brew_seconds,
+                ..base.clone()
+            };
+            assert!(kind.method().validate(&config).is_ok(), "{:?}", kind);
+            assert!(suggestion.predicted.quality_score >= brew(&base).quality_score);
+            assert_eq!(suggestion.method, kind);
+        }
+
+        // A sour shot: extract more, and change several things at once
+        let base = BrewMethodKind::Espresso.method().defaults();
+        let sour = brew(&BrewConfig {
+            grind_microns: 420.0,
+            ..base.clone()
+        });
+        let next = suggest(
+            &base,
+            &[TastedShot {
+                metrics: sour.clone(),
+                taste: vec![Taste::Sour],
+            }],
+        )
+        .unwrap();
+        assert!(next.predicted.extraction_yield_percent > sour.extraction_yield_percent + 0.5);
+        assert!(next.predicted.quality_score > sour.quality_score);
+        assert!(next.changes.iter().any(|c| c.starts_with("Grind finer")));
+
+        // The model calls this shot balanced, but this brewer tastes it bitter
+        let balanced = brew(&base);
+        let next = suggest(
+            &base,
+            &[TastedShot {
+                metrics: balanced.clone(),
+                taste: vec![Taste::Bitter],
+            }],
+        )
+        .unwrap();
+        assert!(next.predicted.extraction_yield_percent < balanced.extraction_yield_percent - 0.5);
+
+        let pour_over = BrewMethodKind::V60.method().defaults();
+        assert!(suggest(
+            &pour_over,
+            &[TastedShot {
+                metrics: balanced,
+                taste: vec![Taste::Good],
+            }]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parameter_sweep() {
+        use super::sweep::{sweep, Axis, AxisValues, SweepParameter};
+
+        let axes = [
+            Axis {
+                parameter: SweepParameter::Temperature,
+                values: AxisValues::Range {
+                    min: 86.0,
+                    max: 96.0,
+                    steps: 6,
+                },
+            },
+            Axis {
+                parameter: SweepParameter::Pressure,
+                values: AxisValues::List {
+                    values: vec![6.0, 9.0, 12.0],
+                },
+            },
+        ];
+        let result = sweep(&ShotConfig::default(), &axes).unwrap();
+        assert_eq!(
+            result.axes[0].values,
+            vec![86.0, 88.0, 90.0, 92.0, 94.0, 96.0]
+        );
+        assert_eq!(result.quality_score.len(), 6);
+        assert!(result.quality_score.iter().all(|row| row.len() == 3));
+        assert_eq!(result.result[4][1], "Perfect Extraction");
+        assert_eq!(result.result[0][1], "Suboptimal Extraction");
+        assert!(result.quality_score[4][1] > result.quality_score[0][1]);
+        // Hotter water extracts more
+        assert!(result.extraction_yield[5][1] > result.extraction_yield[0][1]);
+
+        // Largest swing first, and hotter water always raises EY
+        let sensitivity = &result.sensitivity;
+        assert_eq!(sensitivity.len(), SweepParameter::ALL.len());
+        assert!(sensitivity.windows(2).all(|w| w[0].swing() >= w[1].swing()));
+        let temperature = sensitivity
+            .iter()
+            .find(|s| s.parameter == SweepParameter::Temperature)
+            .unwrap();
+        assert_eq!((temperature.low, temperature.high), (92.0, 94.0));
+        assert!(temperature.extraction_yield_slope > 0.0);
+
+        let too_hot = [Axis {
+            parameter: SweepParameter::Temperature,
+            values: AxisValues::List {
+                values: vec![93.0, 130.0],
+            },
+        }];
+        assert!(sweep(&ShotConfig::default(), &too_hot).is_err());
+        assert!(sweep(&ShotConfig::default(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_robustness() {
+        use super::robustness::{analyze, Tolerances, VarianceSource};
+
+        let recipe = ShotConfig::default();
+        let tolerances = Tolerances::default();
+        let report = analyze(&recipe, &tolerances, 200, 7, false);
+        assert_eq!(report.runs, 200);
+        assert_eq!(report.histogram.iter().sum::<usize>(), 200);
+        assert!(report.percentiles.windows(2).all(|w| w[0] <= w[1]));
+        assert!((0.0..=1.0).contains(&report.perfect_probability));
+        // Same seed, same analysis
+        let again = analyze(&recipe, &tolerances, 200, 7, false);
+        assert_eq!(again.percentiles, report.percentiles);
+        assert_eq!(again.perfect_probability, report.perfect_probability);
+
+        // Near the top of the window, temperature is what tips shots over
+        let hot = ShotConfig {
+            temperature: 95.5,
+            ..Default::default()
+        };
+        let report = analyze(&hot, &tolerances, 200, 7, true);
+        assert!(report.perfect_probability < 0.9);
+        assert_eq!(report.contributions[0].source, VarianceSource::Temperature);
+        assert!(report
+            .contributions
+            .iter()
+            .any(|c| c.source == VarianceSource::ShotVariance));
+
+        // A profile ignores `pressure`, so pressure variation moves its
+        // setpoints instead and still changes the shot
+        let lever = ShotConfig {
+            profile: ShotProfile::preset("lever", 9.0),
+            ..Default::default()
+        };
+        let pressure_only = Tolerances {
+            temperature: 0.0,
+            pressure: 2.0,
+            grind_microns: 0.0,
+            dose_g: 0.0,
+        };
+        let report = analyze(&lever, &pressure_only, 50, 7, false);
+        assert!(report.min_quality_score < report.max_quality_score);
+
+        // No tolerance, no failures
+        let exact = Tolerances {
+            temperature: 0.0,
+            pressure: 0.0,
+            grind_microns: 0.0,
+            dose_g: 0.0,
+        };
+        let report = analyze(&recipe, &exact, 20, 7, false);
+        assert_eq!(report.perfect_probability, 1.0);
+        assert!(report.contributions.is_empty());
+        assert!(Tolerances {
+            temperature: -1.0,
+            ..exact
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_calibration() {
+        use super::calibration::{calibrate, LoggedShot, ShotLabel, ShotLog};
+
+        // The machine's own export parses, extra fields and all
+        let log: ShotLog = serde_json::from_str(include_str!("../metrics.json")).unwrap();
+        assert_eq!(log.extractions[1].result, ShotLabel::OverExtracted);
+        assert_eq!(log.extractions[1].water_volume_oz, Some(1.35));
+        let calibration = calibrate("export", &log).unwrap();
+        assert_eq!(calibration.shots, 2);
+        assert!(calibration.fit.rms_error <= calibration.default_fit.rms_error);
+
+        // Shots pulled on a machine with the given coefficients, logging
+        // either their yield or the water they took
+        let pull = |coefficients: engine::ModelCoefficients, by_volume: bool| {
+            let shots = [88.0, 91.0, 93.0, 95.0]
+                .into_iter()
+                .map(|temperature| {
+                    let metrics = simulate(&ShotConfig {
+                        temperature,
+                        coefficients,
+                        ..Default::default()
+                    });
+                    LoggedShot {
+                        timestamp: None,
+                        temperature,
+                        pressure: 9.0,
+                        time_seconds: 25,
+                        dose_g: None,
+                        grind_microns: None,
+                        yield_g: (!by_volume).then_some(metrics.beverage_weight_g),
+                        water_volume_oz: by_volume.then_some(metrics.water_volume_oz),
+                        coffee_type: None,
+                        roast_level: None,
+                        result: match metrics.flavor.balance {
+                            TasteBalance::Sour => ShotLabel::UnderExtracted,
+                            TasteBalance::Balanced => ShotLabel::Balanced,
+                            TasteBalance::Bitter => ShotLabel::OverExtracted,
+                        },
+                    }
+                })
+                .collect();
+            ShotLog { extractions: shots }
+        };
+
+        // A machine whose puck runs slower than the textbook one, known only
+        // by the water each shot took
+        let slow = engine::ModelCoefficients {
+            resistance_factor: 1.6,
+            ..Default::default()
+        };
+        let calibration = calibrate("slow machine", &pull(slow, true)).unwrap();
+        assert!(calibration.fit.rms_error < calibration.default_fit.rms_error / 2.0);
+        assert!(calibration.coefficients.resistance_factor > 1.3);
+        assert!(calibration.warnings.is_empty());
+
+        // Labels alone say nothing about the puck, so its resistance stays
+        // put and the calibration says why
+        let mut unlogged = pull(slow, true);
+        for shot in &mut unlogged.extractions {
+            shot.water_volume_oz = None;
+        }
+        let calibration = calibrate("labels only", &unlogged).unwrap();
+        assert_eq!(calibration.coefficients.resistance_factor, 1.0);
+        assert_eq!(calibration.warnings.len(), 1);
+
+        // A volume logged in the wrong unit is turned away, not fitted
+        let mut milliliters = pull(slow, true);
+        milliliters.extractions[0].water_volume_oz = Some(36.0);
+        assert!(calibrate("ml", &milliliters)
+            .unwrap_err()
+            .starts_with("Shot 1: Water to dose ratio"));
+
+        // A machine whose puck runs faster than the textbook one
+        let fast = engine::ModelCoefficients {
+            resistance_factor: 0.7,
+            ..Default::default()
+        };
+        let log = pull(fast, false);
+        let calibration = calibrate("  fast machine ", &log).unwrap();
+        assert_eq!(calibration.name, "fast machine");
+        assert!(calibration.fit.rms_error < calibration.default_fit.rms_error / 2.0);
+        assert!(calibration.coefficients.resistance_factor < 0.85);
+
+        // Simulating with the calibration pours what the machine poured
+        let metrics = simulate(&ShotConfig {
+            temperature: 93.0,
+            coefficients: calibration.coefficients,
+            ..Default::default()
+        });
+        let logged = log.extractions[2].yield_g.unwrap();
+        assert!((metrics.beverage_weight_g - logged).abs() / logged < 0.1);
+
+        assert!(calibrate("", &log).is_err());
+        assert!(calibrate(
+            "empty",
+            &ShotLog {
+                extractions: vec![]
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_field_errors_name_every_invalid_field() {
+        use super::profile::ProfileSelection;
+        use super::water::WaterSelection;
+
+        let profile: ProfileSelection = serde_json::from_value(serde_json::json!({
+            "name": "broken",
+            "stages": [
+                {"name": "fill", "kind": "PreInfusion", "target": {"Flow": 40.0}},
+                {"name": "ramp", "kind": "Ramp", "target": {"Pressure": 9.0},
+                 "transition": {"Smooth": 0.0}},
+            ]
+        }))
+        .unwrap();
+        let errors: Vec<String> = profile
+            .resolve_fields(9.0)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.within("profile").field)
+            .collect();
+        assert_eq!(
+            errors,
+            ["profile.stages[0].target", "profile.stages[1].transition"]
+        );
+        // An unknown preset is the profile's fault as a whole
+        let preset = ProfileSelection::Preset("siphon".to_string());
+        assert_eq!(
+            preset.resolve_fields(9.0).unwrap_err()[0]
+                .clone()
+                .within("profile")
+                .field,
+            "profile"
+        );
+
+        let water: WaterSelection = serde_json::from_value(serde_json::json!({
+            "general_hardness": -1.0, "alkalinity": 40.0, "tds": 90.0, "ph": 15.0
+        }))
+        .unwrap();
+        let fields: Vec<String> = water.field_errors().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["general_hardness", "ph"]);
+
+        let environment = environment::Environment {
+            altitude_m: -900.0,
+            humidity: 140.0,
+            ..Default::default()
+        };
+        assert_eq!(environment.field_errors().len(), 2);
+        assert_eq!(
+            environment.validate().unwrap_err(),
+            environment.field_errors()[0].message
+        );
+    }
+}
@@ -0,0 +1,460 @@
+use crate::simulation::methods::{brew, BrewConfig, BrewMethodKind, SearchSpace};
+use crate::simulation::{ExtractionMetrics, ML_PER_OZ};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+// Levels per parameter in the coarse grid the search starts from
+const GRID_LEVELS: [f64; 3] = [0.2, 0.5, 0.8];
+// Nelder-Mead: first step along each axis, iteration cap and when to stop
+const INITIAL_STEP: f64 = 0.2;
+const MAX_ITERATIONS: usize = 120;
+const TOLERANCE: f64 = 1e-3;
+// Points lost per EY window width the brew lands outside what the brewer's
+// feedback asks for, and likewise for strength
+const FEEDBACK_PENALTY: f64 = 20.0;
+// Whatever fails validation is worse than any real brew
+const INVALID: f64 = -1000.0;
+
+// What the brewer thought of a shot
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum Taste {
+    Sour,
+    Bitter,
+    Weak,
+    Strong,
+    Good,
+}
+
+// A brew as it was returned by the simulator, with the brewer's verdict
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct TastedShot {
+    pub metrics: ExtractionMetrics,
+    #[serde(default)]
+    pub taste: Vec<Taste>,
+}
+
+// The parameters the optimizer moves; the dose stays put and the water
+// follows the ratio
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct BrewSettings {
+    pub temperature: f64,
+    pub grind_microns: f64,
+    pub dose_g: f64,
+    pub water_g: f64,
+    pub brew_seconds: f64,
+}
+
+impl BrewSettings {
+    pub fn of(config: &BrewConfig) -> Self {
+        Self {
+            temperature: config.temperature,
+            grind_microns: config.grind_microns,
+            dose_g: config.dose_g,
+            water_g: config.water_g,
+            brew_seconds: config.brew_seconds,
+        }
+    }
+
+    // Stored brews don't carry their steep time, so `brew_seconds` is kept
+    // from `fallback`
+    fn from_metrics(metrics: &ExtractionMetrics, fallback: &BrewConfig) -> Self {
+        let water_g = if metrics.brew_method == BrewMethodKind::Espresso {
+            metrics.beverage_weight_g
+        } else {
+            metrics.water_volume_oz * ML_PER_OZ
+        };
+        Self {
+            temperature: metrics.temperature_setpoint,
+            grind_microns: metrics.grind_microns,
+            dose_g: metrics.dose_g,
+            water_g,
+            brew_seconds: fallback.brew_seconds,
+        }
+    }
+
+    fn ratio(&self) -> f64 {
+        self.water_g / self.dose_g
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Suggestion {
+    pub method: BrewMethodKind,
+    pub settings: BrewSettings,
+    // What the simulator expects from those settings
+    pub predicted: ExtractionMetrics,
+    // Brews simulated to find them
+    pub evaluations: usize,
+    // What to change, all at once, from the last shot or the starting settings
+    pub changes: Vec<String>,
+}
+
+// Extraction and strength the brewer's feedback rules in or out; a Good
+// shot becomes the target to aim for
+#[derive(Debug, Default)]
+struct Preferences {
+    ey_min: Option<f64>,
+    ey_max: Option<f64>,
+    tds_min: Option<f64>,
+    tds_max: Option<f64>,
+    target: Option<(f64, f64)>,
+}
+
+impl Preferences {
+    fn from_feedback(shots: &[TastedShot]) -> Self {
+        let mut preferences = Self::default();
+        let mut good = Vec::new();
+        for shot in shots {
+            let (ey, tds) = (
+                shot.metrics.extraction_yield_percent,
+                shot.metrics.tds_percent,
+            );
+            for taste in &shot.taste {
+                match taste {
+                    Taste::Sour => {
+                        preferences.ey_min =
+                            Some(preferences.ey_min.map_or(ey + 0.5, |m| m.max(ey + 0.5)))
+                    }
+                    Taste::Bitter => {
+                        preferences.ey_max =
+                            Some(preferences.ey_max.map_or(ey - 0.5, |m| m.min(ey - 0.5)))
+                    }
+                    Taste::Weak => {
+                        preferences.tds_min = Some(
+                            preferences
+                                .tds_min
+                                .map_or(tds * 1.05, |m| m.max(tds * 1.05)),
+                        )
+                    }
+                    Taste::Strong => {
+                        preferences.tds_max = Some(
+                            preferences
+                                .tds_max
+                                .map_or(tds * 0.95, |m| m.min(tds * 0.95)),
+                        )
+                    }
+                    Taste::Good => good.push((ey, tds)),
+                }
+            }
+        }
+        // Feedback that contradicts itself settles in the middle
+        if let (Some(min), Some(max)) = (preferences.ey_min, preferences.ey_max) {
+            if min > max {
+                let middle = (min + max) / 2.0;
+                (preferences.ey_min, preferences.ey_max) = (Some(middle), Some(middle));
+            }
+        }
+        if let (Some(min), Some(max)) = (preferences.tds_min, preferences.tds_max) {
+            if min > max {
+                let middle = (min + max) / 2.0;
+                (preferences.tds_min, preferences.tds_max) = (Some(middle), Some(middle));
+            }
+        }
+        if !good.is_empty() {
+            let count = good.len() as f64;
+            preferences.target = Some((
+                good.iter().map(|(ey, _)| ey).sum::<f64>() / count,
+                good.iter().map(|(_, tds)| tds).sum::<f64>() / count,
+            ));
+        }
+        preferences
+    }
+
+    // Predicted score, less what the feedback rules out, plus under a point
+    // for landing near the target: the middle of the ideal windows unless a
+    // shot was Good
+    fn value(&self, metrics: &ExtractionMetrics) -> f64 {
+        let ranges = metrics.ideal_ranges();
+        let ey_width = ranges.extraction_yield_max - ranges.extraction_yield_min;
+        let tds_width = ranges.tds_max - ranges.tds_min;
+        let (ey, tds) = (metrics.extraction_yield_percent, metrics.tds_percent);
+
+        let outside = |value: f64, min: Option<f64>, max: Option<f64>| {
+            min.map_or(0.0, |min| (min - value).max(0.0))
+                + max.map_or(0.0, |max| (value - max).max(0.0))
+        };
+        let penalty = FEEDBACK_PENALTY
+            * (outside(ey, self.ey_min, self.ey_max) / ey_width
+                + outside(tds, self.tds_min, self.tds_max) / tds_width);
+
+        let (target_ey, target_tds) = self.target.unwrap_or((
+            (ranges.extraction_yield_min + ranges.extraction_yield_max) / 2.0,
+            (ranges.tds_min + ranges.tds_max) / 2.0,
+        ));
+        let closeness = (1.0 - (ey - target_ey).abs() / ey_width).max(0.0) * 0.5
+            + (1.0 - (tds - target_tds).abs() / tds_width).max(0.0) * 0.45;
+
+        metrics.quality_score as f64 - penalty + closeness
+    }
+}
+
+// Maps points in the unit cube onto a method's search space
+struct Search<'a> {
+    base: &'a BrewConfig,
+    space: SearchSpace,
+    preferences: Preferences,
+    evaluations: usize,
+}
+
+impl<'a> Search<'a> {
+    fn new(base: &'a BrewConfig, preferences: Preferences) -> Self {
+        let mut space = base.method.method().search_space();
+        // Water can't be hotter than it gets before it boils here
+        let hottest = base.environment.max_brew_temperature();
+        space.temperature.1 = space.temperature.1.min(hottest);
+        space.temperature.0 = space.temperature.0.min(space.temperature.1 - 1.0);
+        Self {
+            base,
+            space,
+            preferences,
+            evaluations: 0,
+        }
+    }
+
+    fn dimensions(&self) -> usize {
+        if self.space.brew_seconds.is_some() {
+            4
+        } else {
+            3
+        }
+    }
+
+    fn config(&self, point: &[f64]) -> BrewConfig {
+        let scale = |(min, max): (f64, f64), share: f64| min + (max - min) * share.clamp(0.0, 1.0);
+        BrewConfig {
+            temperature: scale(self.space.temperature, point[0]),
+            grind_microns: scale(self.space.grind_microns, point[1]),
+            water_g: self.base.dose_g * scale(self.space.ratio, point[2]),
+            brew_seconds: self
+                .space
+                .brew_seconds
+                .map_or(self.base.brew_seconds, |range| scale(range, point[3])),
+            ..self.base.clone()
+        }
+    }
+
+    fn point(&self, settings: &BrewSettings) -> Vec<f64> {
+        let share =
+            |(min, max): (f64, f64), value: f64| ((value - min) / (max - min)).clamp(0.0, 1.0);
+        let mut point = vec![
+            share(self.space.temperature, settings.temperature),
+            share(self.space.grind_microns, settings.grind_microns),
+            share(self.space.ratio, settings.ratio()),
+        ];
+        if let Some(range) = self.space.brew_seconds {
+            point.push(share(range, settings.brew_seconds));
+        }
+        point
+    }
+
+    fn value(&mut self, point: &[f64]) -> f64 {
+        self.evaluations += 1;
+        let config = self.config(point);
+        if config.method.method().validate(&config).is_err() {
+            return INVALID;
+        }
+        self.preferences.value(&brew(&config))
+    }
+
+    // Best point of a coarse grid over the whole space
+    fn grid(&mut self) -> (Vec<f64>, f64) {
+        let dimensions = self.dimensions();
+        let mut best = (vec![0.5; dimensions], f64::NEG_INFINITY);
+        for index in 0..GRID_LEVELS.len().pow(dimensions as u32) {
+            let point: Vec<f64> = (0..dimensions)
+                .map(|d| GRID_LEVELS[index / GRID_LEVELS.len().pow(d as u32) % GRID_LEVELS.len()])
+                .collect();
+            let value = self.value(&point);
+            if value > best.1 {
+                best = (point, value);
+            }
+        }
+        best
+    }
+
+    // Searches from the best grid point and from `start`, if given
+    fn run(mut self, start: Option<Vec<f64>>, reference: &BrewSettings) -> Suggestion {
+        let (grid_best, _) = self.grid();
+        let mut best = nelder_mead(&mut |point| self.value(point), grid_best);
+        if let Some(start) = start {
+            let candidate = nelder_mead(&mut |point| self.value(point), start);
+            if candidate.1 > best.1 {
+                best = candidate;
+            }
+        }
+
+        let config = self.config(&best.0);
+        let predicted = brew(&config);
+        let settings = BrewSettings::of(&config);
+        debug!(
+            method = ?config.method,
+            evaluations = self.evaluations,
+            score = predicted.quality_score,
+            "Optimized brew"
+        );
+        Suggestion {
+            method: config.method,
+            changes: changes(reference, &settings, self.space.brew_seconds.is_some()),
+            settings,
+            predicted,
+            evaluations: self.evaluations,
+        }
+    }
+}
+
+// The settings with the highest predicted score for this bean and method,
+// starting from `base`
+pub fn optimize(base: &BrewConfig) -> Suggestion {
+    Search::new(base, Preferences::default()).run(None, &BrewSettings::of(base))
+}
+
+// The next shot to pull after `shots`, most recent last: the search starts
+// from the last one and keeps away from what the feedback ruled out
+pub fn suggest(base: &BrewConfig, shots: &[TastedShot]) -> Result<Suggestion, String> {
+    if let Some(shot) = shots.iter().find(|s| s.metrics.brew_method != base.method) {
+        return Err(format!(
+            "Shot from {} was brewed with {:?}, not {:?}",
+            shot.metrics.timestamp, shot.metrics.brew_method, base.method
+        ));
+    }
+    let Some(last) = shots.last() else {
+        return Ok(optimize(base));
+    };
+    let reference = BrewSettings::from_metrics(&last.metrics, base);
+    let search = Search::new(base, Preferences::from_feedback(shots));
+    let start = search.point(&reference);
+    Ok(search.run(Some(start), &reference))
+}
+
+// Everything that moved between `from` and `to`, in brewer's terms
+fn changes(from: &BrewSettings, to: &BrewSettings, steeped: bool) -> Vec<String> {
+    let mut changes = Vec::new();
+    if (to.grind_microns - from.grind_microns).abs() >= 10.0 {
+        changes.push(format!(
+            "Grind {}: {:.0} to {:.0} microns",
+            if to.grind_microns < from.grind_microns {
+                "finer"
+            } else {
+                "coarser"
+            },
+            from.grind_microns,
+            to.grind_microns
+        ));
+    }
+    if (to.temperature - from.temperature).abs() >= 0.5 {
+        changes.push(format!(
+            "{} the water: {:.1} to {:.1} degrees",
+            if to.temperature > from.temperature {
+                "Heat"
+            } else {
+                "Cool"
+            },
+            from.temperature,
+            to.temperature
+        ));
+    }
+    if (to.ratio() - from.ratio()).abs() >= 0.05 * from.ratio() {
+        changes.push(format!(
+            "{} ratio: 1:{:.1} to 1:{:.1} ({:.0}g of water for {:.1}g of coffee)",
+            if to.ratio() > from.ratio() {
+                "Longer"
+            } else {
+                "Shorter"
+            },
+            from.ratio(),
+            to.ratio(),
+            to.water_g,
+            to.dose_g
+        ));
+    }
+    if steeped && (to.brew_seconds - from.brew_seconds).abs() >= 0.05 * from.brew_seconds {
+        changes.push(format!(
+            "Steep {}: {:.0} to {:.0} seconds",
+            if to.brew_seconds > from.brew_seconds {
+                "longer"
+            } else {
+                "shorter"
+            },
+            from.brew_seconds,
+            to.brew_seconds
+        ));
+    }
+    changes
+}
+
+// Nelder-Mead, maximizing, with every vertex kept inside the unit cube
+pub(crate) fn nelder_mead(
+    value: &mut dyn FnMut(&[f64]) -> f64,
+    start: Vec<f64>,
+) -> (Vec<f64>, f64) {
+    let dimensions = start.len();
+    let clamp =
+        |point: Vec<f64>| -> Vec<f64> { point.into_iter().map(|x| x.clamp(0.0, 1.0)).collect() };
+    let mut simplex = vec![start.clone()];
+    for d in 0..dimensions {
+        let mut vertex = start.clone();
+        vertex[d] += if vertex[d] + INITIAL_STEP <= 1.0 {
+            INITIAL_STEP
+        } else {
+            -INITIAL_STEP
+        };
+        simplex.push(vertex);
+    }
+    let mut values: Vec<f64> = simplex.iter().map(|p| value(p)).collect();
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut order: Vec<usize> = (0..simplex.len()).collect();
+        order.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+        simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+        values = order.iter().map(|&i| values[i]).collect();
+
+        let worst = dimensions;
+        let spread = (1..=dimensions)
+            .flat_map(|i| (0..dimensions).map(move |d| (i, d)))
+            .map(|(i, d)| (simplex[i][d] - simplex[0][d]).abs())
+            .fold(0.0, f64::max);
+        if spread < TOLERANCE {
+            break;
+        }
+
+        let centroid: Vec<f64> = (0..dimensions)
+            .map(|d| simplex[..worst].iter().map(|p| p[d]).sum::<f64>() / dimensions as f64)
+            .collect();
+        let towards = |from: &[f64], to: &[f64], t: f64| -> Vec<f64> {
+            clamp(from.iter().zip(to).map(|(a, b)| a + (b - a) * t).collect())
+        };
+
+        let reflected = towards(&simplex[worst], &centroid, 2.0);
+        let reflected_value = value(&reflected);
+        if reflected_value > values[0] {
+            let expanded = towards(&simplex[worst], &centroid, 3.0);
+            let expanded_value = value(&expanded);
+            if expanded_value > reflected_value {
+                (simplex[worst], values[worst]) = (expanded, expanded_value);
+            } else {
+                (simplex[worst], values[worst]) = (reflected, reflected_value);
+            }
+            continue;
+        }
+        if reflected_value > values[worst - 1] {
+            (simplex[worst], values[worst]) = (reflected, reflected_value);
+            continue;
+        }
+        let contracted = towards(&simplex[worst], &centroid, 0.5);
+        let contracted_value = value(&contracted);
+        if contracted_value > values[worst] {
+            (simplex[worst], values[worst]) = (contracted, contracted_value);
+            continue;
+        }
+        // Nothing better along that line: pull everything towards the best
+        for i in 1..simplex.len() {
+            simplex[i] = towards(&simplex[i], &simplex[0], 0.5);
+            values[i] = value(&simplex[i]);
+        }
+    }
+
+    let best = (0..simplex.len())
+        .max_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap_or(0);
+    (simplex[best].clone(), values[best])
+}
@@ -0,0 +1,237 @@
+use crate::simulation::FieldError;
+use serde::{Deserialize, Serialize};
+
+// Highest pressure a pump can deliver, used to cap flow-controlled stages
+pub const MAX_PUMP_PRESSURE: f64 = 12.0;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum StageKind {
+    PreInfusion,
+    Ramp,
+    Hold,
+    Decline,
+}
+
+// What the machine regulates during a stage
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum StageTarget {
+    // Bar at the group head
+    Pressure(f64),
+    // ml/s into the puck
+    Flow(f64),
+}
+
+// How the machine moves from the previous stage to this stage's target
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Transition {
+    #[default]
+    Fast,
+    // Linear interpolation over the given seconds
+    Smooth(f64),
+}
+
+// A stage ends as soon as any of its exit conditions is met
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum ExitCondition {
+    // Seconds spent in the stage
+    Time(f64),
+    // Grams in the cup
+    Weight(f64),
+    PressureAbove(f64),
+    PressureBelow(f64),
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct ProfileStage {
+    pub name: String,
+    pub kind: StageKind,
+    pub target: StageTarget,
+    #[serde(default)]
+    pub transition: Transition,
+    #[serde(default)]
+    pub exit: Vec<ExitCondition>,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Default)]
+pub struct ShotProfile {
+    pub name: String,
+    pub stages: Vec<ProfileStage>,
+}
+
+// A profile passed to the API, either by preset name or spelled out in full
+#[derive(Serialize, Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ProfileSelection {
+    Preset(String),
+    Custom(ShotProfile),
+}
+
+impl ProfileSelection {
+    pub fn resolve(&self, pressure: f64) -> Result<ShotProfile, String> {
+        self.resolve_fields(pressure).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|e| e.message)
+                .next()
+                .unwrap_or_default()
+        })
+    }
+
+    // As `resolve`, with every problem and the field it is in
+    pub fn resolve_fields(&self, pressure: f64) -> Result<ShotProfile, Vec<FieldError>> {
+        let profile = match self {
+            ProfileSelection::Preset(name) => {
+                ShotProfile::preset(name, pressure).ok_or_else(|| {
+                    vec![FieldError::new(
+                        "",
+                        format!(
+                            "Unknown profile preset '{}', expected one of: {}",
+                            name,
+                            ShotProfile::PRESETS.join(", ")
+                        ),
+                    )]
+                })?
+            }
+            ProfileSelection::Custom(profile) => profile.clone(),
+        };
+        let errors = profile.field_errors();
+        if errors.is_empty() {
+            Ok(profile)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl ProfileStage {
+    fn new(name: &str, kind: StageKind, target: StageTarget) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            target,
+            transition: Transition::Fast,
+            exit: Vec::new(),
+        }
+    }
+
+    fn smooth(mut self, seconds: f64) -> Self {
+        self.transition = Transition::Smooth(seconds);
+        self
+    }
+
+    fn exit_on(mut self, condition: ExitCondition) -> Self {
+        self.exit.push(condition);
+        self
+    }
+}
+
+impl ShotProfile {
+    pub const PRESETS: [&'static str; 4] = ["flat", "lever", "blooming", "turbo"];
+
+    // The classic pump shot: straight to the target pressure and hold it
+    pub fn flat(pressure: f64) -> Self {
+        Self {
+            name: "flat".to_string(),
+            stages: vec![ProfileStage::new(
+                "hold",
+                StageKind::Hold,
+                StageTarget::Pressure(pressure),
+            )],
+        }
+    }
+
+    pub fn preset(name: &str, pressure: f64) -> Option<Self> {
+        let stages = match name {
+            "flat" => return Some(Self::flat(pressure)),
+            "lever" => vec![
+                ProfileStage::new(
+                    "pre-infusion",
+                    StageKind::PreInfusion,
+                    StageTarget::Pressure(3.0),
+                )
+                .exit_on(ExitCondition::Time(8.0))
+                .exit_on(ExitCondition::Weight(2.0)),
+                ProfileStage::new("ramp", StageKind::Ramp, StageTarget::Pressure(9.0))
+                    .smooth(3.0)
+                    .exit_on(ExitCondition::Time(3.0)),
+                ProfileStage::new("decline", StageKind::Decline, StageTarget::Pressure(5.0))
+                    .smooth(15.0),
+            ],
+            "blooming" => vec![
+                ProfileStage::new("fill", StageKind::PreInfusion, StageTarget::Flow(4.0))
+                    .exit_on(ExitCondition::PressureAbove(4.0))
+                    .exit_on(ExitCondition::Time(8.0)),
+                ProfileStage::new("bloom", StageKind::PreInfusion, StageTarget::Flow(0.0))
+                    .exit_on(ExitCondition::Time(8.0)),
+                ProfileStage::new("ramp", StageKind::Ramp, StageTarget::Pressure(9.0))
+                    .smooth(3.0)
+                    .exit_on(ExitCondition::Time(3.0)),
+                ProfileStage::new("hold", StageKind::Hold, StageTarget::Pressure(9.0)),
+            ],
+            "turbo" => vec![
+                ProfileStage::new("fill", StageKind::PreInfusion, StageTarget::Flow(4.0))
+                    .exit_on(ExitCondition::PressureAbove(4.0))
+                    .exit_on(ExitCondition::Time(6.0)),
+                ProfileStage::new("hold", StageKind::Hold, StageTarget::Pressure(6.0)),
+            ],
+            _ => return None,
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            stages,
+        })
+    }
+
+    // Moves every pressure setpoint by `bar`, as a pump running high or
+    // low would; flow-controlled stages keep their target
+    pub fn shift_pressure(&mut self, bar: f64) {
+        for stage in &mut self.stages {
+            if let StageTarget::Pressure(target) = &mut stage.target {
+                *target = (*target + bar).clamp(0.0, MAX_PUMP_PRESSURE);
+            }
+        }
+    }
+
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.stages.is_empty() {
+            errors.push(FieldError::new(
+                "stages",
+                format!("Profile '{}' has no stages", self.name),
+            ));
+        }
+        for (i, stage) in self.stages.iter().enumerate() {
+            match stage.target {
+                StageTarget::Pressure(bar) if !(0.0..=MAX_PUMP_PRESSURE).contains(&bar) => {
+                    errors.push(FieldError::new(
+                        format!("stages[{}].target", i),
+                        format!(
+                            "Stage '{}' pressure must be between 0 and {} bar",
+                            stage.name, MAX_PUMP_PRESSURE
+                        ),
+                    ));
+                }
+                StageTarget::Flow(ml) if !(0.0..=10.0).contains(&ml) => {
+                    errors.push(FieldError::new(
+                        format!("stages[{}].target", i),
+                        format!("Stage '{}' flow must be between 0 and 10 ml/s", stage.name),
+                    ));
+                }
+                _ => {}
+            }
+            if let Transition::Smooth(seconds) = stage.transition {
+                if seconds <= 0.0 {
+                    errors.push(FieldError::new(
+                        format!("stages[{}].transition", i),
+                        format!(
+                            "Stage '{}' smooth transition needs a positive duration",
+                            stage.name
+                        ),
+                    ));
+                }
+            }
+        }
+        errors
+    }
+}
@@ -0,0 +1,101 @@
+use crate::simulation::degassing::{self, REFERENCE_DAYS_OFF_ROAST};
+use crate::simulation::{GrindSize, ShotConfig};
+use serde::{Deserialize, Serialize};
+
+// Reference puck the model is calibrated against: 18g at 300µm tamped with 15kg
+const REFERENCE_MICRONS: f64 = 300.0;
+const REFERENCE_DOSE_G: f64 = 18.0;
+const REFERENCE_TAMP_KG: f64 = 15.0;
+const REFERENCE_POROSITY: f64 = 0.40;
+// Resistance of the reference puck with water at 93°C, in bar·s/ml
+const REFERENCE_RESISTANCE: f64 = 7.0;
+
+// Water soaked up per gram of coffee before the first drops reach the cup
+const RETENTION_RATIO: f64 = 0.67;
+// Share of its resistance a puck loses as its solubles wash out
+const EROSION: f64 = 0.3;
+
+impl GrindSize {
+    // Typical median particle size for each coarse setting
+    pub fn microns(&self) -> f64 {
+        match self {
+            GrindSize::Fine => 250.0,
+            GrindSize::Medium => 300.0,
+            GrindSize::Coarse => 400.0,
+        }
+    }
+
+    pub fn from_microns(microns: f64) -> Self {
+        if microns < 275.0 {
+            GrindSize::Fine
+        } else if microns < 350.0 {
+            GrindSize::Medium
+        } else {
+            GrindSize::Coarse
+        }
+    }
+}
+
+// The bed of ground coffee water has to push through
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct Puck {
+    pub grind_microns: f64,
+    pub dose_g: f64,
+    pub tamp_kg: f64,
+    pub days_off_roast: f64,
+}
+
+impl Puck {
+    pub fn from_config(config: &ShotConfig) -> Self {
+        Self {
+            grind_microns: config
+                .grind_microns
+                .unwrap_or_else(|| config.grind_size.microns()),
+            dose_g: config.dose_g,
+            tamp_kg: config.tamp_kg,
+            days_off_roast: config.days_off_roast.unwrap_or(REFERENCE_DAYS_OFF_ROAST),
+        }
+    }
+
+    // Harder tamps squeeze the air out of the bed, with diminishing returns
+    pub fn porosity(&self) -> f64 {
+        (REFERENCE_POROSITY - 0.03 * (self.tamp_kg / REFERENCE_TAMP_KG).ln()).clamp(0.30, 0.50)
+    }
+
+    // Kozeny-Carman: resistance grows with bed depth and shrinks with the
+    // square of the particle size; hotter water is thinner and flows easier
+    // and freshly roasted coffee still degassing pushes back
+    pub fn resistance(&self, temperature: f64) -> f64 {
+        let particle = (REFERENCE_MICRONS / self.grind_microns).powi(2);
+        let depth = self.dose_g / REFERENCE_DOSE_G;
+        let packing = kozeny_carman(self.porosity()) / kozeny_carman(REFERENCE_POROSITY);
+        let viscosity = water_viscosity(temperature) / water_viscosity(93.0);
+        let co2 = degassing::resistance_factor(self.days_off_roast);
+
+        REFERENCE_RESISTANCE * particle * depth * packing * viscosity * co2
+    }
+
+    // Resistance once `progress` (0..1) of the solubles have washed out
+    pub fn eroded_resistance(&self, temperature: f64, progress: f64) -> f64 {
+        self.resistance(temperature) * (1.0 - EROSION * progress.clamp(0.0, 1.0))
+    }
+
+    pub fn absorption_ml(&self) -> f64 {
+        self.dose_g * RETENTION_RATIO
+    }
+
+    // Finer grounds expose more surface to the water, unless CO2 is in the way
+    pub fn extraction_factor(&self) -> f64 {
+        (REFERENCE_MICRONS / self.grind_microns).sqrt()
+            * degassing::extraction_factor(self.days_off_roast)
+    }
+}
+
+fn kozeny_carman(porosity: f64) -> f64 {
+    (1.0 - porosity).powi(2) / porosity.powi(3)
+}
+
+// Vogel equation for water, in Pa·s
+fn water_viscosity(temperature: f64) -> f64 {
+    2.414e-5 * 10f64.powf(247.8 / (temperature + 273.15 - 140.0))
+}
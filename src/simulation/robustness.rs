@@ -0,0 +1,214 @@
+use crate::simulation::sweep::SweepParameter;
+use crate::simulation::variance::ShotRng;
+use crate::simulation::{simulate, ExtractionMetrics, ShotConfig};
+use serde::{Deserialize, Serialize};
+
+pub const MAX_RUNS: usize = 2000;
+
+// Standard deviations of how far each input strays from the recipe from one
+// shot to the next
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Tolerances {
+    pub temperature: f64,
+    pub pressure: f64,
+    pub grind_microns: f64,
+    pub dose_g: f64,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Self {
+            temperature: 1.0,
+            pressure: 0.3,
+            grind_microns: 10.0,
+            dose_g: 0.3,
+        }
+    }
+}
+
+impl Tolerances {
+    pub fn validate(&self) -> Result<(), String> {
+        let limits = [
+            ("Temperature", self.temperature, 5.0),
+            ("Pressure", self.pressure, 2.0),
+            ("Grind", self.grind_microns, 100.0),
+            ("Dose", self.dose_g, 2.0),
+        ];
+        for (name, std_dev, max) in limits {
+            if !(0.0..=max).contains(&std_dev) {
+                return Err(format!(
+                    "{} tolerance must be between 0 and {}, got {}",
+                    name, max, std_dev
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn inputs(&self) -> [(VarianceSource, SweepParameter, f64); 4] {
+        [
+            (
+                VarianceSource::Temperature,
+                SweepParameter::Temperature,
+                self.temperature,
+            ),
+            (
+                VarianceSource::Pressure,
+                SweepParameter::Pressure,
+                self.pressure,
+            ),
+            (
+                VarianceSource::GrindMicrons,
+                SweepParameter::GrindMicrons,
+                self.grind_microns,
+            ),
+            (VarianceSource::DoseG, SweepParameter::DoseG, self.dose_g),
+        ]
+    }
+}
+
+// Where a shot's deviation from the recipe comes from; ShotVariance is the
+// channeling, drift and retention every shot can suffer
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum VarianceSource {
+    Temperature,
+    Pressure,
+    GrindMicrons,
+    DoseG,
+    ShotVariance,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Contribution {
+    pub source: VarianceSource,
+    // Chance of missing a Perfect Extraction with only this source varying
+    pub failure_probability: f64,
+    // Its part of the failures across all sources
+    pub share: f64,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Robustness {
+    pub runs: usize,
+    // Replays the same analysis
+    pub seed: u64,
+    pub perfect_probability: f64,
+    pub mean_quality_score: f64,
+    pub std_dev_quality_score: f64,
+    pub min_quality_score: u8,
+    pub max_quality_score: u8,
+    // 5th, 25th, 50th, 75th and 95th percentiles
+    pub percentiles: [u8; 5],
+    // Runs per ten points of quality score; the last bucket is 100 alone
+    pub histogram: [usize; 11],
+    // Largest share first
+    pub contributions: Vec<Contribution>,
+}
+
+// Runs the recipe `runs` times with every input drawn from its tolerance,
+// then once more per source with only that source varying, to see which one
+// costs the most Perfect Extractions
+pub fn analyze(
+    recipe: &ShotConfig,
+    tolerances: &Tolerances,
+    runs: usize,
+    seed: u64,
+    shot_variance: bool,
+) -> Robustness {
+    let inputs = tolerances.inputs();
+    // Draws for each run: a deviation per input and a seed for shot variance
+    let draws: Vec<([f64; 4], u64)> = (0..runs as u64)
+        .map(|run| {
+            let mut rng = ShotRng::new(seed.wrapping_add(run));
+            let deviations = inputs.map(|(_, _, std_dev)| rng.normal(0.0, std_dev));
+            (deviations, rng.next_u64())
+        })
+        .collect();
+
+    let shot = |deviations: [f64; 4], varied: &[bool; 4], shot_seed: Option<u64>| {
+        let mut config = ShotConfig {
+            seed: shot_seed,
+            ..recipe.clone()
+        };
+        for (i, (_, parameter, _)) in inputs.iter().enumerate() {
+            if varied[i] {
+                config =
+                    parameter.apply_within_limits(&config, parameter.value(recipe) + deviations[i]);
+            }
+        }
+        simulate(&config)
+    };
+
+    let joint: Vec<ExtractionMetrics> = draws
+        .iter()
+        .map(|(deviations, shot_seed)| {
+            shot(*deviations, &[true; 4], shot_variance.then_some(*shot_seed))
+        })
+        .collect();
+
+    let failure_rate = |shots: &mut dyn Iterator<Item = ExtractionMetrics>| {
+        shots.filter(|m| !m.is_perfect()).count() as f64 / runs as f64
+    };
+    let mut contributions: Vec<Contribution> = inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, _, std_dev))| *std_dev > 0.0)
+        .map(|(i, (source, _, _))| {
+            let mut varied = [false; 4];
+            varied[i] = true;
+            let mut shots = draws
+                .iter()
+                .map(|(deviations, _)| shot(*deviations, &varied, None));
+            Contribution {
+                source: *source,
+                failure_probability: failure_rate(&mut shots),
+                share: 0.0,
+            }
+        })
+        .collect();
+    if shot_variance {
+        let mut shots = draws
+            .iter()
+            .map(|(deviations, shot_seed)| shot(*deviations, &[false; 4], Some(*shot_seed)));
+        contributions.push(Contribution {
+            source: VarianceSource::ShotVariance,
+            failure_probability: failure_rate(&mut shots),
+            share: 0.0,
+        });
+    }
+    let total: f64 = contributions.iter().map(|c| c.failure_probability).sum();
+    for contribution in &mut contributions {
+        if total > 0.0 {
+            contribution.share = contribution.failure_probability / total;
+        }
+    }
+    contributions.sort_by(|a, b| b.share.total_cmp(&a.share));
+
+    let mut scores: Vec<u8> = joint.iter().map(|m| m.quality_score).collect();
+    scores.sort_unstable();
+    let mean = scores.iter().map(|s| *s as f64).sum::<f64>() / runs as f64;
+    let variance = scores
+        .iter()
+        .map(|s| (*s as f64 - mean).powi(2))
+        .sum::<f64>()
+        / runs as f64;
+    let percentile = |p: f64| scores[((p * runs as f64).ceil() as usize).clamp(1, runs) - 1];
+    let mut histogram = [0; 11];
+    for score in &scores {
+        histogram[(*score as usize / 10).min(10)] += 1;
+    }
+
+    Robustness {
+        runs,
+        seed,
+        perfect_probability: joint.iter().filter(|m| m.is_perfect()).count() as f64 / runs as f64,
+        mean_quality_score: mean,
+        std_dev_quality_score: variance.sqrt(),
+        min_quality_score: scores[0],
+        max_quality_score: scores[runs - 1],
+        percentiles: [0.05, 0.25, 0.5, 0.75, 0.95].map(percentile),
+        histogram,
+        contributions,
+    }
+}
@@ -0,0 +1,155 @@
+use crate::simulation::flavor::TasteBalance;
+use crate::simulation::methods::{window_score, BrewMethodKind};
+use crate::simulation::variance::ShotEvent;
+use crate::simulation::ExtractionMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Cupping form attributes run from 6 (fair) to 10 (outstanding)
+const CUPPING_MIN: f64 = 6.0;
+const CUPPING_RANGE: f64 = 4.0;
+
+#[derive(
+    Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default,
+)]
+pub enum ScorerKind {
+    #[default]
+    Classic,
+    ControlChart,
+    Cupping,
+}
+
+impl ScorerKind {
+    pub const ALL: [ScorerKind; 3] = [
+        ScorerKind::Classic,
+        ScorerKind::ControlChart,
+        ScorerKind::Cupping,
+    ];
+
+    pub fn scorer(&self) -> &'static dyn QualityScorer {
+        match self {
+            ScorerKind::Classic => &Classic,
+            ScorerKind::ControlChart => &ControlChart,
+            ScorerKind::Cupping => &Cupping,
+        }
+    }
+}
+
+// Turns a finished brew into a 0-100 quality score
+pub trait QualityScorer: Sync {
+    fn kind(&self) -> ScorerKind;
+
+    fn score(&self, metrics: &ExtractionMetrics) -> u8;
+}
+
+// A stored brew under every scorer, next to the score it was stored with
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct ShotScores {
+    pub timestamp: u64,
+    pub brew_method: BrewMethodKind,
+    pub scorer: ScorerKind,
+    pub quality_score: u8,
+    pub scores: BTreeMap<ScorerKind, u8>,
+}
+
+impl ShotScores {
+    pub fn of(metrics: &ExtractionMetrics) -> Self {
+        Self {
+            timestamp: metrics.timestamp,
+            brew_method: metrics.brew_method,
+            scorer: metrics.scorer,
+            quality_score: metrics.quality_score,
+            scores: ScorerKind::ALL
+                .iter()
+                .map(|kind| kind.scorer())
+                .map(|scorer| (scorer.kind(), scorer.score(metrics)))
+                .collect(),
+        }
+    }
+}
+
+// The simulator's own formula: temperature, pressure and time windows plus
+// pairing bonuses for espresso, and each brew method's own weighting otherwise
+pub struct Classic;
+// SCA brewing control chart: only extraction yield and strength count
+pub struct ControlChart;
+// SCA cupping form, filled in from the predicted flavor
+pub struct Cupping;
+
+impl QualityScorer for Classic {
+    fn kind(&self) -> ScorerKind {
+        ScorerKind::Classic
+    }
+
+    fn score(&self, metrics: &ExtractionMetrics) -> u8 {
+        metrics.brew_method.method().quality_score(metrics)
+    }
+}
+
+impl QualityScorer for ControlChart {
+    fn kind(&self) -> ScorerKind {
+        ScorerKind::ControlChart
+    }
+
+    fn score(&self, metrics: &ExtractionMetrics) -> u8 {
+        let ranges = metrics.ideal_ranges();
+        let score = window_score(
+            metrics.extraction_yield_percent,
+            ranges.extraction_yield_min,
+            ranges.extraction_yield_max,
+            60.0,
+        ) + window_score(metrics.tds_percent, ranges.tds_min, ranges.tds_max, 40.0);
+        to_score(score)
+    }
+}
+
+impl QualityScorer for Cupping {
+    fn kind(&self) -> ScorerKind {
+        ScorerKind::Cupping
+    }
+
+    // Each attribute earns 6 to 10 points; aroma isn't modeled, so it and the
+    // overall impression take the mean of the rest
+    fn score(&self, metrics: &ExtractionMetrics) -> u8 {
+        let flavor = &metrics.flavor;
+        let channeling = metrics
+            .events
+            .iter()
+            .map(|event| match *event {
+                ShotEvent::Channeling { severity, .. } => severity,
+                _ => 0.0,
+            })
+            .fold(0.0, f64::max);
+        // Shares (0-1) of each attribute's top mark
+        let attributes = [
+            // Flavor and sweetness come from the sugars
+            flavor.sweetness / 8.0,
+            flavor.sweetness / 8.0,
+            // A clean finish lacks harsh bitterness and dryness
+            1.0 - (flavor.bitterness + flavor.astringency) / 12.0,
+            // Bright but not sharp, full but not heavy
+            1.0 - (flavor.acidity - 6.0).abs() / 6.0,
+            1.0 - (flavor.body - 6.0).abs() / 6.0,
+            match flavor.balance {
+                TasteBalance::Balanced => 1.0,
+                _ => 0.3,
+            },
+            // Channeling extracts the puck unevenly
+            1.0 - channeling,
+            1.0 - (flavor.astringency - 1.0) / 6.0,
+        ];
+        let mean = attributes
+            .iter()
+            .map(|share| CUPPING_MIN + CUPPING_RANGE * share.clamp(0.0, 1.0))
+            .sum::<f64>()
+            / attributes.len() as f64;
+        to_score(mean * 10.0)
+    }
+}
+
+fn to_score(score: f64) -> u8 {
+    if score.is_nan() {
+        return 0;
+    }
+    score.round().clamp(0.0, 100.0) as u8
+}
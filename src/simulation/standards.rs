@@ -0,0 +1,169 @@
+use crate::simulation::methods::BrewMethodKind;
+use crate::simulation::{CoffeeType, IdealRanges, RoastLevel};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, RwLock};
+
+// The table the server starts with when there is no standards file
+const BUILT_IN: &str = include_str!("../../standards.toml");
+
+static CURRENT: LazyLock<RwLock<Arc<Standards>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(Standards::default())));
+
+// Ideal ranges for a brew method, narrowed to a coffee type and/or roast
+// level when they are given
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct RangeStandard {
+    pub brew_method: BrewMethodKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coffee_type: Option<CoffeeType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roast_level: Option<RoastLevel>,
+    #[serde(flatten)]
+    pub ranges: IdealRanges,
+}
+
+// Coffee and roast that go well together, worth extra points on an espresso
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct Pairing {
+    pub coffee_type: CoffeeType,
+    pub roast_level: RoastLevel,
+    pub bonus: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recommendation: Option<String>,
+}
+
+// The extraction standards table everything is judged against
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct Standards {
+    pub ranges: Vec<RangeStandard>,
+    #[serde(default)]
+    pub pairings: Vec<Pairing>,
+}
+
+impl Default for Standards {
+    fn default() -> Self {
+        toml::from_str(BUILT_IN).expect("built-in standards.toml is valid")
+    }
+}
+
+// Standards in effect for this process
+pub fn current() -> Arc<Standards> {
+    CURRENT.read().expect("standards lock poisoned").clone()
+}
+
+pub fn replace(standards: Standards) {
+    *CURRENT.write().expect("standards lock poisoned") = Arc::new(standards);
+}
+
+impl Standards {
+    // JSON for .json files, TOML for anything else
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let standards: Self = if is_json(path) {
+            serde_json::from_str(&text).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())?
+        };
+        standards.validate()?;
+        Ok(standards)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())?
+        } else {
+            toml::to_string(self).map_err(|e| e.to_string())?
+        };
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for method in BrewMethodKind::ALL {
+            if !self.ranges.iter().any(|r| {
+                r.brew_method == method && r.coffee_type.is_none() && r.roast_level.is_none()
+            }) {
+                return Err(format!(
+                    "Standards need ranges for {:?} without a coffee type or roast level",
+                    method
+                ));
+            }
+        }
+        for (i, standard) in self.ranges.iter().enumerate() {
+            let key = (
+                standard.brew_method,
+                standard.coffee_type,
+                standard.roast_level,
+            );
+            if self.ranges[..i]
+                .iter()
+                .any(|r| (r.brew_method, r.coffee_type, r.roast_level) == key)
+            {
+                return Err(format!("Standards list {:?} more than once", key));
+            }
+            let r = &standard.ranges;
+            let windows = [
+                ("temperature", r.temperature_min, r.temperature_max),
+                ("pressure", r.pressure_min, r.pressure_max),
+                ("time", r.time_min as f64, r.time_max as f64),
+                (
+                    "extraction yield",
+                    r.extraction_yield_min,
+                    r.extraction_yield_max,
+                ),
+                ("TDS", r.tds_min, r.tds_max),
+            ];
+            for (name, min, max) in windows {
+                if !(min.is_finite() && max.is_finite() && min >= 0.0 && min < max) {
+                    return Err(format!(
+                        "{:?} {} range must be a non-negative min below its max, got {} to {}",
+                        key, name, min, max
+                    ));
+                }
+            }
+        }
+        for (i, pairing) in self.pairings.iter().enumerate() {
+            let key = (pairing.coffee_type, pairing.roast_level);
+            if self.pairings[..i]
+                .iter()
+                .any(|p| (p.coffee_type, p.roast_level) == key)
+            {
+                return Err(format!("Pairing {:?} is listed more than once", key));
+            }
+            if pairing.bonus > 20 {
+                return Err(format!("Pairing {:?} bonus must be at most 20", key));
+            }
+        }
+        Ok(())
+    }
+
+    // The most specific row that matches; a coffee type match beats a roast
+    // level match
+    pub fn ranges(
+        &self,
+        brew_method: BrewMethodKind,
+        coffee_type: CoffeeType,
+        roast_level: RoastLevel,
+    ) -> IdealRanges {
+        self.ranges
+            .iter()
+            .filter(|r| r.brew_method == brew_method)
+            .filter(|r| r.coffee_type.is_none_or(|c| c == coffee_type))
+            .filter(|r| r.roast_level.is_none_or(|l| l == roast_level))
+            .max_by_key(|r| 2 * r.coffee_type.is_some() as u8 + r.roast_level.is_some() as u8)
+            .map(|r| r.ranges)
+            .expect("standards cover every brew method")
+    }
+
+    pub fn pairing(&self, coffee_type: CoffeeType, roast_level: RoastLevel) -> Option<&Pairing> {
+        self.pairings
+            .iter()
+            .find(|p| p.coffee_type == coffee_type && p.roast_level == roast_level)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
@@ -0,0 +1,277 @@
+use crate::simulation::{simulate, ExtractionMetrics, ShotConfig};
+use serde::{Deserialize, Serialize};
+
+// Largest grid a single sweep may simulate
+const MAX_CELLS: usize = 400;
+const MAX_STEPS: usize = 50;
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum SweepParameter {
+    Temperature,
+    Pressure,
+    TimeSeconds,
+    GrindMicrons,
+    DoseG,
+    TampKg,
+}
+
+impl SweepParameter {
+    pub const ALL: [SweepParameter; 6] = [
+        SweepParameter::Temperature,
+        SweepParameter::Pressure,
+        SweepParameter::TimeSeconds,
+        SweepParameter::GrindMicrons,
+        SweepParameter::DoseG,
+        SweepParameter::TampKg,
+    ];
+
+    // What a machine and grinder can physically be set to; wider than the
+    // ideal ranges so a sweep can show what happens outside them
+    fn limits(&self) -> (f64, f64) {
+        match self {
+            SweepParameter::Temperature => (70.0, 100.0),
+            SweepParameter::Pressure => (1.0, 15.0),
+            SweepParameter::TimeSeconds => (5.0, 120.0),
+            SweepParameter::GrindMicrons => (150.0, 1200.0),
+            SweepParameter::DoseG => (6.0, 25.0),
+            SweepParameter::TampKg => (5.0, 30.0),
+        }
+    }
+
+    // The change sensitivities are measured over: the nudges a barista
+    // actually makes
+    fn step(&self) -> f64 {
+        match self {
+            SweepParameter::Temperature => 1.0,
+            SweepParameter::Pressure => 0.5,
+            SweepParameter::TimeSeconds => 2.0,
+            SweepParameter::GrindMicrons => 20.0,
+            SweepParameter::DoseG => 0.5,
+            SweepParameter::TampKg => 2.0,
+        }
+    }
+
+    pub(crate) fn value(&self, config: &ShotConfig) -> f64 {
+        match self {
+            SweepParameter::Temperature => config.temperature,
+            SweepParameter::Pressure => config.pressure,
+            SweepParameter::TimeSeconds => config.time_seconds as f64,
+            SweepParameter::GrindMicrons => config
+                .grind_microns
+                .unwrap_or_else(|| config.grind_size.microns()),
+            SweepParameter::DoseG => config.dose_g,
+            SweepParameter::TampKg => config.tamp_kg,
+        }
+    }
+
+    fn apply(&self, config: &ShotConfig, value: f64) -> ShotConfig {
+        let mut config = config.clone();
+        match self {
+            SweepParameter::Temperature => config.temperature = value,
+            SweepParameter::Pressure => {
+                // A profile drives the shot instead of `pressure`, so its
+                // setpoints move by as much
+                if let Some(profile) = &mut config.profile {
+                    profile.shift_pressure(value - config.pressure);
+                }
+                config.pressure = value;
+            }
+            SweepParameter::TimeSeconds => config.time_seconds = value.round() as u64,
+            SweepParameter::GrindMicrons => config.grind_microns = Some(value),
+            SweepParameter::DoseG => config.dose_g = value,
+            SweepParameter::TampKg => config.tamp_kg = value,
+        }
+        config
+    }
+
+    // `apply`, held to what the equipment can be set to
+    pub(crate) fn apply_within_limits(&self, config: &ShotConfig, value: f64) -> ShotConfig {
+        let (min, max) = self.limits();
+        self.apply(config, value.clamp(min, max))
+    }
+}
+
+// Values to try for a parameter: listed one by one, or `steps` evenly
+// spaced values from `min` to `max`
+#[derive(Serialize, Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum AxisValues {
+    List { values: Vec<f64> },
+    Range { min: f64, max: f64, steps: usize },
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Axis {
+    pub parameter: SweepParameter,
+    #[serde(flatten)]
+    pub values: AxisValues,
+}
+
+impl Axis {
+    fn values(&self) -> Result<Vec<f64>, String> {
+        let values = match &self.values {
+            AxisValues::List { values } => values.clone(),
+            AxisValues::Range { min, max, steps } => {
+                if !(2..=MAX_STEPS).contains(steps) || min >= max {
+                    return Err(format!(
+                        "{:?} range needs min below max and 2 to {} steps",
+                        self.parameter, MAX_STEPS
+                    ));
+                }
+                (0..*steps)
+                    .map(|i| min + (max - min) * i as f64 / (*steps - 1) as f64)
+                    .collect()
+            }
+        };
+        let (low, high) = self.parameter.limits();
+        if values.is_empty() || values.len() > MAX_STEPS {
+            return Err(format!(
+                "{:?} needs between 1 and {} values",
+                self.parameter, MAX_STEPS
+            ));
+        }
+        if let Some(value) = values.iter().find(|v| !(low..=high).contains(*v)) {
+            return Err(format!(
+                "{:?} must stay between {} and {}, got {}",
+                self.parameter, low, high, value
+            ));
+        }
+        Ok(values)
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct AxisResult {
+    pub parameter: SweepParameter,
+    pub values: Vec<f64>,
+}
+
+// Results laid out for a heatmap: one row per value of the first axis and
+// one column per value of the second (a single column for a single axis)
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Sweep {
+    pub axes: Vec<AxisResult>,
+    pub quality_score: Vec<Vec<u8>>,
+    pub extraction_yield: Vec<Vec<f64>>,
+    pub result: Vec<Vec<String>>,
+    // How the score and EY respond to each parameter around the base shot,
+    // largest swing first
+    pub sensitivity: Vec<Sensitivity>,
+}
+
+// One bar of a tornado chart: the base shot with a single parameter nudged
+// down and up by its step
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Sensitivity {
+    pub parameter: SweepParameter,
+    pub low: f64,
+    pub high: f64,
+    pub quality_score_low: u8,
+    pub quality_score_high: u8,
+    pub extraction_yield_low: f64,
+    pub extraction_yield_high: f64,
+    // Change per unit of the parameter (partial derivatives)
+    pub quality_score_slope: f64,
+    pub extraction_yield_slope: f64,
+}
+
+impl Sensitivity {
+    pub fn swing(&self) -> f64 {
+        (self.quality_score_high as f64 - self.quality_score_low as f64).abs()
+    }
+}
+
+pub fn sweep(base: &ShotConfig, axes: &[Axis]) -> Result<Sweep, String> {
+    if !(1..=2).contains(&axes.len()) {
+        return Err("A sweep takes one or two axes".to_string());
+    }
+    if axes.len() == 2 && axes[0].parameter == axes[1].parameter {
+        return Err(format!("{:?} is swept twice", axes[0].parameter));
+    }
+    if base.profile.is_some() && axes.iter().any(|a| a.parameter == SweepParameter::Pressure) {
+        return Err("Pressure can only be swept on a flat profile".to_string());
+    }
+    let rows = axes[0].values()?;
+    let columns = match axes.get(1) {
+        Some(axis) => Some(axis.values()?),
+        None => None,
+    };
+    let cells = rows.len() * columns.as_ref().map_or(1, Vec::len);
+    if cells > MAX_CELLS {
+        return Err(format!(
+            "A sweep runs at most {} shots, this one needs {}",
+            MAX_CELLS, cells
+        ));
+    }
+
+    let mut quality_score = Vec::new();
+    let mut extraction_yield = Vec::new();
+    let mut result = Vec::new();
+    for row in &rows {
+        let config = axes[0].parameter.apply(base, *row);
+        let shots: Vec<ExtractionMetrics> = match (&columns, axes.get(1)) {
+            (Some(columns), Some(axis)) => columns
+                .iter()
+                .map(|column| simulate(&axis.parameter.apply(&config, *column)))
+                .collect(),
+            _ => vec![simulate(&config)],
+        };
+        quality_score.push(shots.iter().map(|m| m.quality_score).collect());
+        extraction_yield.push(shots.iter().map(|m| m.extraction_yield_percent).collect());
+        result.push(shots.into_iter().map(|m| m.result).collect());
+    }
+
+    let mut axis_results = vec![AxisResult {
+        parameter: axes[0].parameter,
+        values: rows,
+    }];
+    if let (Some(columns), Some(axis)) = (columns, axes.get(1)) {
+        axis_results.push(AxisResult {
+            parameter: axis.parameter,
+            values: columns,
+        });
+    }
+    Ok(Sweep {
+        axes: axis_results,
+        quality_score,
+        extraction_yield,
+        result,
+        sensitivity: sensitivity(base),
+    })
+}
+
+// Every parameter nudged by its step either side of the base shot, staying
+// within its limits
+pub fn sensitivity(base: &ShotConfig) -> Vec<Sensitivity> {
+    let mut sensitivities: Vec<Sensitivity> = SweepParameter::ALL
+        .iter()
+        // A profile sets its own pressures
+        .filter(|p| base.profile.is_none() || **p != SweepParameter::Pressure)
+        .map(|parameter| {
+            let (min, max) = parameter.limits();
+            let value = parameter.value(base);
+            let (low, high) = (
+                (value - parameter.step()).max(min),
+                (value + parameter.step()).min(max),
+            );
+            let below = simulate(&parameter.apply(base, low));
+            let above = simulate(&parameter.apply(base, high));
+            Sensitivity {
+                parameter: *parameter,
+                low,
+                high,
+                quality_score_low: below.quality_score,
+                quality_score_high: above.quality_score,
+                extraction_yield_low: below.extraction_yield_percent,
+                extraction_yield_high: above.extraction_yield_percent,
+                quality_score_slope: (above.quality_score as f64 - below.quality_score as f64)
+                    / (high - low),
+                extraction_yield_slope: (above.extraction_yield_percent
+                    - below.extraction_yield_percent)
+                    / (high - low),
+            }
+        })
+        .collect();
+    sensitivities.sort_by(|a, b| b.swing().total_cmp(&a.swing()));
+    sensitivities
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+// One tick of a simulated shot, as an espresso machine would log it
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct ShotSample {
+    // Seconds since the pump started
+    pub elapsed: f64,
+    // Bar at the group head
+    pub pressure: f64,
+    // ml/s of water going into the puck
+    pub flow: f64,
+    // °C of the water reaching the puck
+    pub temperature: f64,
+    // Cumulative grams of beverage in the cup
+    pub cup_weight: f64,
+    // Cumulative % of the dose dissolved into the cup
+    #[serde(default)]
+    pub extraction_yield: f64,
+    // Index of the profile stage that was running
+    #[serde(default)]
+    pub stage: usize,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Default)]
+pub struct ShotTrace {
+    pub tick_seconds: f64,
+    pub samples: Vec<ShotSample>,
+}
+
+impl ShotTrace {
+    pub fn new(tick_seconds: f64) -> Self {
+        Self {
+            tick_seconds,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: ShotSample) {
+        self.samples.push(sample);
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.samples.last().map(|s| s.elapsed).unwrap_or(0.0)
+    }
+
+    pub fn peak_pressure(&self) -> f64 {
+        self.samples.iter().map(|s| s.pressure).fold(0.0, f64::max)
+    }
+
+    pub fn mean_temperature(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().map(|s| s.temperature).sum::<f64>() / self.samples.len() as f64
+    }
+
+    pub fn final_cup_weight(&self) -> f64 {
+        self.samples.last().map(|s| s.cup_weight).unwrap_or(0.0)
+    }
+
+    pub fn final_extraction_yield(&self) -> f64 {
+        self.samples
+            .last()
+            .map(|s| s.extraction_yield)
+            .unwrap_or(0.0)
+    }
+
+    // Total ml of water pushed through the group
+    pub fn water_dispensed(&self) -> f64 {
+        self.samples
+            .iter()
+            .map(|s| s.flow * self.tick_seconds)
+            .sum()
+    }
+
+    // Time at which the first drops reached the cup
+    pub fn first_drip(&self) -> Option<f64> {
+        self.samples
+            .iter()
+            .find(|s| s.cup_weight > 0.0)
+            .map(|s| s.elapsed)
+    }
+
+    // Samples taken once liquid is flowing into the cup
+    pub fn dripping(&self) -> impl Iterator<Item = &ShotSample> {
+        self.samples.iter().filter(|s| s.cup_weight > 0.0)
+    }
+}
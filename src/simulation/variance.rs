@@ -0,0 +1,100 @@
+use crate::simulation::degassing::{self, REFERENCE_DAYS_OFF_ROAST};
+use crate::simulation::ShotConfig;
+use serde::{Deserialize, Serialize};
+
+// Chance of a channel opening in a reference puck pulled without pre-infusion
+const BASE_CHANNELING_CHANCE: f64 = 0.2;
+// Temperature drift is fully developed after this many seconds
+pub const DRIFT_SECONDS: f64 = 30.0;
+// Share of its solubles that water running through a channel still picks up
+pub const CHANNEL_EFFICIENCY: f64 = 0.2;
+
+// Something that made this shot differ from an ideal run of the same recipe
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum ShotEvent {
+    // From `at_seconds` on, `severity` (0..1) of the water bypasses the puck
+    Channeling { at_seconds: f64, severity: f64 },
+    // Brew water ends up this many °C off the setpoint
+    TemperatureDrift { degrees: f64 },
+    // Grams the grinder held back (negative) or released from the last dose
+    GrinderRetention { grams: f64 },
+}
+
+// SplitMix64: small, fast and fixed forever, so a stored seed always replays
+// the same shot
+#[derive(Debug, Clone)]
+pub struct ShotRng(u64);
+
+impl ShotRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn uniform(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
+
+    // Box-Muller
+    pub fn normal(&mut self, mean: f64, std_dev: f64) -> f64 {
+        let u1 = self.next_f64().max(f64::MIN_POSITIVE);
+        let u2 = self.next_f64();
+        mean + std_dev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+// Rolls what goes wrong for this shot. Always draws the same number of values
+// so one event never shifts the others for a given seed.
+pub fn roll_events(seed: u64, config: &ShotConfig, pre_infusion: bool) -> Vec<ShotEvent> {
+    let mut rng = ShotRng::new(seed);
+    let mut events = Vec::new();
+
+    let retention = rng.normal(0.0, 0.3).clamp(-1.0, 1.0);
+    if retention.abs() >= 0.05 {
+        events.push(ShotEvent::GrinderRetention { grams: retention });
+    }
+
+    let drift = rng.normal(0.0, 0.8).clamp(-3.0, 3.0);
+    if drift.abs() >= 0.1 {
+        events.push(ShotEvent::TemperatureDrift { degrees: drift });
+    }
+
+    let channel_roll = rng.next_f64();
+    let at_seconds = rng.uniform(5.0, 20.0);
+    let severity = rng.uniform(0.1, 0.4);
+    if channel_roll < channeling_chance(config, pre_infusion) {
+        events.push(ShotEvent::Channeling {
+            at_seconds,
+            severity,
+        });
+    }
+
+    events
+}
+
+// Fine grinds, light tamps and fresh beans channel more; pre-infusion settles
+// the puck first
+fn channeling_chance(config: &ShotConfig, pre_infusion: bool) -> f64 {
+    let microns = config
+        .grind_microns
+        .unwrap_or_else(|| config.grind_size.microns());
+    let grind = (300.0 / microns).powi(2);
+    let tamp = (15.0 / config.tamp_kg).sqrt();
+    let settle = if pre_infusion { 0.5 } else { 1.0 };
+    let co2 =
+        degassing::channeling_factor(config.days_off_roast.unwrap_or(REFERENCE_DAYS_OFF_ROAST));
+
+    (BASE_CHANNELING_CHANCE * grind * tamp * settle * co2).min(0.9)
+}
@@ -0,0 +1,149 @@
+use crate::simulation::{first_error, FieldError};
+use serde::{Deserialize, Serialize};
+
+// Share of the temporary hardness that drops out as scale once heated
+const SCALE_PRECIPITATION: f64 = 0.25;
+
+// Brew water chemistry, with hardness and alkalinity in ppm as CaCO3
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct WaterProfile {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub general_hardness: f64,
+    pub alkalinity: f64,
+    pub tds: f64,
+    pub ph: f64,
+}
+
+// Well-known water recipes
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum WaterRecipe {
+    Sca,
+    ThirdWaveEspresso,
+    Rao,
+    Distilled,
+    SoftTap,
+    HardTap,
+}
+
+// Water passed to the API, either by recipe or spelled out in full
+#[derive(Serialize, Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum WaterSelection {
+    Recipe(WaterRecipe),
+    Custom(WaterProfile),
+}
+
+impl WaterSelection {
+    pub fn resolve(&self) -> Result<WaterProfile, String> {
+        let water = match self {
+            WaterSelection::Recipe(recipe) => recipe.profile(),
+            WaterSelection::Custom(water) => water.clone(),
+        };
+        water.validate()?;
+        Ok(water)
+    }
+
+    // Every problem with a custom water, by field; recipes are always valid
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        match self {
+            WaterSelection::Recipe(_) => Vec::new(),
+            WaterSelection::Custom(water) => water.field_errors(),
+        }
+    }
+}
+
+impl WaterRecipe {
+    pub fn profile(&self) -> WaterProfile {
+        let (name, general_hardness, alkalinity, tds, ph) = match self {
+            WaterRecipe::Sca => ("Sca", 68.0, 40.0, 150.0, 7.0),
+            WaterRecipe::ThirdWaveEspresso => ("ThirdWaveEspresso", 85.0, 40.0, 120.0, 7.0),
+            WaterRecipe::Rao => ("Rao", 80.0, 50.0, 135.0, 7.2),
+            WaterRecipe::Distilled => ("Distilled", 0.0, 0.0, 0.0, 7.0),
+            WaterRecipe::SoftTap => ("SoftTap", 35.0, 30.0, 80.0, 7.5),
+            WaterRecipe::HardTap => ("HardTap", 250.0, 180.0, 400.0, 7.8),
+        };
+        WaterProfile {
+            name: Some(name.to_string()),
+            general_hardness,
+            alkalinity,
+            tds,
+            ph,
+        }
+    }
+}
+
+impl Default for WaterProfile {
+    fn default() -> Self {
+        WaterRecipe::Sca.profile()
+    }
+}
+
+impl WaterProfile {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or("Custom")
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        first_error(self.field_errors())
+    }
+
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        let checks = [
+            (
+                "general_hardness",
+                self.general_hardness,
+                0.0,
+                500.0,
+                "Water general hardness",
+                "ppm",
+            ),
+            (
+                "alkalinity",
+                self.alkalinity,
+                0.0,
+                400.0,
+                "Water alkalinity",
+                "ppm",
+            ),
+            ("tds", self.tds, 0.0, 1000.0, "Water TDS", "ppm"),
+            ("ph", self.ph, 5.0, 10.0, "Water pH", ""),
+        ];
+        checks
+            .into_iter()
+            .filter(|(_, value, min, max, _, _)| !(*min..=*max).contains(value))
+            .map(|(field, _, min, max, name, unit)| {
+                let message = match unit {
+                    "" => format!("{} must be between {:.1} and {:.1}", name, min, max),
+                    unit => format!("{} must be between {} and {} {}", name, min, max, unit),
+                };
+                FieldError::new(field, message)
+            })
+            .collect()
+    }
+
+    // Calcium and magnesium pull flavour out of the grounds, while water
+    // already loaded with solids has less room left to dissolve coffee
+    pub fn extraction_factor(&self) -> f64 {
+        let minerals = 0.10 * ((self.general_hardness - 68.0) / 60.0).tanh();
+        let saturation = 0.0004 * (self.tds - 150.0).max(0.0);
+        (1.0 + minerals - saturation).max(0.5)
+    }
+
+    // Points of acidity taken away by bicarbonate buffering, negative when
+    // water with no buffer leaves the cup sharp
+    pub fn acidity_buffering(&self) -> f64 {
+        (self.alkalinity - 40.0) / 25.0
+    }
+
+    // Points of body added by hardness
+    pub fn body_boost(&self) -> f64 {
+        (self.general_hardness - 68.0) / 100.0
+    }
+
+    // mg of limescale left behind in the machine by this much water
+    pub fn scale_mg(&self, water_ml: f64) -> f64 {
+        let temporary_hardness = self.general_hardness.min(self.alkalinity);
+        water_ml / 1000.0 * temporary_hardness * SCALE_PRECIPITATION
+    }
+}
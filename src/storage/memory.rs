@@ -0,0 +1,113 @@
+use super::{Batch, Entries, Storage, Table};
+use crate::analytics::errors::RepositoryError;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// Keeps every record in memory, so nothing outlives the process. Tests use
+// it to stay out of the working directory
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<HashMap<String, Arc<MemoryTable>>>,
+    ids: AtomicU64,
+}
+
+#[derive(Default)]
+struct MemoryTable {
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryTable {
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.entries.read().expect("memory table lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.entries.write().expect("memory table lock poisoned")
+    }
+}
+
+impl Table for MemoryTable {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, RepositoryError> {
+        Ok(self.read().get(key).cloned())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), RepositoryError> {
+        self.write().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool, RepositoryError> {
+        Ok(self.write().remove(key).is_some())
+    }
+
+    fn clear(&self) -> Result<(), RepositoryError> {
+        self.write().clear();
+        Ok(())
+    }
+
+    // Copies the range out, so the lock is not held while the caller walks it
+    fn range(&self, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>, reverse: bool) -> Entries<'_> {
+        if super::is_empty_range(&lower, &upper) {
+            return Box::new(std::iter::empty());
+        }
+        let entries = self.read();
+        let mut range: Vec<_> = entries
+            .range::<Vec<u8>, _>((lower, upper))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if reverse {
+            range.reverse();
+        }
+        Box::new(range.into_iter().map(Ok))
+    }
+}
+
+impl MemoryStorage {
+    fn memory_table(&self, name: &str) -> Arc<MemoryTable> {
+        let mut tables = self.tables.lock().expect("memory storage lock poisoned");
+        tables.entry(name.to_string()).or_default().clone()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn table(&self, name: &str) -> Result<Arc<dyn Table>, RepositoryError> {
+        Ok(self.memory_table(name))
+    }
+
+    fn generate_id(&self) -> Result<u64, RepositoryError> {
+        Ok(self.ids.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), RepositoryError> {
+        Ok(())
+    }
+
+    // Holds every table the batch touches for the whole batch. Other writes
+    // lock one table at a time, so taking these in name order cannot deadlock
+    fn apply(&self, batch: &Batch) -> Result<bool, RepositoryError> {
+        let tables: Vec<_> = batch
+            .tables()
+            .into_iter()
+            .map(|name| (name, self.memory_table(name)))
+            .collect();
+        let mut guards: HashMap<&str, _> = tables
+            .iter()
+            .map(|(name, table)| (*name, table.write()))
+            .collect();
+        for (table, key, value) in &batch.checks {
+            if guards[table.as_str()].get(key) != value.as_ref() {
+                return Ok(false);
+            }
+        }
+        for (table, key, value) in &batch.writes {
+            let entries = guards.get_mut(table.as_str()).expect("table locked above");
+            match value {
+                Some(value) => entries.insert(key.clone(), value.clone()),
+                None => entries.remove(key),
+            };
+        }
+        Ok(true)
+    }
+}
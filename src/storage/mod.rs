@@ -0,0 +1,376 @@
+use crate::analytics::errors::RepositoryError;
+use chrono::Utc;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+mod memory;
+mod sled_store;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use memory::MemoryStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+// Every record kind lives in its own table, so a scan only ever meets
+// records of the kind it expects. Beans, calibrations and recipes open
+// theirs in their repositories
+pub const SHOTS: &str = "shots";
+pub const ALERTS: &str = "alerts";
+// Trends are worked out from the shots when asked for; this only holds
+// the ones older versions stored, moved here by the sled migration
+pub const TRENDS: &str = "trends";
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+pub type Entries<'a> = Box<dyn Iterator<Item = Result<Entry, RepositoryError>> + 'a>;
+
+// An ordered map of byte keys to byte values. Keys compare byte by byte,
+// which the time and score keys rely on
+pub trait Table: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, RepositoryError>;
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), RepositoryError>;
+
+    // Whether there was a value to remove
+    fn remove(&self, key: &[u8]) -> Result<bool, RepositoryError>;
+
+    fn clear(&self) -> Result<(), RepositoryError>;
+
+    // The entries between the bounds, in key order or in reverse. Walking
+    // them never blocks writes, so a caller may change the table as it goes
+    fn range(&self, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>, reverse: bool) -> Entries<'_>;
+
+    fn iter(&self) -> Entries<'_> {
+        self.range(Bound::Unbounded, Bound::Unbounded, false)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Entries<'_> {
+        self.range(Bound::Included(prefix.to_vec()), prefix_end(prefix), false)
+    }
+
+    // The entry with the greatest key starting with `prefix`
+    fn last_with_prefix(&self, prefix: &[u8]) -> Result<Option<Entry>, RepositoryError> {
+        self.range(Bound::Included(prefix.to_vec()), prefix_end(prefix), true)
+            .next()
+            .transpose()
+    }
+}
+
+// Where records are kept: named tables and a sequence of ids
+pub trait Storage: Send + Sync {
+    // Opens the table, creating it when it does not exist yet
+    fn table(&self, name: &str) -> Result<Arc<dyn Table>, RepositoryError>;
+
+    // Ids from one sequence, increasing over the life of the storage
+    fn generate_id(&self) -> Result<u64, RepositoryError>;
+
+    // Makes every write so far durable
+    fn flush(&self) -> Result<(), RepositoryError>;
+
+    // Applies every write in the batch at once, or none of them when a key
+    // it checks no longer holds what the batch expects
+    fn apply(&self, batch: &Batch) -> Result<bool, RepositoryError>;
+}
+
+// A key, in a named table, and a value or None for no value
+type Slot = (String, Vec<u8>, Option<Vec<u8>>);
+
+// Writes to several tables that land together. Checks guard a
+// read-modify-write: if another writer got there first, the batch is
+// dropped and the caller reads again
+#[derive(Debug, Default)]
+pub struct Batch {
+    checks: Vec<Slot>,
+    writes: Vec<Slot>,
+}
+
+impl Batch {
+    // Requires `key` to still hold `value`, or to be missing for None
+    pub fn check(&mut self, table: &str, key: &[u8], value: Option<&[u8]>) {
+        self.checks
+            .push((table.to_string(), key.to_vec(), value.map(<[u8]>::to_vec)));
+    }
+
+    pub fn insert(&mut self, table: &str, key: &[u8], value: &[u8]) {
+        self.writes
+            .push((table.to_string(), key.to_vec(), Some(value.to_vec())));
+    }
+
+    pub fn remove(&mut self, table: &str, key: &[u8]) {
+        self.writes.push((table.to_string(), key.to_vec(), None));
+    }
+
+    // Every table the batch reads or writes, in name order, which is the
+    // order backends lock them in
+    fn tables(&self) -> Vec<&str> {
+        let names: BTreeSet<&str> = self
+            .checks
+            .iter()
+            .chain(&self.writes)
+            .map(|(table, _, _)| table.as_str())
+            .collect();
+        names.into_iter().collect()
+    }
+}
+
+// The first key after every key starting with `prefix`
+pub fn prefix_end(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Bound::Excluded(end);
+        }
+    }
+    Bound::Unbounded
+}
+
+// Whether no key can lie between the bounds. Backends check this first,
+// since a cursor from a client may point outside the range it is used with
+fn is_empty_range(lower: &Bound<Vec<u8>>, upper: &Bound<Vec<u8>>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (Bound::Included(lower), Bound::Excluded(upper))
+        | (Bound::Excluded(lower), Bound::Included(upper))
+        | (Bound::Excluded(lower), Bound::Excluded(upper)) => lower >= upper,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    Sled,
+    Memory,
+    Sqlite,
+}
+
+// Which backend to store records in, and where. Read from
+// `ESPRESSIA_STORAGE` (sled, memory or sqlite; default: sled) and
+// `ESPRESSIA_DB`, the path of the database
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub backend: Backend,
+    pub path: PathBuf,
+}
+
+impl StorageConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let backend = match std::env::var("ESPRESSIA_STORAGE").as_deref() {
+            Err(_) | Ok("sled") => Backend::Sled,
+            Ok("memory") => Backend::Memory,
+            Ok("sqlite") => Backend::Sqlite,
+            Ok(other) => {
+                return Err(format!(
+                    "ESPRESSIA_STORAGE must be sled, memory or sqlite, got {}",
+                    other
+                ))
+            }
+        };
+        let path = std::env::var("ESPRESSIA_DB").unwrap_or_else(|_| {
+            match backend {
+                Backend::Sqlite => "espressia.sqlite3",
+                _ => "espressia_metrics_db",
+            }
+            .to_string()
+        });
+        Ok(Self {
+            backend,
+            path: PathBuf::from(path),
+        })
+    }
+
+    pub fn open(&self) -> Result<Arc<dyn Storage>, RepositoryError> {
+        match self.backend {
+            Backend::Sled => Ok(Arc::new(sled_store::open(&self.path)?)),
+            Backend::Memory => Ok(Arc::new(MemoryStorage::default())),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => Ok(Arc::new(SqliteStorage::open(&self.path)?)),
+            #[cfg(not(feature = "sqlite"))]
+            Backend::Sqlite => Err(RepositoryError::BackendError(
+                "This build has no SQLite support; rebuild with --features sqlite".to_string(),
+            )),
+        }
+    }
+
+    // Where the records are, for the logs
+    pub fn describe(&self) -> String {
+        match self.backend {
+            Backend::Sled => format!("sled database at {}", self.path.display()),
+            Backend::Memory => "memory, until the server stops".to_string(),
+            Backend::Sqlite => format!("SQLite database at {}", self.path.display()),
+        }
+    }
+}
+
+// Key of a record kept in time order: the big-endian millisecond it was
+// stored at, then an id from the storage's sequence, so keys sort by time and
+// records stored in the same millisecond stay apart and in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeKey {
+    pub millis: u64,
+    pub id: u64,
+}
+
+impl TimeKey {
+    pub const LEN: usize = 16;
+
+    // A key for a record stored now
+    pub fn now(db: &dyn Storage) -> Result<Self, RepositoryError> {
+        Ok(Self {
+            millis: Utc::now().timestamp_millis().max(0) as u64,
+            id: db.generate_id()?,
+        })
+    }
+
+    // The first key at or after `millis`
+    pub fn at(millis: u64) -> Self {
+        Self { millis, id: 0 }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..8].copy_from_slice(&self.millis.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.id.to_be_bytes());
+        bytes
+    }
+}
+
+// Keys handed to clients, such as page cursors, travel as hex
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_time_keys_sort_by_time() {
+        let db = MemoryStorage::default();
+        let first = TimeKey::now(&db).unwrap();
+        let second = TimeKey::now(&db).unwrap();
+        assert!(first.to_bytes() < second.to_bytes());
+        // Byte order follows time order across digit counts too
+        assert!(TimeKey::at(999).to_bytes() < TimeKey::at(1_000).to_bytes());
+        assert_eq!(
+            from_hex(&to_hex(&first.to_bytes())).unwrap(),
+            first.to_bytes()
+        );
+        assert!(from_hex("abc").is_none() && from_hex("zz").is_none());
+    }
+
+    fn keys(entries: Entries<'_>) -> Vec<Vec<u8>> {
+        entries.map(|entry| entry.unwrap().0).collect()
+    }
+
+    // What the repositories rely on, which every backend must do alike
+    fn check_backend(db: &dyn Storage) {
+        let table = db.table("test").unwrap();
+        for key in [&[2][..], &[1, 0xff], &[1], &[0xff, 0xff], &[1, 2]] {
+            table.insert(key, key).unwrap();
+        }
+        table.insert(&[2], b"two").unwrap();
+        // Tables with other names hold other records
+        db.table("other").unwrap().insert(&[1], &[]).unwrap();
+
+        assert_eq!(table.get(&[2]).unwrap().unwrap(), b"two");
+        assert_eq!(table.get(&[3]).unwrap(), None);
+        assert_eq!(
+            keys(table.iter()),
+            [&[1][..], &[1, 2], &[1, 0xff], &[2], &[0xff, 0xff]]
+        );
+        assert_eq!(
+            keys(table.range(Bound::Excluded(vec![1]), Bound::Included(vec![2]), true)),
+            [&[2][..], &[1, 0xff], &[1, 2]]
+        );
+        assert_eq!(
+            keys(table.scan_prefix(&[1])),
+            [&[1][..], &[1, 2], &[1, 0xff]]
+        );
+        assert_eq!(keys(table.scan_prefix(&[0xff])), [&[0xff, 0xff]]);
+        assert_eq!(table.last_with_prefix(&[1]).unwrap().unwrap().0, [1, 0xff]);
+        assert!(table.last_with_prefix(&[3]).unwrap().is_none());
+        // Bounds that cross give nothing rather than failing
+        assert!(
+            keys(table.range(Bound::Included(vec![2]), Bound::Excluded(vec![1]), false)).is_empty()
+        );
+
+        // Writes while walking a range
+        let walked = table.iter().try_for_each(|entry| {
+            let (key, _) = entry.unwrap();
+            table.remove(&key).unwrap();
+            table.insert(&[&[0x80][..], &key].concat(), &[]).unwrap();
+            if key == [2] {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(walked.is_break());
+        assert!(table.remove(&[0xff, 0xff]).unwrap());
+        assert!(!table.remove(&[0xff, 0xff]).unwrap());
+        assert_eq!(keys(table.iter()).len(), 4);
+
+        table.clear().unwrap();
+        assert!(keys(table.iter()).is_empty());
+        assert_eq!(keys(db.table("other").unwrap().iter()), [&[1]]);
+        // Long ranges, which some backends read a batch at a time
+        let many = db.table("many").unwrap();
+        for i in 0..1_000u16 {
+            many.insert(&i.to_be_bytes(), &[]).unwrap();
+        }
+        assert_eq!(many.iter().count(), 1_000);
+        let last = many.range(Bound::Unbounded, Bound::Unbounded, true).last();
+        assert_eq!(last.unwrap().unwrap().0, [0, 0]);
+
+        // A batch lands whole across tables, or not at all once a key it
+        // checks has moved on
+        let mut batch = Batch::default();
+        batch.check("test", &[7], None);
+        batch.insert("test", &[7], b"seven");
+        batch.remove("other", &[1]);
+        assert!(db.apply(&batch).unwrap());
+        assert_eq!(table.get(&[7]).unwrap().unwrap(), b"seven");
+        assert!(db.table("other").unwrap().get(&[1]).unwrap().is_none());
+        let mut stale = Batch::default();
+        stale.check("test", &[7], Some(b"six"));
+        stale.insert("test", &[7], b"eight");
+        stale.insert("other", &[2], &[]);
+        assert!(!db.apply(&stale).unwrap());
+        assert_eq!(table.get(&[7]).unwrap().unwrap(), b"seven");
+        assert!(db.table("other").unwrap().get(&[2]).unwrap().is_none());
+
+        let id = db.generate_id().unwrap();
+        assert!(db.generate_id().unwrap() > id);
+        db.flush().unwrap();
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        check_backend(&MemoryStorage::default());
+    }
+
+    #[test]
+    fn test_sled_backend() {
+        check_backend(&sled::Config::new().temporary(true).open().unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_backend() {
+        check_backend(&SqliteStorage::open(std::path::Path::new(":memory:")).unwrap());
+    }
+}
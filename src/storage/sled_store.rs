@@ -0,0 +1,242 @@
+use super::{Batch, Entries, Storage, Table, TimeKey, ALERTS, SHOTS, TRENDS};
+use crate::analytics::alerts::Alert;
+use crate::analytics::errors::RepositoryError;
+use crate::analytics::trends::ExtractionTrends;
+use sled::transaction::{abort, TransactionError};
+use sled::{Db, Transactional, Tree};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+// Sled keeps every table as a tree of its own
+impl Table for Tree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, RepositoryError> {
+        Ok(Tree::get(self, key)?.map(|value| value.to_vec()))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), RepositoryError> {
+        Tree::insert(self, key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool, RepositoryError> {
+        Ok(Tree::remove(self, key)?.is_some())
+    }
+
+    fn clear(&self) -> Result<(), RepositoryError> {
+        Ok(Tree::clear(self)?)
+    }
+
+    fn range(&self, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>, reverse: bool) -> Entries<'_> {
+        if super::is_empty_range(&lower, &upper) {
+            return Box::new(std::iter::empty());
+        }
+        let entries = Tree::range::<Vec<u8>, _>(self, (lower, upper)).map(|entry| {
+            let (key, value) = entry?;
+            Ok((key.to_vec(), value.to_vec()))
+        });
+        if reverse {
+            Box::new(entries.rev())
+        } else {
+            Box::new(entries)
+        }
+    }
+}
+
+impl Storage for Db {
+    fn table(&self, name: &str) -> Result<Arc<dyn Table>, RepositoryError> {
+        Ok(Arc::new(self.open_tree(name)?))
+    }
+
+    fn generate_id(&self) -> Result<u64, RepositoryError> {
+        Ok(Db::generate_id(self)?)
+    }
+
+    fn flush(&self) -> Result<(), RepositoryError> {
+        Tree::flush(self)?;
+        Ok(())
+    }
+
+    fn apply(&self, batch: &Batch) -> Result<bool, RepositoryError> {
+        let names = batch.tables();
+        let trees = names
+            .iter()
+            .map(|name| self.open_tree(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let applied = trees.as_slice().transaction(|trees| {
+            let tree = |name: &String| &trees[names.binary_search(&name.as_str()).unwrap()];
+            for (table, key, value) in &batch.checks {
+                if tree(table).get(key)?.as_deref() != value.as_deref() {
+                    return abort(());
+                }
+            }
+            for (table, key, value) in &batch.writes {
+                match value {
+                    Some(value) => tree(table).insert(key.as_slice(), value.as_slice())?,
+                    None => tree(table).remove(key.as_slice())?,
+                };
+            }
+            Ok(())
+        });
+        match applied {
+            Ok(()) => Ok(true),
+            Err(TransactionError::Abort(())) => Ok(false),
+            Err(TransactionError::Storage(err)) => Err(err.into()),
+        }
+    }
+}
+
+// Opens the database at `path`, moving records older versions kept in the
+// default tree into their own trees
+pub fn open(path: &Path) -> Result<Db, RepositoryError> {
+    let db = sled::Config::new()
+        .path(path)
+        .use_compression(true)
+        .mode(sled::Mode::HighThroughput)
+        .open()?;
+    let moved = migrate(&db)?;
+    if moved > 0 {
+        info!("Moved {} stored records to their own trees", moved);
+    }
+    Ok(db)
+}
+
+// Where a `metric_{millis}[_{suffix}]` key from older versions goes. A
+// numeric suffix was a sequence id and is kept, so running the move twice
+// rewrites the same keys
+fn legacy_shot_key(db: &Db, key: &[u8]) -> Result<Option<TimeKey>, RepositoryError> {
+    let key = String::from_utf8_lossy(key);
+    let Some(rest) = key.strip_prefix("metric_") else {
+        return Ok(None);
+    };
+    let (millis, suffix) = rest.split_once('_').unwrap_or((rest, ""));
+    let Ok(millis) = millis.parse() else {
+        return Ok(None);
+    };
+    let id = match suffix {
+        "" => 0,
+        suffix => match suffix.parse() {
+            Ok(id) => id,
+            Err(_) => db.generate_id()?,
+        },
+    };
+    Ok(Some(TimeKey { millis, id }))
+}
+
+// Moves records older versions kept in the default tree into their own
+// trees; returns how many were moved
+fn migrate(db: &Db) -> Result<usize, RepositoryError> {
+    let shots = db.open_tree(SHOTS)?;
+    let alerts = db.open_tree(ALERTS)?;
+    let trends = db.open_tree(TRENDS)?;
+    let mut moved = 0;
+    for entry in db.iter() {
+        let (key, value) = entry?;
+        if key.starts_with(b"metric_") {
+            let Some(time_key) = legacy_shot_key(db, &key)? else {
+                warn!("Left unreadable shot key {:?} in place", key);
+                continue;
+            };
+            shots.insert(time_key.to_bytes(), value)?;
+        } else if key.starts_with(b"alert_") {
+            // Alerts and trends were both stored as `alert_{millis}`, so the
+            // value tells which one it is
+            let Ok(millis) = String::from_utf8_lossy(&key["alert_".len()..]).parse() else {
+                warn!("Left unreadable alert key {:?} in place", key);
+                continue;
+            };
+            let time_key = TimeKey {
+                millis,
+                id: db.generate_id()?,
+            }
+            .to_bytes();
+            if serde_json::from_slice::<Alert>(&value).is_ok() {
+                alerts.insert(time_key, value)?;
+            } else if serde_json::from_slice::<ExtractionTrends>(&value).is_ok() {
+                trends.insert(time_key, value)?;
+            } else {
+                warn!("Left unreadable record {:?} in place", key);
+                continue;
+            }
+        } else {
+            continue;
+        }
+        db.remove(&key)?;
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::alerts::AlertGenerator;
+    use crate::analytics::repository::AnalyticsRepository;
+    use crate::analytics::trends::TrendPeriod;
+    use crate::simulation::{simulate, ExtractionMetrics, ShotConfig};
+    use chrono::Utc;
+
+    #[test]
+    fn test_migrate_moves_each_kind_to_its_tree() {
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let shot = ExtractionMetrics {
+            perfect_extraction_rate: 0.1,
+            ..simulate(&ShotConfig::default())
+        };
+        let alerts = AlertGenerator::new().generate_alerts(&shot);
+        assert!(!alerts.is_empty());
+        // Older versions kept everything in the default tree, with alerts
+        // and trends under the same prefix
+        let millis = Utc::now().timestamp_millis();
+        db.insert(
+            format!("metric_{}", millis),
+            serde_json::to_vec(&shot).unwrap(),
+        )
+        .unwrap();
+        db.insert(
+            format!("alert_{}", millis),
+            serde_json::to_vec(&alerts[0]).unwrap(),
+        )
+        .unwrap();
+        let trends = ExtractionTrends::calculate(&[shot], TrendPeriod::Daily);
+        db.insert(
+            format!("alert_{}", millis + 1),
+            serde_json::to_vec(&trends).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&db).unwrap(), 3);
+        assert_eq!(db.len(), 0);
+        assert_eq!(migrate(&db).unwrap(), 0);
+
+        // Both kinds read back now that neither meets the other
+        let repository = AnalyticsRepository::new(db.clone()).unwrap();
+        assert_eq!(repository.get_alerts().unwrap().len(), 1);
+        assert_eq!(db.open_tree(SHOTS).unwrap().len(), 1);
+        assert_eq!(db.open_tree(TRENDS).unwrap().len(), 1);
+        repository.store_alerts(&alerts).unwrap();
+        assert_eq!(repository.get_alerts().unwrap().len(), 1 + alerts.len());
+    }
+
+    #[test]
+    fn test_migrate_keeps_every_alert() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let shot = ExtractionMetrics {
+            perfect_extraction_rate: 0.1,
+            ..simulate(&ShotConfig::default())
+        };
+        let alert = serde_json::to_vec(&AlertGenerator::new().generate_alerts(&shot)[0]).unwrap();
+        // Two keys for the same millisecond each keep their alert, and keys
+        // without one stay where they are
+        for key in ["alert_42", "alert_0042", "alert_soon", "alert_"] {
+            db.insert(key, alert.clone()).unwrap();
+        }
+
+        assert_eq!(migrate(&db).unwrap(), 2);
+        assert_eq!(db.open_tree(ALERTS).unwrap().len(), 2);
+        assert!(db.contains_key("alert_soon").unwrap());
+        assert!(db.contains_key("alert_").unwrap());
+        assert_eq!(migrate(&db).unwrap(), 0);
+    }
+}
@@ -0,0 +1,225 @@
+use super::{Batch, Entries, Entry, Storage, Table};
+use crate::analytics::errors::RepositoryError;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::VecDeque;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// Rows a range reads at a time; the connection is free between batches
+const BATCH: usize = 256;
+const GET: &str = "SELECT value FROM records WHERE tree = ?1 AND key = ?2";
+const INSERT: &str = "INSERT INTO records (tree, key, value) VALUES (?1, ?2, ?3)
+     ON CONFLICT (tree, key) DO UPDATE SET value = excluded.value";
+const REMOVE: &str = "DELETE FROM records WHERE tree = ?1 AND key = ?2";
+
+// Keeps every table in one SQLite file. SQLite compares blobs byte by byte,
+// so keys sort the same way they do in sled
+pub struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self, RepositoryError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS records (
+                 tree TEXT NOT NULL,
+                 key BLOB NOT NULL,
+                 value BLOB NOT NULL,
+                 PRIMARY KEY (tree, key)
+             ) WITHOUT ROWID;
+             CREATE TABLE IF NOT EXISTS counters (
+                 name TEXT PRIMARY KEY,
+                 value INTEGER NOT NULL
+             );",
+        )?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+}
+
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    connection.lock().expect("SQLite connection lock poisoned")
+}
+
+struct SqliteTable {
+    connection: Arc<Mutex<Connection>>,
+    name: String,
+}
+
+impl SqliteTable {
+    // Up to BATCH entries between the bounds, nearest to where the walk
+    // starts first
+    fn batch(
+        &self,
+        lower: &Bound<Vec<u8>>,
+        upper: &Bound<Vec<u8>>,
+        reverse: bool,
+    ) -> Result<Vec<Entry>, RepositoryError> {
+        let mut sql = "SELECT key, value FROM records WHERE tree = ?1".to_string();
+        let mut keys = Vec::new();
+        for (bound, included, excluded) in [(lower, ">=", ">"), (upper, "<=", "<")] {
+            let (operator, key) = match bound {
+                Bound::Included(key) => (included, key),
+                Bound::Excluded(key) => (excluded, key),
+                Bound::Unbounded => continue,
+            };
+            keys.push(key);
+            sql.push_str(&format!(" AND key {} ?{}", operator, keys.len() + 1));
+        }
+        sql.push_str(if reverse {
+            " ORDER BY key DESC"
+        } else {
+            " ORDER BY key ASC"
+        });
+        sql.push_str(&format!(" LIMIT {}", BATCH));
+
+        let connection = lock(&self.connection);
+        let mut statement = connection.prepare_cached(&sql)?;
+        let mut values: Vec<&dyn rusqlite::ToSql> = vec![&self.name];
+        values.extend(keys.iter().map(|key| *key as &dyn rusqlite::ToSql));
+        let rows = statement.query_map(values.as_slice(), |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+// Walks a range a batch at a time, each batch starting past the last key
+// of the one before
+struct Batches<'a> {
+    table: &'a SqliteTable,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+    reverse: bool,
+    buffer: VecDeque<Entry>,
+    done: bool,
+}
+
+impl Iterator for Batches<'_> {
+    type Item = Result<Entry, RepositoryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.is_empty() && !self.done {
+            match self.table.batch(&self.lower, &self.upper, self.reverse) {
+                Ok(batch) => {
+                    self.done = batch.len() < BATCH;
+                    if let Some((key, _)) = batch.last() {
+                        let after = Bound::Excluded(key.clone());
+                        if self.reverse {
+                            self.upper = after;
+                        } else {
+                            self.lower = after;
+                        }
+                    }
+                    self.buffer.extend(batch);
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+impl Table for SqliteTable {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, RepositoryError> {
+        let connection = lock(&self.connection);
+        let value = connection
+            .prepare_cached(GET)?
+            .query_row(params![self.name, key], |row| row.get(0))
+            .optional()?;
+        Ok(value)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), RepositoryError> {
+        let connection = lock(&self.connection);
+        connection
+            .prepare_cached(INSERT)?
+            .execute(params![self.name, key, value])?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool, RepositoryError> {
+        let connection = lock(&self.connection);
+        let removed = connection
+            .prepare_cached(REMOVE)?
+            .execute(params![self.name, key])?;
+        Ok(removed > 0)
+    }
+
+    fn clear(&self) -> Result<(), RepositoryError> {
+        let connection = lock(&self.connection);
+        connection.execute("DELETE FROM records WHERE tree = ?1", params![self.name])?;
+        Ok(())
+    }
+
+    fn range(&self, lower: Bound<Vec<u8>>, upper: Bound<Vec<u8>>, reverse: bool) -> Entries<'_> {
+        let done = super::is_empty_range(&lower, &upper);
+        Box::new(Batches {
+            table: self,
+            lower,
+            upper,
+            reverse,
+            buffer: VecDeque::new(),
+            done,
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn table(&self, name: &str) -> Result<Arc<dyn Table>, RepositoryError> {
+        Ok(Arc::new(SqliteTable {
+            connection: self.connection.clone(),
+            name: name.to_string(),
+        }))
+    }
+
+    fn generate_id(&self) -> Result<u64, RepositoryError> {
+        let connection = lock(&self.connection);
+        let id: i64 = connection
+            .prepare_cached(
+                "INSERT INTO counters (name, value) VALUES ('ids', 0)
+                 ON CONFLICT (name) DO UPDATE SET value = value + 1
+                 RETURNING value",
+            )?
+            .query_row([], |row| row.get(0))?;
+        Ok(id as u64)
+    }
+
+    // Every statement commits as it runs
+    fn flush(&self) -> Result<(), RepositoryError> {
+        Ok(())
+    }
+
+    // The connection is held throughout, so nothing else runs in between;
+    // the transaction only makes the writes land together
+    fn apply(&self, batch: &Batch) -> Result<bool, RepositoryError> {
+        let mut connection = lock(&self.connection);
+        let transaction = connection.transaction()?;
+        for (table, key, value) in &batch.checks {
+            let current: Option<Vec<u8>> = transaction
+                .prepare_cached(GET)?
+                .query_row(params![table, key], |row| row.get(0))
+                .optional()?;
+            if current != *value {
+                return Ok(false);
+            }
+        }
+        for (table, key, value) in &batch.writes {
+            match value {
+                Some(value) => transaction
+                    .prepare_cached(INSERT)?
+                    .execute(params![table, key, value])?,
+                None => transaction
+                    .prepare_cached(REMOVE)?
+                    .execute(params![table, key])?,
+            };
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+}